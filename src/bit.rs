@@ -0,0 +1,39 @@
+use std::ops::{BitAnd, BitOr, BitXor, Not};
+
+use super::boolean::FheBool;
+
+// 1 ビットの値. 回路をこの trait の上の普通の関数として書いておけば、
+// bool で平文のまま、FheBool で暗号文のまま、Traced で回路として記録、と同じコードを使い回せる
+pub trait Bit:
+    Clone + BitAnd<Output = Self> + BitOr<Output = Self> + BitXor<Output = Self> + Not<Output = Self>
+{
+    // self と同じ文脈 (鍵や記録中の回路) の定数
+    fn constant(&self, b: bool) -> Self;
+
+    // self ? x : y
+    fn mux(&self, x: &Self, y: &Self) -> Self;
+}
+
+impl Bit for bool {
+    fn constant(&self, b: bool) -> bool {
+        b
+    }
+
+    fn mux(&self, x: &bool, y: &bool) -> bool {
+        if *self {
+            *x
+        } else {
+            *y
+        }
+    }
+}
+
+impl Bit for FheBool {
+    fn constant(&self, b: bool) -> FheBool {
+        FheBool::trivial(b, self.key().clone())
+    }
+
+    fn mux(&self, x: &FheBool, y: &FheBool) -> FheBool {
+        FheBool::mux(self, x, y)
+    }
+}
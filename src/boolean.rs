@@ -0,0 +1,155 @@
+use std::ops::{BitAnd, BitOr, BitXor, Not};
+use std::sync::Arc;
+
+use super::gates::Gates;
+use super::key::CloudKey;
+use super::tlwe::{CipherTLWELv0, TLWE};
+
+// 暗号化された真偽値. 演算子でゲートを書けるように鍵を一緒に持っておく
+#[derive(Clone, Debug)]
+pub struct FheBool {
+    c: CipherTLWELv0,
+    key: Arc<CloudKey>,
+}
+
+impl FheBool {
+    pub fn new(c: CipherTLWELv0, key: Arc<CloudKey>) -> Self {
+        Self { c, key }
+    }
+
+    pub fn encrypt(b: bool, tlwe: &TLWE, key: Arc<CloudKey>) -> Self {
+        Self::new(tlwe.encrypt(b), key)
+    }
+
+    // 自明な暗号文. 秘密鍵なしで作れる
+    pub fn trivial(b: bool, key: Arc<CloudKey>) -> Self {
+        Self::new(key.constant(b), key)
+    }
+
+    pub fn decrypt(&self, tlwe: &TLWE) -> bool {
+        tlwe.decrypt(self.c)
+    }
+
+    // 別の cloud key で作った値とは演算できない
+    fn check_key(&self, key: &Arc<CloudKey>) {
+        assert!(
+            Arc::ptr_eq(&self.key, key),
+            "operands were created with different cloud keys"
+        );
+    }
+
+    // self ? x : y
+    pub fn mux(&self, x: &FheBool, y: &FheBool) -> FheBool {
+        self.check_key(&x.key);
+        self.check_key(&y.key);
+        FheBool::new(self.key.mux(&self.c, &x.c, &y.c), self.key.clone())
+    }
+
+    pub fn ciphertext(&self) -> CipherTLWELv0 {
+        self.c
+    }
+
+    pub fn key(&self) -> &Arc<CloudKey> {
+        &self.key
+    }
+}
+
+impl Not for &FheBool {
+    type Output = FheBool;
+    fn not(self) -> FheBool {
+        FheBool::new(self.key.not(&self.c), self.key.clone())
+    }
+}
+
+impl Not for FheBool {
+    type Output = FheBool;
+    fn not(self) -> FheBool {
+        !&self
+    }
+}
+
+// 値と参照の全ての組み合わせで同じゲートを呼ぶ
+macro_rules! impl_binary_op {
+    ($trait:ident, $method:ident, $gate:ident) => {
+        impl $trait<&FheBool> for &FheBool {
+            type Output = FheBool;
+            fn $method(self, rhs: &FheBool) -> FheBool {
+                self.check_key(&rhs.key);
+                FheBool::new(self.key.$gate(&self.c, &rhs.c), self.key.clone())
+            }
+        }
+
+        impl $trait<FheBool> for &FheBool {
+            type Output = FheBool;
+            fn $method(self, rhs: FheBool) -> FheBool {
+                self.$method(&rhs)
+            }
+        }
+
+        impl $trait<&FheBool> for FheBool {
+            type Output = FheBool;
+            fn $method(self, rhs: &FheBool) -> FheBool {
+                (&self).$method(rhs)
+            }
+        }
+
+        impl $trait<FheBool> for FheBool {
+            type Output = FheBool;
+            fn $method(self, rhs: FheBool) -> FheBool {
+                (&self).$method(&rhs)
+            }
+        }
+    };
+}
+
+impl_binary_op!(BitAnd, bitand, and);
+impl_binary_op!(BitOr, bitor, or);
+impl_binary_op!(BitXor, bitxor, xor);
+
+#[test]
+fn test_fhe_bool() {
+    use super::key::SecretKey;
+
+    let sk = SecretKey::new();
+    let tlwe = TLWE::new(sk);
+    let key = Arc::new(CloudKey::new(sk));
+    let encrypt = |b: bool| FheBool::encrypt(b, &tlwe, key.clone());
+
+    for x in [true, false] {
+        let cx = encrypt(x);
+        assert_eq!((!&cx).decrypt(&tlwe), !x);
+        for y in [true, false] {
+            let cy = encrypt(y);
+            assert_eq!((&cx & &cy).decrypt(&tlwe), x & y);
+            assert_eq!((&cx | cy.clone()).decrypt(&tlwe), x | y);
+            assert_eq!((cx.clone() ^ &cy).decrypt(&tlwe), x ^ y);
+        }
+    }
+
+    for c in [true, false] {
+        for x in [true, false] {
+            for y in [true, false] {
+                let (cc, cx, cy) = (encrypt(c), encrypt(x), encrypt(y));
+                let expected = if c { x } else { y };
+                assert_eq!(cc.mux(&cx, &cy).decrypt(&tlwe), expected);
+            }
+        }
+    }
+
+    let one = FheBool::trivial(true, key);
+    assert!(one.decrypt(&tlwe));
+}
+
+#[test]
+#[should_panic(expected = "different cloud keys")]
+fn test_fhe_bool_different_keys() {
+    use super::key::SecretKey;
+
+    // 鍵生成は重いので、別の Arc に複製したものを別の鍵とみなす
+    let sk = SecretKey::new();
+    let tlwe = TLWE::new(sk);
+    let ck = CloudKey::new(sk);
+    let cx = FheBool::encrypt(true, &tlwe, Arc::new(ck.clone()));
+    let cy = FheBool::encrypt(true, &tlwe, Arc::new(ck));
+    let _ = &cx & &cy;
+}
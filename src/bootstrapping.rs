@@ -1,16 +1,41 @@
-use super::key::SecretKey;
+use super::key::{KeyDistribution, SecretKey};
+use super::key_switching::{identity_key_switching_with_key, KeySwitchingKey};
+use super::ops::{rmadd, rmsub};
 use super::params::{tlwe, trgsw};
 use super::tlwe::{CipherTLWELv0, CipherTLWELv1};
-use super::trgsw::{cmux, TRGSWMatrix, TRGSW};
+use super::trgsw::{cmux, external_product, rotate_trgsw_matrix, TRGSWMatrix, TRGSW};
 use super::trlwe::{sample_extract_index, CipherTRLWE, TRLWE};
-use super::util::rotate_ring;
+use super::util::{float_to_torus, rotate_ring, torus_negative, Torus};
 
 const N: usize = trgsw::N;
 const L: usize = trgsw::L;
 const NBIT: usize = trgsw::NBIT;
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BootstrappingKeyLayout {
+    // j 番目: TRGSW(s_j)
+    Binary,
+    // 2j 番目: TRGSW(s_j == 1), 2j+1 番目: TRGSW(s_j == -1)
+    Ternary,
+    // 2 ビットずつ束ねる (i = 2p, j = 2p + 1)
+    // 3p 番目: TRGSW(s_i * s_j), 3p+1 番目: TRGSW(s_i * (1 - s_j)), 3p+2 番目: TRGSW((1 - s_i) * s_j)
+    // tlwe::N が奇数の場合、最後のビットは TRGSW(s_j) を 1 つだけ持つ
+    Bundled,
+}
+
+impl BootstrappingKeyLayout {
+    // 必要な TRGSW の個数
+    pub fn size(self) -> usize {
+        match self {
+            BootstrappingKeyLayout::Binary => tlwe::N,
+            BootstrappingKeyLayout::Ternary => 2 * tlwe::N,
+            BootstrappingKeyLayout::Bundled => 3 * (tlwe::N / 2) + tlwe::N % 2,
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
-pub struct BootstrappingKey(pub Vec<TRGSWMatrix>);
+pub struct BootstrappingKey(pub Vec<TRGSWMatrix>, pub BootstrappingKeyLayout);
 
 impl BootstrappingKey {
     pub fn get(&self, k: usize) -> TRGSWMatrix {
@@ -20,17 +45,62 @@ impl BootstrappingKey {
     pub fn set(&mut self, k: usize, val: TRGSWMatrix) {
         self.0[k] = val;
     }
+
+    pub fn layout(&self) -> BootstrappingKeyLayout {
+        self.1
+    }
 }
 
 pub fn bootstrapping_key(sk: SecretKey) -> BootstrappingKey {
+    let layout = match sk.lv0_dist {
+        KeyDistribution::Binary => BootstrappingKeyLayout::Binary,
+        KeyDistribution::Ternary => BootstrappingKeyLayout::Ternary,
+        KeyDistribution::Gaussian => unreachable!("lv0 key must be binary or ternary"),
+    };
+    bootstrapping_key_with_layout(sk, layout)
+}
+
+pub fn bootstrapping_key_with_layout(
+    sk: SecretKey,
+    layout: BootstrappingKeyLayout,
+) -> BootstrappingKey {
     let trgsw = TRGSW::new(sk);
     let lv0 = sk.lv0;
-    let mut bk = BootstrappingKey(vec![[[[0; N]; 2]; 2 * L]; tlwe::N]);
 
-    for (j, &item) in lv0.iter().enumerate().take(tlwe::N) {
-        bk.set(j, trgsw.coefficient(item as i8));
+    match layout {
+        BootstrappingKeyLayout::Binary => {
+            assert_eq!(sk.lv0_dist, KeyDistribution::Binary);
+            let mut bk = BootstrappingKey(vec![[[[0; N]; 2]; 2 * L]; layout.size()], layout);
+            for (j, &item) in lv0.iter().enumerate().take(tlwe::N) {
+                bk.set(j, trgsw.coefficient(item as i8));
+            }
+            bk
+        }
+        BootstrappingKeyLayout::Ternary => {
+            let mut bk = BootstrappingKey(vec![[[[0; N]; 2]; 2 * L]; layout.size()], layout);
+            for (j, &item) in lv0.iter().enumerate().take(tlwe::N) {
+                bk.set(2 * j, trgsw.coefficient_bool(item == 1));
+                bk.set(2 * j + 1, trgsw.coefficient_bool(item == torus_negative(1)));
+            }
+            bk
+        }
+        BootstrappingKeyLayout::Bundled => {
+            assert_eq!(sk.lv0_dist, KeyDistribution::Binary);
+            let pairs = tlwe::N / 2;
+            let mut bk = BootstrappingKey(vec![[[[0; N]; 2]; 2 * L]; layout.size()], layout);
+            for p in 0..pairs {
+                let si = lv0[2 * p] == 1;
+                let sj = lv0[2 * p + 1] == 1;
+                bk.set(3 * p, trgsw.coefficient_bool(si && sj));
+                bk.set(3 * p + 1, trgsw.coefficient_bool(si && !sj));
+                bk.set(3 * p + 2, trgsw.coefficient_bool(!si && sj));
+            }
+            if tlwe::N % 2 == 1 {
+                bk.set(3 * pairs, trgsw.coefficient(lv0[tlwe::N - 1] as i8));
+            }
+            bk
+        }
     }
-    bk
 }
 
 fn rotate_trlwe_cipher(c: CipherTRLWE, k: usize) -> CipherTRLWE {
@@ -40,16 +110,49 @@ fn rotate_trlwe_cipher(c: CipherTRLWE, k: usize) -> CipherTRLWE {
     CipherTRLWE(a_rot, b_rot)
 }
 
-pub fn blind_rotate(c0: CipherTLWELv0, c1: CipherTRLWE, bk: BootstrappingKey) -> CipherTRLWE {
+// (X^k - 1) * matrix
+fn monomial_minus_one(matrix: TRGSWMatrix, k: usize) -> TRGSWMatrix {
+    rmsub(rotate_trgsw_matrix(matrix, k % (2 * N)), matrix)
+}
+
+pub fn blind_rotate(c0: CipherTLWELv0, c1: CipherTRLWE, bk: &BootstrappingKey) -> CipherTRLWE {
     let (a0, b0) = c0.describe();
     let b_floor = (b0 >> (31 - NBIT)) as usize;
     let offset = 2u32.pow(30 - NBIT as u32);
 
     let mut c_ret = rotate_trlwe_cipher(c1, 2 * N - b_floor);
-    for (j, a0) in a0.iter().enumerate().take(tlwe::N) {
-        let a_floor = ((a0 + offset) >> (31 - NBIT)) as usize;
-        let c_ret_rot = rotate_trlwe_cipher(c_ret, a_floor);
-        c_ret = cmux(bk.get(j), c_ret_rot, c_ret);
+    // a0 が 2^32 - offset 以上だと桁があふれる. 2N に丸めると 0 なので wrap させてよい
+    let a_floor = |j: usize| (a0[j].wrapping_add(offset) >> (31 - NBIT)) as usize;
+    match bk.layout() {
+        BootstrappingKeyLayout::Binary => {
+            for j in 0..tlwe::N {
+                let c_ret_rot = rotate_trlwe_cipher(c_ret, a_floor(j));
+                c_ret = cmux(bk.get(j), c_ret_rot, c_ret);
+            }
+        }
+        BootstrappingKeyLayout::Ternary => {
+            for j in 0..tlwe::N {
+                // c + ((X^a - 1) * TRGSW(s_j == 1) + (X^-a - 1) * TRGSW(s_j == -1)) ⊡ c
+                let plus = monomial_minus_one(bk.get(2 * j), a_floor(j));
+                let minus = monomial_minus_one(bk.get(2 * j + 1), 2 * N - a_floor(j));
+                c_ret = external_product(rmadd(plus, minus), c_ret) + c_ret;
+            }
+        }
+        BootstrappingKeyLayout::Bundled => {
+            let pairs = tlwe::N / 2;
+            for p in 0..pairs {
+                // c + ((X^(a_i + a_j) - 1) * BK_11 + (X^a_i - 1) * BK_10 + (X^a_j - 1) * BK_01) ⊡ c
+                let (ai, aj) = (a_floor(2 * p), a_floor(2 * p + 1));
+                let m11 = monomial_minus_one(bk.get(3 * p), ai + aj);
+                let m10 = monomial_minus_one(bk.get(3 * p + 1), ai);
+                let m01 = monomial_minus_one(bk.get(3 * p + 2), aj);
+                c_ret = external_product(rmadd(rmadd(m11, m10), m01), c_ret) + c_ret;
+            }
+            if tlwe::N % 2 == 1 {
+                let c_ret_rot = rotate_trlwe_cipher(c_ret, a_floor(tlwe::N - 1));
+                c_ret = cmux(bk.get(3 * pairs), c_ret_rot, c_ret);
+            }
+        }
     }
     c_ret
 }
@@ -57,10 +160,77 @@ pub fn blind_rotate(c0: CipherTLWELv0, c1: CipherTRLWE, bk: BootstrappingKey) ->
 pub fn gate_bootstrapping(c0: CipherTLWELv0, sk: SecretKey) -> CipherTLWELv1 {
     let tv = TRLWE::new(sk).test_vector();
     let bk = bootstrapping_key(sk);
+    let c = blind_rotate(c0, tv, &bk);
+    sample_extract_index(c, 0)
+}
+
+// 秘密鍵を使わず、自明な test vector で gate bootstrapping する
+pub fn gate_bootstrapping_with_key(c0: CipherTLWELv0, bk: &BootstrappingKey) -> CipherTLWELv1 {
+    let tv = functional_test_vector(1, |_| float_to_torus(0.125));
     let c = blind_rotate(c0, tv, bk);
     sample_extract_index(c, 0)
 }
 
+// [0, 1/2) を slots 個に等分し、k 番目の係数に f(その区間の番号) を置いた自明な TRLWE
+fn functional_test_vector<F: Fn(usize) -> Torus>(slots: usize, f: F) -> CipherTRLWE {
+    let mut tv = [0; N];
+    for (k, t) in tv.iter_mut().enumerate() {
+        *t = f(k * slots / N);
+    }
+    CipherTRLWE([0; N], tv)
+}
+
+// 入力は m / (2p) (m in [0, p)) で符号化されていることを仮定し、f(m) を暗号化した TLWE を返す
+// 負巡回性のため、位相が [1/2, 1) に入ると -f になる (パディングビットが必要)
+pub fn programmable_bootstrapping<F: Fn(usize) -> Torus>(
+    c0: CipherTLWELv0,
+    p: usize,
+    f: F,
+    bk: &BootstrappingKey,
+) -> CipherTLWELv1 {
+    assert!(0 < p && p <= N, "p: {}", p);
+
+    // 区間の中央に寄せる
+    let shift = CipherTLWELv0::trivial(float_to_torus(1. / (4 * p) as f64));
+    let tv = functional_test_vector(p, f);
+    let c = blind_rotate(c0 + shift, tv, bk);
+    sample_extract_index(c, 0)
+}
+
+// 入力は m / p (m in [0, p)) で符号化されていることを仮定し、任意の f について f(m) を暗号化した TLWE を返す
+//   (1) 符号抽出: 位相が [1/2, 1) にあるか (h) を bootstrapping で求め、c' = c - h/2 を作る
+//   (2) f0(m') = f(m'), f1(m') = f(m' + p/2) として
+//       A = (f0 - f1) / 2 を c に、B = A + f1 を c' に対してそれぞれ bootstrapping する
+//       c は負巡回性で (-1)^h * A(m') になるので、足すと h = 0 なら f0、h = 1 なら f1 になる
+pub fn full_domain_bootstrapping<F: Fn(usize) -> Torus>(
+    c0: CipherTLWELv0,
+    p: usize,
+    f: F,
+    bk: &BootstrappingKey,
+    ks: &KeySwitchingKey,
+) -> CipherTLWELv1 {
+    assert!(0 < p && p <= 2 * N && p & 1 == 0, "p: {}", p);
+
+    let half = p / 2;
+    let quarter = CipherTLWELv0::trivial(float_to_torus(0.25));
+
+    // 区間の中央に寄せる
+    let c = c0 + CipherTLWELv0::trivial(float_to_torus(1. / (2 * p) as f64));
+
+    // (1) ±1/4 から h/2 を作る
+    let sign_tv = functional_test_vector(1, |_| float_to_torus(0.25));
+    let sign = sample_extract_index(blind_rotate(c, sign_tv, bk), 0);
+    let h = identity_key_switching_with_key(sign, ks) - quarter;
+    let c_ = c + h;
+
+    // (2)
+    let a = |m: usize| f(m).wrapping_sub(f(m + half)) / 2;
+    let b = |m: usize| f(m + half).wrapping_add(a(m));
+    let ca = sample_extract_index(blind_rotate(c, functional_test_vector(half, a), bk), 0);
+    let cb = sample_extract_index(blind_rotate(c_, functional_test_vector(half, b), bk), 0);
+    ca + cb
+}
+
 #[test]
 fn test_trlwe_rotate() {
     use rand_distr::{Distribution, Uniform};
@@ -93,6 +263,7 @@ fn test_trlwe_rotate() {
 }
 
 #[test]
+#[allow(clippy::bool_assert_comparison)]
 fn test_blind_rotate() {
     use super::tlwe::TLWELv1;
 
@@ -103,7 +274,7 @@ fn test_blind_rotate() {
     let tv = trlwe.test_vector();
     let bk = bootstrapping_key(sk);
 
-    let rot_c = blind_rotate(c0, tv, bk);
+    let rot_c = blind_rotate(c0, tv, &bk);
     let c1 = sample_extract_index(rot_c, 0);
     let b1 = tlwe1.decrypt(c1);
 
@@ -111,6 +282,7 @@ fn test_blind_rotate() {
 }
 
 #[test]
+#[allow(clippy::bool_assert_comparison)]
 fn test_blind_rotate_2() {
     use super::tlwe::TLWELv1;
 
@@ -121,13 +293,188 @@ fn test_blind_rotate_2() {
     let tv = trlwe.test_vector();
     let bk = bootstrapping_key(sk);
 
-    let rot_c = blind_rotate(-c0, tv, bk);
+    let rot_c = blind_rotate(-c0, tv, &bk);
     let c1 = sample_extract_index(rot_c, 0);
     let b1 = tlwe1.decrypt(c1);
 
     assert_eq!(false, b1);
 }
 
+#[test]
+fn test_blind_rotate_offset_overflow() {
+    use super::tlwe::TLWELv1;
+    use super::util::float_to_torus;
+
+    // a の係数がすべて -1 (2N に丸めると 0) なので、位相は b のまま
+    let sk = SecretKey::new();
+    let tlwe1 = TLWELv1::new(sk);
+    let trlwe = TRLWE::new(sk);
+    let bk = bootstrapping_key(sk);
+    for (b, expected) in [(0.125, true), (-0.125, false)] {
+        let c0 = CipherTLWELv0([u32::MAX; tlwe::N], float_to_torus(b));
+        let rot_c = blind_rotate(c0, trlwe.test_vector(), &bk);
+        assert_eq!(tlwe1.decrypt(sample_extract_index(rot_c, 0)), expected);
+    }
+}
+
+#[test]
+fn test_blind_rotate_ternary() {
+    use super::tlwe::{TLWELv1, TLWE};
+
+    let sk = SecretKey::with_distribution(KeyDistribution::Ternary, KeyDistribution::Binary);
+    let tlwe0 = TLWE::new(sk);
+    let tlwe1 = TLWELv1::new(sk);
+    let tv = TRLWE::new(sk).test_vector();
+    let bk = bootstrapping_key(sk);
+    assert_eq!(bk.layout(), BootstrappingKeyLayout::Ternary);
+
+    for b in [true, false] {
+        let c0 = tlwe0.encrypt(b);
+        let rot_c = blind_rotate(c0, tv, &bk);
+        let c1 = sample_extract_index(rot_c, 0);
+        assert_eq!(b, tlwe1.decrypt(c1));
+    }
+}
+
+#[test]
+fn test_blind_rotate_bundled() {
+    use super::tlwe::{TLWELv1, TLWE};
+
+    let sk = SecretKey::new();
+    let tlwe0 = TLWE::new(sk);
+    let tlwe1 = TLWELv1::new(sk);
+    let tv = TRLWE::new(sk).test_vector();
+    let bk = bootstrapping_key(sk);
+    let bk_bundled = bootstrapping_key_with_layout(sk, BootstrappingKeyLayout::Bundled);
+    assert_eq!(bk_bundled.0.len(), 3 * (tlwe::N / 2) + tlwe::N % 2);
+
+    for b in [true, false] {
+        let c0 = tlwe0.encrypt(b);
+        let c1 = sample_extract_index(blind_rotate(c0, tv, &bk), 0);
+        let c1_bundled = sample_extract_index(blind_rotate(c0, tv, &bk_bundled), 0);
+        assert_eq!(tlwe1.decrypt(c1), tlwe1.decrypt(c1_bundled));
+        assert_eq!(b, tlwe1.decrypt(c1_bundled));
+    }
+}
+
+#[test]
+fn test_gate_bootstrapping_ternary_gaussian() {
+    use super::key_switching::identity_key_switching;
+    use super::tlwe::TLWE;
+
+    let sk = SecretKey::with_distribution(KeyDistribution::Ternary, KeyDistribution::Gaussian);
+    let tlwe0 = TLWE::new(sk);
+
+    for b in [true, false] {
+        let c = tlwe0.encrypt(b);
+        let c1 = gate_bootstrapping(c, sk);
+        let c0 = identity_key_switching(c1, sk);
+        assert_eq!(b, tlwe0.decrypt(c0));
+    }
+}
+
+// f(m + p/2) = -f(m) (mod p) を満たす負巡回な関数と、満たさない関数
+#[cfg(test)]
+const NEGACYCLIC: fn(usize, usize) -> usize = |m, p| {
+    if m < p / 2 {
+        m + 1
+    } else {
+        3 * p / 2 - m - 1
+    }
+};
+#[cfg(test)]
+const NON_NEGACYCLIC: fn(usize, usize) -> usize = |m, p| (m * m + 1) % p;
+
+#[cfg(test)]
+fn check_programmable_bootstrapping(ps: &[usize], fs: &[fn(usize, usize) -> usize]) {
+    use super::tlwe::{TLWELv1, TLWE};
+    use super::util::torus_to_float;
+
+    let sk = SecretKey::new();
+    let tlwe0 = TLWE::new(sk);
+    let tlwe1 = TLWELv1::new(sk);
+    let bk = bootstrapping_key(sk);
+
+    for &p in ps {
+        let encode = |m: usize| float_to_torus(m as f64 / (2 * p) as f64);
+        for f in fs {
+            for m in 0..p {
+                let c = tlwe0.encrypt_torus(encode(m));
+                let c1 = programmable_bootstrapping(c, p, |x| encode(f(x, p)), &bk);
+                let x = torus_to_float(tlwe1.decrypt_torus(c1));
+                let dec = (x * (2 * p) as f64).round() as i64;
+                assert_eq!(dec, f(m, p) as i64, "p: {}, m: {}", p, m);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+fn check_full_domain_bootstrapping(ps: &[usize], fs: &[fn(usize, usize) -> usize]) {
+    use super::tlwe::{TLWELv1, TLWE};
+    use super::util::torus_to_float;
+
+    let sk = SecretKey::new();
+    let tlwe0 = TLWE::new(sk);
+    let tlwe1 = TLWELv1::new(sk);
+    let bk = bootstrapping_key(sk);
+    let ks = KeySwitchingKey::new(sk);
+
+    for &p in ps {
+        let encode = |m: usize| float_to_torus(m as f64 / p as f64);
+        for f in fs {
+            for m in 0..p {
+                let c = tlwe0.encrypt_torus(encode(m));
+                let c1 = full_domain_bootstrapping(c, p, |x| encode(f(x, p)), &bk, &ks);
+                let x = torus_to_float(tlwe1.decrypt_torus(c1));
+                let dec = (x * p as f64).round() as i64;
+                assert_eq!(
+                    dec.rem_euclid(p as i64),
+                    f(m, p) as i64,
+                    "p: {}, m: {}",
+                    p,
+                    m
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn test_programmable_bootstrapping() {
+    check_programmable_bootstrapping(&[4], &[NEGACYCLIC, NON_NEGACYCLIC]);
+}
+
+// いくつかの p と関数のすべての平文. FFT を使っていないので時間がかかる (cargo test -- --ignored)
+#[test]
+#[ignore]
+fn test_programmable_bootstrapping_exhaustive() {
+    check_programmable_bootstrapping(
+        &[2, 4, 8],
+        &[
+            NEGACYCLIC,
+            NON_NEGACYCLIC,
+            |m, p| (3 * m + 1) % p,
+            |m, p| (m * m) % p,
+        ],
+    );
+}
+
+#[test]
+fn test_full_domain_bootstrapping() {
+    check_full_domain_bootstrapping(&[4], &[NEGACYCLIC, NON_NEGACYCLIC]);
+}
+
+// 1 つの平文につき 2 回 bootstrapping するのでさらに時間がかかる (cargo test -- --ignored)
+#[test]
+#[ignore]
+fn test_full_domain_bootstrapping_exhaustive() {
+    check_full_domain_bootstrapping(
+        &[2, 4, 8],
+        &[NEGACYCLIC, NON_NEGACYCLIC, |m, p| (p - 1 - m) / 2],
+    );
+}
+
 // FFTじゃないと重すぎて時間がかかる
 
 // #[test]
@@ -0,0 +1,346 @@
+// Bristol Fashion 形式の回路を読み込む
+// https://homes.esat.kuleuven.be/~nsmart/MPC/
+//
+// 入力ワイヤは先頭から、出力ワイヤは末尾から、それぞれ値ごとに幅の分だけ並んでいる
+// 値の中のビット順はファイルのワイヤ順のまま (bits[0] が最初のワイヤ)
+
+use std::fmt;
+
+use super::circuit::{Circuit, Netlist, NodeId, Port};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BristolError {
+    // line は 1 始まり
+    Parse { line: usize, msg: String },
+    UnsupportedGate { line: usize, op: String },
+    UndefinedWire { line: usize, wire: usize },
+}
+
+impl fmt::Display for BristolError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BristolError::Parse { line, msg } => write!(f, "line {}: {}", line, msg),
+            BristolError::UnsupportedGate { line, op } => {
+                write!(f, "line {}: unsupported gate {}", line, op)
+            }
+            BristolError::UndefinedWire { line, wire } => {
+                write!(f, "line {}: wire {} is used before defined", line, wire)
+            }
+        }
+    }
+}
+
+impl std::error::Error for BristolError {}
+
+fn parse_error<T>(line: usize, msg: &str) -> Result<T, BristolError> {
+    Err(BristolError::Parse {
+        line,
+        msg: msg.to_string(),
+    })
+}
+
+fn parse_numbers(line: usize, tokens: &[&str]) -> Result<Vec<usize>, BristolError> {
+    tokens
+        .iter()
+        .map(|t| {
+            t.parse::<usize>().map_err(|_| BristolError::Parse {
+                line,
+                msg: format!("expected a number, found {}", t),
+            })
+        })
+        .collect()
+}
+
+// "<個数> <幅> <幅> ..." の行
+fn parse_widths(line: usize, text: &str) -> Result<Vec<usize>, BristolError> {
+    let tokens: Vec<&str> = text.split_whitespace().collect();
+    let ns = parse_numbers(line, &tokens)?;
+    match ns.split_first() {
+        Some((&n, widths)) if widths.len() == n => Ok(widths.to_vec()),
+        _ => parse_error(line, "number of values does not match"),
+    }
+}
+
+// 幅の合計. 溢れたら None
+fn total_width(widths: &[usize]) -> Option<usize> {
+    widths.iter().try_fold(0usize, |n, &w| n.checked_add(w))
+}
+
+pub fn parse_bristol(text: &str) -> Result<Netlist, BristolError> {
+    let text_len = text.len();
+    // 空行を飛ばしつつ、行番号を覚えておく
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .map(|(i, l)| (i + 1, l.trim()))
+        .filter(|(_, l)| !l.is_empty());
+
+    let (ln, header) = match lines.next() {
+        Some(l) => l,
+        None => return parse_error(1, "empty circuit"),
+    };
+    let header = parse_numbers(ln, &header.split_whitespace().collect::<Vec<_>>())?;
+    let (num_gates, num_wires) = match header[..] {
+        [g, w] => (g, w),
+        _ => return parse_error(ln, "expected <gates> <wires>"),
+    };
+    let header_ln = ln;
+
+    let (ln, text) = match lines.next() {
+        Some(l) => l,
+        None => return parse_error(ln + 1, "missing input header"),
+    };
+    let input_widths = parse_widths(ln, text)?;
+    let num_inputs = match total_width(&input_widths) {
+        Some(n) if n <= num_wires => n,
+        _ => return parse_error(ln, "too many input wires"),
+    };
+    let (ln, text) = match lines.next() {
+        Some(l) => l,
+        None => return parse_error(ln + 1, "missing output header"),
+    };
+    let output_widths = parse_widths(ln, text)?;
+    let num_outputs = match total_width(&output_widths) {
+        Some(n) if n <= num_wires => n,
+        _ => return parse_error(ln, "too many output wires"),
+    };
+
+    // 入力以外のワイヤはゲートの出力としてファイルに書かれているはずなので、
+    // ヘッダの値をそのまま信じて巨大な領域を確保しない
+    if num_wires - num_inputs > text_len {
+        return parse_error(header_ln, "too many wires for the size of the circuit");
+    }
+
+    let mut circuit = Circuit::new();
+    let mut wires: Vec<Option<NodeId>> = vec![None; num_wires];
+    for w in wires.iter_mut().take(num_inputs) {
+        *w = Some(circuit.input());
+    }
+
+    let mut consts: [Option<NodeId>; 2] = [None, None];
+    let mut count = 0;
+    for (ln, text) in lines {
+        let tokens: Vec<&str> = text.split_whitespace().collect();
+        let (op, tokens) = match tokens.split_last() {
+            Some((op, tokens)) if tokens.len() >= 2 => (*op, tokens),
+            _ => return parse_error(ln, "malformed gate"),
+        };
+        let ns = parse_numbers(ln, tokens)?;
+        let (nin, nout) = (ns[0], ns[1]);
+        if nin.checked_add(nout) != Some(ns.len() - 2) {
+            return parse_error(ln, "number of wires does not match");
+        }
+        let (ins, outs) = ns[2..].split_at(nin);
+
+        for (i, &w) in outs.iter().enumerate() {
+            if w >= num_wires {
+                return parse_error(ln, &format!("wire {} is out of range", w));
+            }
+            if wires[w].is_some() || outs[..i].contains(&w) {
+                return parse_error(ln, &format!("wire {} is assigned twice", w));
+            }
+        }
+        let get = |wires: &[Option<NodeId>], w: usize| match wires.get(w).copied().flatten() {
+            Some(id) => Ok(id),
+            None => Err(BristolError::UndefinedWire { line: ln, wire: w }),
+        };
+
+        match (op, nin, nout) {
+            ("XOR", 2, 1) => {
+                let (x, y) = (get(&wires, ins[0])?, get(&wires, ins[1])?);
+                wires[outs[0]] = Some(circuit.xor(x, y));
+            }
+            ("AND", 2, 1) => {
+                let (x, y) = (get(&wires, ins[0])?, get(&wires, ins[1])?);
+                wires[outs[0]] = Some(circuit.and(x, y));
+            }
+            ("INV", 1, 1) => {
+                let x = get(&wires, ins[0])?;
+                wires[outs[0]] = Some(circuit.not(x));
+            }
+            // 入力はワイヤではなく定数
+            ("EQ", 1, 1) => {
+                let b = match ins[0] {
+                    0 => false,
+                    1 => true,
+                    _ => return parse_error(ln, "EQ takes 0 or 1"),
+                };
+                let id = *consts[b as usize].get_or_insert_with(|| circuit.constant(b));
+                wires[outs[0]] = Some(id);
+            }
+            ("EQW", 1, 1) => {
+                wires[outs[0]] = Some(get(&wires, ins[0])?);
+            }
+            ("MAND", _, _) if nin == 2 * nout => {
+                let (xs, ys) = ins.split_at(nout);
+                for i in 0..nout {
+                    let (x, y) = (get(&wires, xs[i])?, get(&wires, ys[i])?);
+                    wires[outs[i]] = Some(circuit.and(x, y));
+                }
+            }
+            ("XOR", ..) | ("AND", ..) | ("INV", ..) | ("EQ", ..) | ("EQW", ..) | ("MAND", ..) => {
+                return parse_error(ln, &format!("wrong number of wires for {}", op));
+            }
+            _ => {
+                return Err(BristolError::UnsupportedGate {
+                    line: ln,
+                    op: op.to_string(),
+                })
+            }
+        }
+        count += 1;
+    }
+
+    if count != num_gates {
+        // ゲート数はヘッダ (1 行目) に書かれている
+        return parse_error(1, &format!("expected {} gates, found {}", num_gates, count));
+    }
+
+    for (w, &id) in wires.iter().enumerate().skip(num_wires - num_outputs) {
+        match id {
+            Some(id) => circuit.output(id),
+            None => return parse_error(1, &format!("output wire {} is never assigned", w)),
+        }
+    }
+
+    let ports = |prefix: &str, widths: &[usize]| -> Vec<Port> {
+        widths
+            .iter()
+            .enumerate()
+            .map(|(i, &width)| Port {
+                name: format!("{}{}", prefix, i),
+                width,
+            })
+            .collect()
+    };
+
+    Ok(Netlist {
+        circuit,
+        inputs: ports("in", &input_widths),
+        outputs: ports("out", &output_widths),
+    })
+}
+
+// 2 ビット加算器 (3 ビット出力). 全種類のゲートを使っている
+#[cfg(test)]
+const ADDER2: &str = "12 17
+2 2 2
+1 3
+
+2 1 0 2 4 XOR
+2 1 0 2 5 AND
+2 1 1 3 6 XOR
+1 1 6 7 EQW
+2 1 7 5 8 XOR
+4 2 1 7 3 5 9 10 MAND
+1 1 9 11 INV
+1 1 10 12 INV
+2 1 11 12 13 AND
+1 1 13 16 INV
+1 1 4 14 EQW
+1 1 8 15 EQW
+";
+
+#[test]
+fn test_parse_adder() {
+    let netlist = parse_bristol(ADDER2).unwrap();
+    assert_eq!(netlist.inputs.len(), 2);
+    assert_eq!(netlist.outputs[0].width, 3);
+
+    for x in 0..4 {
+        for y in 0..4 {
+            let mut bs = Vec::new();
+            for v in [x, y] {
+                bs.push(v & 1 == 1);
+                bs.push(v & 2 == 2);
+            }
+            let out = netlist.circuit.evaluate_cleartext(&bs);
+            let z = out
+                .iter()
+                .enumerate()
+                .fold(0, |z, (i, &b)| z | ((b as usize) << i));
+            assert_eq!(z, x + y, "x: {}, y: {}", x, y);
+        }
+    }
+}
+
+#[test]
+fn test_parse_constants() {
+    let text = "3 4
+1 1
+1 2
+1 1 1 1 EQ
+2 1 0 1 2 XOR
+1 1 0 3 EQ
+";
+    let netlist = parse_bristol(text).unwrap();
+    for b in [false, true] {
+        assert_eq!(netlist.circuit.evaluate_cleartext(&[b]), vec![!b, false]);
+    }
+}
+
+#[test]
+fn test_parse_errors() {
+    let or = "1 3\n1 2\n1 1\n2 1 0 1 2 OR\n";
+    assert_eq!(
+        parse_bristol(or),
+        Err(BristolError::UnsupportedGate {
+            line: 4,
+            op: "OR".to_string()
+        })
+    );
+
+    let undefined = "1 4\n1 2\n1 1\n2 1 0 2 3 AND\n";
+    assert_eq!(
+        parse_bristol(undefined),
+        Err(BristolError::UndefinedWire { line: 4, wire: 2 })
+    );
+
+    let bad = "1 3\n1 2\n1 1\n2 1 0 x 2 AND\n";
+    assert!(matches!(
+        parse_bristol(bad),
+        Err(BristolError::Parse { line: 4, .. })
+    ));
+}
+
+#[test]
+fn test_parse_malformed_sizes() {
+    let parse_line = |text: &str| match parse_bristol(text) {
+        Err(BristolError::Parse { line, .. }) => Some(line),
+        _ => None,
+    };
+
+    // ゲートのワイヤ数が溢れる
+    let overflow = "1 3\n1 2\n1 1\n18446744073709551615 1 0 1 2 AND\n";
+    assert_eq!(parse_line(overflow), Some(4));
+    let overflow = "1 3\n1 2\n1 1\n18446744073709551614 2 0 1 2 AND\n";
+    assert_eq!(parse_line(overflow), Some(4));
+
+    // 入出力の幅の合計が溢れる
+    let widths = "1 3\n2 18446744073709551615 1\n1 1\n2 1 0 1 2 AND\n";
+    assert_eq!(parse_line(widths), Some(2));
+    let widths = "1 3\n1 2\n2 18446744073709551615 1\n2 1 0 1 2 AND\n";
+    assert_eq!(parse_line(widths), Some(3));
+
+    // ファイルの大きさに見合わないワイヤ数は確保する前に弾く
+    let wires = "1 18446744073709551615\n1 2\n1 1\n2 1 0 1 2 AND\n";
+    assert_eq!(parse_line(wires), Some(1));
+}
+
+#[test]
+fn test_parse_reassigned_wire() {
+    let parse_line = |text: &str| match parse_bristol(text) {
+        Err(BristolError::Parse { line, .. }) => Some(line),
+        _ => None,
+    };
+
+    // 同じワイヤを 2 回定義する
+    let twice = "2 4\n1 2\n1 1\n2 1 0 1 3 AND\n2 1 0 1 3 XOR\n";
+    assert_eq!(parse_line(twice), Some(5));
+    // 入力ワイヤに書き込む
+    let input = "1 3\n1 2\n1 1\n2 1 0 1 0 AND\n";
+    assert_eq!(parse_line(input), Some(4));
+    // 1 つのゲートの中で同じワイヤに 2 回書き込む
+    let mand = "1 6\n1 4\n1 2\n4 2 0 1 2 3 5 5 MAND\n";
+    assert_eq!(parse_line(mand), Some(4));
+}
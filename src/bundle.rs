@@ -0,0 +1,144 @@
+// 暗号文の束. 平文をビット列にして 1 ビットずつ TLWE lv0 で暗号化する
+// 整数は下位ビットから、バイト列は各バイトの下位ビットから並べる
+
+use super::tlwe::{CipherTLWELv0, TLWE};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    Bits,
+    // width ビットの符号なし整数の列 (1 <= width <= 64)
+    Integers { width: usize },
+    Bytes,
+}
+
+impl Encoding {
+    // 1 つの値が何ビットか
+    pub fn unit(self) -> usize {
+        match self {
+            Encoding::Bits => 1,
+            Encoding::Integers { width } => width,
+            Encoding::Bytes => 8,
+        }
+    }
+
+    // 回路の出力ポートの幅から決める. 1 ビットや 64 ビットを超えるものはビット列
+    pub fn for_width(width: usize) -> Self {
+        if (2..=64).contains(&width) {
+            Encoding::Integers { width }
+        } else {
+            Encoding::Bits
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Plaintext {
+    Bits(Vec<bool>),
+    Integers { width: usize, values: Vec<u64> },
+    Bytes(Vec<u8>),
+}
+
+impl Plaintext {
+    pub fn encoding(&self) -> Encoding {
+        match self {
+            Plaintext::Bits(_) => Encoding::Bits,
+            Plaintext::Integers { width, .. } => Encoding::Integers { width: *width },
+            Plaintext::Bytes(_) => Encoding::Bytes,
+        }
+    }
+
+    pub fn to_bits(&self) -> Vec<bool> {
+        match self {
+            Plaintext::Bits(bs) => bs.clone(),
+            Plaintext::Integers { width, values } => {
+                assert!((1..=64).contains(width));
+                values
+                    .iter()
+                    .flat_map(|&v| (0..*width).map(move |i| (v >> i) & 1 == 1))
+                    .collect()
+            }
+            Plaintext::Bytes(bytes) => bytes
+                .iter()
+                .flat_map(|&v| (0..8).map(move |i| (v >> i) & 1 == 1))
+                .collect(),
+        }
+    }
+
+    pub fn from_bits(encoding: Encoding, bits: &[bool]) -> Self {
+        let unit = encoding.unit();
+        assert!((1..=64).contains(&unit));
+        assert_eq!(bits.len() % unit, 0);
+
+        let values = bits.chunks(unit).map(|chunk| {
+            chunk
+                .iter()
+                .enumerate()
+                .fold(0u64, |v, (i, &b)| v | ((b as u64) << i))
+        });
+        match encoding {
+            Encoding::Bits => Plaintext::Bits(bits.to_vec()),
+            Encoding::Integers { width } => Plaintext::Integers {
+                width,
+                values: values.collect(),
+            },
+            Encoding::Bytes => Plaintext::Bytes(values.map(|v| v as u8).collect()),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct CiphertextBundle {
+    pub encoding: Encoding,
+    pub ciphertexts: Vec<CipherTLWELv0>,
+}
+
+impl CiphertextBundle {
+    pub fn encrypt(plaintext: &Plaintext, tlwe: &TLWE) -> Self {
+        Self {
+            encoding: plaintext.encoding(),
+            ciphertexts: plaintext
+                .to_bits()
+                .into_iter()
+                .map(|b| tlwe.encrypt(b))
+                .collect(),
+        }
+    }
+
+    pub fn decrypt(&self, tlwe: &TLWE) -> Plaintext {
+        let bits: Vec<bool> = self.ciphertexts.iter().map(|&c| tlwe.decrypt(c)).collect();
+        Plaintext::from_bits(self.encoding, &bits)
+    }
+
+    pub fn len(&self) -> usize {
+        self.ciphertexts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ciphertexts.is_empty()
+    }
+}
+
+#[test]
+fn test_plaintext_bits() {
+    let p = Plaintext::Integers {
+        width: 3,
+        values: vec![5, 2],
+    };
+    let bits = p.to_bits();
+    assert_eq!(bits, vec![true, false, true, false, true, false]);
+    assert_eq!(Plaintext::from_bits(p.encoding(), &bits), p);
+
+    let p = Plaintext::Bytes(vec![0x01, 0x80, 0xff]);
+    assert_eq!(Plaintext::from_bits(Encoding::Bytes, &p.to_bits()), p);
+}
+
+#[test]
+fn test_bundle_encrypt_decrypt() {
+    use super::key::SecretKey;
+
+    let tlwe = TLWE::new(SecretKey::new());
+    let p = Plaintext::Bytes(b"kfhe".to_vec());
+    let bundle = CiphertextBundle::encrypt(&p, &tlwe);
+    assert_eq!(bundle.len(), 32);
+    assert_eq!(bundle.decrypt(&tlwe), p);
+}
@@ -0,0 +1,273 @@
+use super::gates::{Cleartext, Gates};
+use super::key::CloudKey;
+use super::tlwe::CipherTLWELv0;
+
+pub type NodeId = usize;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Gate {
+    // 回路の何番目の入力か
+    Input(usize),
+    Const(bool),
+    Nand(NodeId, NodeId),
+    And(NodeId, NodeId),
+    Or(NodeId, NodeId),
+    Xor(NodeId, NodeId),
+    // !x & y
+    AndNY(NodeId, NodeId),
+    // x | !y
+    OrYN(NodeId, NodeId),
+    Not(NodeId),
+    // c ? x : y
+    Mux(NodeId, NodeId, NodeId),
+}
+
+impl Gate {
+    pub fn operands(&self) -> Vec<NodeId> {
+        match *self {
+            Gate::Input(_) | Gate::Const(_) => vec![],
+            Gate::Not(x) => vec![x],
+            Gate::Nand(x, y)
+            | Gate::And(x, y)
+            | Gate::Or(x, y)
+            | Gate::Xor(x, y)
+            | Gate::AndNY(x, y)
+            | Gate::OrYN(x, y) => vec![x, y],
+            Gate::Mux(c, x, y) => vec![c, x, y],
+        }
+    }
+
+    // 評価に必要な bootstrapping の回数 (NOT と定数、入力は不要)
+    pub fn bootstrapping_cost(&self) -> usize {
+        match self {
+            Gate::Input(_) | Gate::Const(_) | Gate::Not(_) => 0,
+            Gate::Mux(..) => 2,
+            _ => 1,
+        }
+    }
+}
+
+// ゲートを頂点とする DAG. 各ゲートのオペランドは必ずそれより前に追加されている
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Circuit {
+    nodes: Vec<Gate>,
+    inputs: Vec<NodeId>,
+    outputs: Vec<NodeId>,
+}
+
+impl Circuit {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn push(&mut self, gate: Gate) -> NodeId {
+        for x in gate.operands() {
+            assert!(x < self.nodes.len(), "undefined node: {}", x);
+        }
+        self.nodes.push(gate);
+        self.nodes.len() - 1
+    }
+
+    pub fn input(&mut self) -> NodeId {
+        let id = self.push(Gate::Input(self.inputs.len()));
+        self.inputs.push(id);
+        id
+    }
+
+    pub fn constant(&mut self, b: bool) -> NodeId {
+        self.push(Gate::Const(b))
+    }
+
+    pub fn nand(&mut self, x: NodeId, y: NodeId) -> NodeId {
+        self.push(Gate::Nand(x, y))
+    }
+
+    pub fn and(&mut self, x: NodeId, y: NodeId) -> NodeId {
+        self.push(Gate::And(x, y))
+    }
+
+    pub fn or(&mut self, x: NodeId, y: NodeId) -> NodeId {
+        self.push(Gate::Or(x, y))
+    }
+
+    pub fn xor(&mut self, x: NodeId, y: NodeId) -> NodeId {
+        self.push(Gate::Xor(x, y))
+    }
+
+    pub fn andny(&mut self, x: NodeId, y: NodeId) -> NodeId {
+        self.push(Gate::AndNY(x, y))
+    }
+
+    pub fn oryn(&mut self, x: NodeId, y: NodeId) -> NodeId {
+        self.push(Gate::OrYN(x, y))
+    }
+
+    pub fn not(&mut self, x: NodeId) -> NodeId {
+        self.push(Gate::Not(x))
+    }
+
+    pub fn mux(&mut self, c: NodeId, x: NodeId, y: NodeId) -> NodeId {
+        self.push(Gate::Mux(c, x, y))
+    }
+
+    pub fn output(&mut self, x: NodeId) {
+        assert!(x < self.nodes.len(), "undefined node: {}", x);
+        self.outputs.push(x);
+    }
+
+    pub fn nodes(&self) -> &[Gate] {
+        &self.nodes
+    }
+
+    pub fn inputs(&self) -> &[NodeId] {
+        &self.inputs
+    }
+
+    pub fn outputs(&self) -> &[NodeId] {
+        &self.outputs
+    }
+
+    pub fn bootstrapping_count(&self) -> usize {
+        self.nodes.iter().map(|g| g.bootstrapping_cost()).sum()
+    }
+
+    pub fn evaluate<G: Gates>(&self, g: &G, inputs: &[G::Bit]) -> Vec<G::Bit> {
+        assert_eq!(inputs.len(), self.inputs.len());
+
+        let mut values: Vec<G::Bit> = Vec::with_capacity(self.nodes.len());
+        for gate in self.nodes.iter() {
+            let v = match *gate {
+                Gate::Input(i) => inputs[i].clone(),
+                Gate::Const(b) => g.constant(b),
+                Gate::Nand(x, y) => g.nand(&values[x], &values[y]),
+                Gate::And(x, y) => g.and(&values[x], &values[y]),
+                Gate::Or(x, y) => g.or(&values[x], &values[y]),
+                Gate::Xor(x, y) => g.xor(&values[x], &values[y]),
+                Gate::AndNY(x, y) => g.andny(&values[x], &values[y]),
+                Gate::OrYN(x, y) => g.oryn(&values[x], &values[y]),
+                Gate::Not(x) => g.not(&values[x]),
+                Gate::Mux(c, x, y) => g.mux(&values[c], &values[x], &values[y]),
+            };
+            values.push(v);
+        }
+        self.outputs.iter().map(|&x| values[x].clone()).collect()
+    }
+
+    // 参照実装
+    pub fn evaluate_cleartext(&self, inputs: &[bool]) -> Vec<bool> {
+        self.evaluate(&Cleartext, inputs)
+    }
+
+    pub fn evaluate_encrypted(
+        &self,
+        ck: &CloudKey,
+        inputs: &[CipherTLWELv0],
+    ) -> Vec<CipherTLWELv0> {
+        self.evaluate(ck, inputs)
+    }
+}
+
+// 外部の回路記述から読み込んだ回路. ポートは回路の入出力を先頭から順に width ずつ区切ったもの
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Port {
+    pub name: String,
+    pub width: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Netlist {
+    pub circuit: Circuit,
+    pub inputs: Vec<Port>,
+    pub outputs: Vec<Port>,
+}
+
+#[cfg(test)]
+fn full_adder() -> Circuit {
+    let mut c = Circuit::new();
+    let (x, y, cin) = (c.input(), c.input(), c.input());
+    let t = c.xor(x, y);
+    let s = c.xor(t, cin);
+    let cout = c.mux(t, cin, x);
+    c.output(s);
+    c.output(cout);
+    c
+}
+
+#[test]
+fn test_evaluate_cleartext() {
+    let c = full_adder();
+    assert_eq!(c.bootstrapping_count(), 4);
+
+    for i in 0..8 {
+        let bs = [i & 1 == 1, i & 2 == 2, i & 4 == 4];
+        let sum = bs.iter().filter(|&&b| b).count();
+        assert_eq!(c.evaluate_cleartext(&bs), vec![sum & 1 == 1, sum >= 2]);
+    }
+
+    // 全種類のゲート
+    let mut c = Circuit::new();
+    let (x, y) = (c.input(), c.input());
+    let one = c.constant(true);
+    let gates = [
+        c.nand(x, y),
+        c.and(x, y),
+        c.or(x, y),
+        c.xor(x, y),
+        c.not(x),
+        c.mux(x, y, one),
+        c.andny(x, y),
+        c.oryn(x, y),
+    ];
+    for g in gates {
+        c.output(g);
+    }
+    for (x, y) in [(false, false), (false, true), (true, false), (true, true)] {
+        let expected = vec![
+            !(x && y),
+            x && y,
+            x || y,
+            x ^ y,
+            !x,
+            if x { y } else { true },
+            !x && y,
+            x || !y,
+        ];
+        assert_eq!(c.evaluate_cleartext(&[x, y]), expected);
+    }
+}
+
+#[test]
+#[should_panic]
+fn test_undefined_node() {
+    let mut c = Circuit::new();
+    let x = c.input();
+    c.and(x, x + 1);
+}
+
+#[test]
+fn test_evaluate_encrypted() {
+    use super::key::SecretKey;
+    use super::sampling::random_bool_initialization;
+    use super::tlwe::TLWE;
+
+    let sk = SecretKey::new();
+    let tlwe = TLWE::new(sk);
+    let ck = CloudKey::new(sk);
+
+    // 半加算器
+    let mut c = Circuit::new();
+    let (x, y) = (c.input(), c.input());
+    let s = c.xor(x, y);
+    let carry = c.and(x, y);
+    c.output(s);
+    c.output(carry);
+
+    let bs: [bool; 2] = random_bool_initialization();
+    let cs: Vec<CipherTLWELv0> = bs.iter().map(|&b| tlwe.encrypt(b)).collect();
+    let out: Vec<bool> = c
+        .evaluate_encrypted(&ck, &cs)
+        .into_iter()
+        .map(|o| tlwe.decrypt(o))
+        .collect();
+    assert_eq!(out, c.evaluate_cleartext(&bs));
+}
@@ -0,0 +1,156 @@
+use std::time::{Duration, Instant};
+
+use serde_json::json;
+
+use kfhe::bootstrapping::{blind_rotate, bootstrapping_key_with_layout};
+use kfhe::gates::Gates;
+use kfhe::key::CloudKey;
+use kfhe::key_switching::{identity_key_switching_with_key, KeySwitchingKey};
+use kfhe::params::trlwe;
+use kfhe::sampling::random_bool_initialization;
+use kfhe::tlwe::{CipherTLWELv0, TLWE};
+use kfhe::trlwe::{sample_extract_index, CipherTRLWE};
+use kfhe::util::float_to_torus;
+
+use super::{error, Args, CliResult};
+
+const STAGES: [&str; 8] = [
+    "bootstrapping_key",
+    "KeySwitchingKey::new",
+    "encrypt",
+    "blind_rotate",
+    "sample_extract_index",
+    "identity_key_switching",
+    "decrypt",
+    "homnand",
+];
+
+struct Stage {
+    name: &'static str,
+    samples: Vec<Duration>,
+}
+
+impl Stage {
+    fn mean(&self) -> Duration {
+        self.samples.iter().sum::<Duration>() / self.samples.len() as u32
+    }
+
+    // nearest-rank
+    fn percentile(&self, p: f64) -> Duration {
+        let mut xs = self.samples.clone();
+        xs.sort();
+        let rank = ((p / 100.) * xs.len() as f64).ceil() as usize;
+        xs[rank.clamp(1, xs.len()) - 1]
+    }
+}
+
+fn time<T>(stage: &mut Stage, f: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let x = f();
+    stage.samples.push(start.elapsed());
+    x
+}
+
+pub fn run(args: &Args) -> CliResult {
+    let params = args.params()?;
+    let iterations = match args.value("iterations")? {
+        Some(n) => match n.parse::<usize>() {
+            Ok(n) if n > 0 => n,
+            _ => return error(format!("invalid number of iterations: {}", n)),
+        },
+        None => 5,
+    };
+
+    let mut stages: Vec<Stage> = STAGES
+        .iter()
+        .map(|&name| Stage {
+            name,
+            samples: Vec::with_capacity(iterations),
+        })
+        .collect();
+    let mut gate_errors = 0;
+
+    // 自明な test vector (gate bootstrapping と同じもの)
+    let tv = CipherTRLWE([0; trlwe::N], [float_to_torus(0.125); trlwe::N]);
+
+    for _ in 0..iterations {
+        let sk = params.secret_key();
+        let tlwe = TLWE::new(sk);
+        let [x, y]: [bool; 2] = random_bool_initialization();
+        let expected = !(x && y);
+
+        let [bk_s, ks_s, enc_s, br_s, se_s, iks_s, dec_s, nand_s] = &mut stages[..] else {
+            unreachable!()
+        };
+        let bk = time(bk_s, || {
+            bootstrapping_key_with_layout(sk, params.bootstrapping_layout())
+        });
+        let ks = time(ks_s, || KeySwitchingKey::new(sk));
+        let cx = time(enc_s, || tlwe.encrypt(x));
+        let cy = tlwe.encrypt(y);
+
+        // homnand を段階ごとに測る
+        let c = CipherTLWELv0::clearly_true() - (cx + cy);
+        let acc = time(br_s, || blind_rotate(c, tv, &bk));
+        let lv1 = time(se_s, || sample_extract_index(acc, 0));
+        let lv0 = time(iks_s, || identity_key_switching_with_key(lv1, &ks));
+        let m = time(dec_s, || tlwe.decrypt(lv0));
+        gate_errors += (m != expected) as usize;
+
+        let ck = CloudKey { bk, ks };
+        let c = time(nand_s, || ck.nand(&cx, &cy));
+        gate_errors += (tlwe.decrypt(c) != expected) as usize;
+    }
+    let gates = 2 * iterations;
+
+    if args.flag("json") {
+        let us = |d: Duration| d.as_secs_f64() * 1e6;
+        let stages: Vec<_> = stages
+            .iter()
+            .map(|s| {
+                json!({
+                    "name": s.name,
+                    "mean_us": us(s.mean()),
+                    "p50_us": us(s.percentile(50.)),
+                    "p99_us": us(s.percentile(99.)),
+                })
+            })
+            .collect();
+        let report = json!({
+            "params": params.name(),
+            "iterations": iterations,
+            "stages": stages,
+            "gates": gates,
+            "gate_errors": gate_errors,
+        });
+        println!("{}", report);
+        return Ok(());
+    }
+
+    println!("params: {}, iterations: {}", params.name(), iterations);
+    println!("{:<24} {:>12} {:>12} {:>12}", "stage", "mean", "p50", "p99");
+    for s in stages.iter() {
+        println!(
+            "{:<24} {:>12.2?} {:>12.2?} {:>12.2?}",
+            s.name,
+            s.mean(),
+            s.percentile(50.),
+            s.percentile(99.)
+        );
+    }
+    println!("gate errors: {} / {}", gate_errors, gates);
+    Ok(())
+}
+
+#[test]
+fn test_percentile() {
+    let stage = Stage {
+        name: "test",
+        samples: (1..=100).rev().map(Duration::from_millis).collect(),
+    };
+    assert_eq!(stage.mean(), Duration::from_micros(50_500));
+    assert_eq!(stage.percentile(50.), Duration::from_millis(50));
+    assert_eq!(stage.percentile(99.), Duration::from_millis(99));
+    assert_eq!(stage.percentile(100.), Duration::from_millis(100));
+    assert_eq!(stage.percentile(0.), Duration::from_millis(1));
+}
@@ -0,0 +1,249 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+
+use kfhe::bundle::{CiphertextBundle, Encoding, Plaintext};
+use kfhe::key::SecretKey;
+use kfhe::serialize::{decode_bundle, encode_bundle, Header};
+use kfhe::stream::{encrypt_bytes, StreamHeader};
+use kfhe::tlwe::TLWE;
+
+use super::{
+    create_stream, error, is_stream, load_secret_key, open_stream, read_file, write_file, Args,
+    CliResult,
+};
+
+fn parse_bits(values: &[&str]) -> Result<Vec<bool>, Box<dyn std::error::Error>> {
+    let mut bits = Vec::new();
+    for v in values {
+        for ch in v.chars() {
+            match ch {
+                '0' => bits.push(false),
+                '1' => bits.push(true),
+                _ => return error(format!("not a bit: {}", ch)),
+            }
+        }
+    }
+    Ok(bits)
+}
+
+fn parse_plaintext(args: &Args) -> Result<Plaintext, Box<dyn std::error::Error>> {
+    let given: Vec<&str> = ["bits", "int", "file"]
+        .iter()
+        .copied()
+        .filter(|name| args.flag(name))
+        .collect();
+    match given[..] {
+        ["bits"] => Ok(Plaintext::Bits(parse_bits(&args.values("bits"))?)),
+        ["int"] => {
+            let width = match args.value("width")? {
+                Some(w) => w.parse::<usize>().ok().filter(|w| (1..=64).contains(w)),
+                None => Some(8),
+            };
+            let width = match width {
+                Some(w) => w,
+                None => return error("--width must be between 1 and 64"),
+            };
+            let mut values = Vec::new();
+            for v in args.values("int") {
+                match v.parse::<u64>() {
+                    Ok(x) if width == 64 || x >> width == 0 => values.push(x),
+                    Ok(_) => return error(format!("{} does not fit in {} bits", v, width)),
+                    Err(_) => return error(format!("not an integer: {}", v)),
+                }
+            }
+            Ok(Plaintext::Integers { width, values })
+        }
+        ["file"] => Ok(Plaintext::Bytes(read_file(args.required("file")?)?)),
+        _ => error("give exactly one of --bits, --int or --file"),
+    }
+}
+
+// --file はバイト列のまま読みながら暗号化する
+fn encrypt_stream(args: &Args, key: &Header, sk: SecretKey, out: &str) -> CliResult {
+    let tlwe = TLWE::new(sk);
+    let chunk_len = args.chunk_len()?;
+    let header = |encoding| StreamHeader::new(key.params, key.key_id, encoding, chunk_len);
+    let w = if args.flag("file") && !args.flag("bits") && !args.flag("int") {
+        let path = args.required("file")?;
+        let file = File::open(path).map_err(|e| format!("{}: {}", path, e))?;
+        let mut w = create_stream(out, header(Encoding::Bytes))?;
+        encrypt_bytes(BufReader::new(file), &tlwe, &mut w)
+            .map_err(|e| format!("{} -> {}: {}", path, out, e))?;
+        w
+    } else {
+        let plaintext = parse_plaintext(args)?;
+        let mut w = create_stream(out, header(plaintext.encoding()))?;
+        w.extend(plaintext.to_bits().into_iter().map(|b| tlwe.encrypt(b)))
+            .map_err(|e| format!("{}: {}", out, e))?;
+        w
+    };
+    let (n, chunk_len) = (w.len(), w.header().chunk_len);
+    w.finish().map_err(|e| format!("{}: {}", out, e))?;
+    println!("{}: {} ciphertexts in chunks of {}", out, n, chunk_len);
+    Ok(())
+}
+
+pub fn encrypt(args: &Args) -> CliResult {
+    let (header, sk) = load_secret_key(args.required("key")?)?;
+    let out = args.required("out")?;
+    if args.flag("stream") {
+        return encrypt_stream(args, &header, sk, out);
+    }
+    let plaintext = parse_plaintext(args)?;
+
+    let bundle = CiphertextBundle::encrypt(&plaintext, &TLWE::new(sk));
+    write_file(out, &encode_bundle(&bundle, header.params, header.key_id))?;
+    println!("{}: {} ciphertexts", out, bundle.len());
+    Ok(())
+}
+
+// バイト列は 16 進で、整数は空白区切りで書く
+fn plaintext_text(plaintext: &Plaintext) -> String {
+    match plaintext {
+        Plaintext::Bytes(bytes) => bytes.iter().map(|b| format!("{:02x}", b)).collect(),
+        Plaintext::Bits(bits) => bits.iter().map(|&b| if b { '1' } else { '0' }).collect(),
+        Plaintext::Integers { values, .. } => values
+            .iter()
+            .map(|v| v.to_string())
+            .collect::<Vec<_>>()
+            .join(" "),
+    }
+}
+
+// チャンクごとに復号して書き出す
+fn decrypt_stream(args: &Args, key: &Header, sk: SecretKey, path: &str) -> CliResult {
+    let mut reader = open_stream(path)?;
+    if reader.header().key_id != key.key_id {
+        return error(format!("{} was encrypted under a different key", path));
+    }
+    let tlwe = TLWE::new(sk);
+    let encoding = reader.header().encoding;
+    let out_path = args.value("out")?;
+    let mut out: Box<dyn Write> = match out_path {
+        Some(p) => Box::new(BufWriter::new(
+            File::create(p).map_err(|e| format!("{}: {}", p, e))?,
+        )),
+        None => Box::new(io::stdout().lock()),
+    };
+    let raw = out_path.is_some() && encoding == Encoding::Bytes;
+
+    let mut first = true;
+    while let Some(cs) = reader
+        .next_chunk()
+        .map_err(|e| format!("{}: {}", path, e))?
+    {
+        let bits: Vec<bool> = cs.iter().map(|&c| tlwe.decrypt(c)).collect();
+        match Plaintext::from_bits(encoding, &bits) {
+            Plaintext::Bytes(bytes) if raw => out.write_all(&bytes)?,
+            plaintext => {
+                if !first && matches!(encoding, Encoding::Integers { .. }) {
+                    write!(out, " ")?;
+                }
+                write!(out, "{}", plaintext_text(&plaintext))?;
+            }
+        }
+        first = false;
+    }
+    if !raw {
+        writeln!(out)?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+pub fn decrypt(args: &Args) -> CliResult {
+    let (key, sk) = load_secret_key(args.required("key")?)?;
+    let path = args.required("in")?;
+    if is_stream(path)? {
+        return decrypt_stream(args, &key, sk, path);
+    }
+    let (header, bundle) = decode_bundle(&read_file(path)?)?;
+    if header.key_id != key.key_id {
+        return error(format!("{} was encrypted under a different key", path));
+    }
+
+    let text = match bundle.decrypt(&TLWE::new(sk)) {
+        Plaintext::Bytes(bytes) if args.flag("out") => {
+            return write_file(args.required("out")?, &bytes)
+        }
+        plaintext => plaintext_text(&plaintext),
+    };
+    match args.value("out")? {
+        Some(out) => write_file(out, format!("{}\n", text).as_bytes()),
+        None => {
+            println!("{}", text);
+            Ok(())
+        }
+    }
+}
+
+#[test]
+fn test_encrypt_decrypt_file() {
+    use super::strings;
+    use kfhe::params::ParamSet;
+    use kfhe::serialize::{encode_secret_key, new_key_id};
+
+    let dir = std::env::temp_dir().join(format!("kfhe-test-encrypt-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+    let (sk_path, other_path) = (path("sk.bin"), path("other.bin"));
+    let (data, ct, out) = (path("data"), path("data.ct"), path("data.out"));
+
+    let params = ParamSet::Ternary;
+    let sk = params.secret_key();
+    std::fs::write(&sk_path, encode_secret_key(&sk, params, new_key_id())).unwrap();
+    std::fs::write(&data, b"hello, kfhe").unwrap();
+
+    let args = ["encrypt", "--key", &sk_path, "--file", &data, "--out", &ct];
+    super::run(&strings(&args)).unwrap();
+    let args = ["decrypt", "--key", &sk_path, "--in", &ct, "--out", &out];
+    super::run(&strings(&args)).unwrap();
+    assert_eq!(std::fs::read(&out).unwrap(), b"hello, kfhe");
+
+    // ストリームでも同じ
+    let args = [
+        "encrypt", "--key", &sk_path, "--file", &data, "--out", &ct, "--stream", "--chunk", "16",
+    ];
+    super::run(&strings(&args)).unwrap();
+    std::fs::remove_file(&out).unwrap();
+    let args = ["decrypt", "--key", &sk_path, "--in", &ct, "--out", &out];
+    super::run(&strings(&args)).unwrap();
+    assert_eq!(std::fs::read(&out).unwrap(), b"hello, kfhe");
+    let args = [
+        "encrypt", "--key", &sk_path, "--int", "1", "2", "3", "--width", "4",
+    ];
+    let args = [&args[..], &["--out", &ct, "--stream", "--chunk", "8"]].concat();
+    super::run(&strings(&args)).unwrap();
+    let args = ["decrypt", "--key", &sk_path, "--in", &ct, "--out", &out];
+    super::run(&strings(&args)).unwrap();
+    assert_eq!(std::fs::read(&out).unwrap(), b"1 2 3\n");
+
+    // 別の鍵では復号しない
+    let other = params.secret_key();
+    std::fs::write(&other_path, encode_secret_key(&other, params, new_key_id())).unwrap();
+    let args = ["decrypt", "--key", &other_path, "--in", &ct];
+    assert!(super::run(&strings(&args)).is_err());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_parse_plaintext() {
+    let parse = |args: &[&str]| parse_plaintext(&Args::parse(&super::strings(args)));
+
+    assert_eq!(
+        parse(&["--bits", "10", "1"]).unwrap(),
+        Plaintext::Bits(vec![true, false, true])
+    );
+    assert_eq!(
+        parse(&["--int", "3", "255", "--width", "8"]).unwrap(),
+        Plaintext::Integers {
+            width: 8,
+            values: vec![3, 255]
+        }
+    );
+    assert!(parse(&["--int", "256"]).is_err());
+    assert!(parse(&["--bits", "102"]).is_err());
+    assert!(parse(&["--bits", "1", "--int", "1"]).is_err());
+    assert!(parse(&[]).is_err());
+}
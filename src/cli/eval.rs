@@ -0,0 +1,388 @@
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Instant;
+
+use kfhe::bristol::parse_bristol;
+use kfhe::bundle::{CiphertextBundle, Encoding};
+use kfhe::circuit::{Netlist, Port};
+use kfhe::key::CloudKey;
+use kfhe::optimizer::optimize;
+use kfhe::scheduler::Scheduler;
+use kfhe::serialize::{decode_bundle, encode_bundle, Header};
+use kfhe::stream::{evaluate, StreamHeader};
+use kfhe::tlwe::CipherTLWELv0;
+use kfhe::yosys::parse_yosys_json;
+
+use super::{
+    create_stream, error, is_stream, load_cloud_key, open_stream, read_file, write_file, Args,
+    CliResult,
+};
+
+type Error = Box<dyn std::error::Error>;
+
+pub fn load_netlist(
+    path: &str,
+    format: Option<&str>,
+    module: Option<&str>,
+) -> Result<Netlist, Error> {
+    let format = match format {
+        Some(f) => f,
+        // 拡張子で判断する
+        None if Path::new(path).extension().is_some_and(|e| e == "json") => "yosys",
+        None => "bristol",
+    };
+    let bytes = read_file(path)?;
+    let text = match String::from_utf8(bytes) {
+        Ok(t) => t,
+        Err(_) => return error(format!("{}: not a text file", path)),
+    };
+    let netlist = match format {
+        "yosys" => parse_yosys_json(&text, module).map_err(|e| format!("{}: {}", path, e))?,
+        "bristol" => parse_bristol(&text).map_err(|e| format!("{}: {}", path, e))?,
+        _ => return error(format!("unknown circuit format: {}", format)),
+    };
+    Ok(netlist)
+}
+
+// "name=file" なら名前で、"file" なら順番でポートに割り当てる
+fn assign<'a>(ports: &[Port], files: &[&'a str], what: &str) -> Result<Vec<&'a str>, Error> {
+    if files.iter().all(|f| !f.contains('=')) {
+        if files.len() != ports.len() {
+            return error(format!(
+                "circuit has {} {} ports, but {} files are given",
+                ports.len(),
+                what,
+                files.len()
+            ));
+        }
+        return Ok(files.to_vec());
+    }
+
+    let mut assigned: Vec<Option<&str>> = vec![None; ports.len()];
+    for f in files {
+        let (name, file) = match f.split_once('=') {
+            Some(x) => x,
+            None => return error(format!("mix of named and unnamed {} files", what)),
+        };
+        match ports.iter().position(|p| p.name == name) {
+            Some(i) => assigned[i] = Some(file),
+            None => return error(format!("no {} port named {}", what, name)),
+        }
+    }
+    ports
+        .iter()
+        .zip(assigned)
+        .map(|(p, f)| match f {
+            Some(f) => Ok(f),
+            None => error(format!("no file for {} port {}", what, p.name)),
+        })
+        .collect()
+}
+
+// 入力ポートをすべて並べた幅ごとにストリームを区切り、順に評価する
+fn run_stream(
+    args: &Args,
+    ck_header: Header,
+    ck: CloudKey,
+    netlist: &Netlist,
+    file: &str,
+) -> CliResult {
+    let out = match args.values("out")[..] {
+        [out] if !out.contains('=') => out,
+        _ => return error("a stream input needs a single --out file"),
+    };
+    let mut input = open_stream(file)?;
+    if input.header().key_id != ck_header.key_id {
+        return error(format!("{} was encrypted under a different key", file));
+    }
+
+    let (circuit, report) = optimize(&netlist.circuit);
+    let (width, out_width) = (circuit.inputs().len(), circuit.outputs().len());
+    println!(
+        "circuit: {} inputs, {} outputs, {}",
+        width, out_width, report
+    );
+    if width == 0 {
+        return error("the circuit has no inputs");
+    }
+
+    let header = StreamHeader::new(
+        ck_header.params,
+        ck_header.key_id,
+        Encoding::for_width(out_width),
+        input.header().chunk_len,
+    );
+    let mut output = create_stream(out, header)?;
+    let threads = args.threads()?;
+    let scheduler = Scheduler::new(Arc::new(ck), threads);
+    let start = Instant::now();
+    let blocks = evaluate(&scheduler, &circuit, &mut input, &mut output)
+        .map_err(|e| format!("{} -> {}: {}", file, out, e))?;
+    let n = output.len();
+    output.finish().map_err(|e| format!("{}: {}", out, e))?;
+    println!(
+        "evaluated {} blocks on {} threads in {:.2?}",
+        blocks,
+        threads,
+        start.elapsed()
+    );
+    println!("{}: {} ciphertexts", out, n);
+    Ok(())
+}
+
+pub fn run(args: &Args) -> CliResult {
+    let (ck_header, ck) = load_cloud_key(args.required("cloud")?)?;
+    let netlist = load_netlist(
+        args.required("circuit")?,
+        args.value("format")?,
+        args.value("module")?,
+    )?;
+    if let [file] = args.values("in")[..] {
+        if !file.contains('=') && is_stream(file)? {
+            return run_stream(args, ck_header, ck, &netlist, file);
+        }
+    }
+    let threads = args.threads()?;
+
+    // 入力を集める
+    let mut inputs: Vec<CipherTLWELv0> = Vec::new();
+    let files = assign(&netlist.inputs, &args.values("in"), "input")?;
+    for (port, file) in netlist.inputs.iter().zip(files) {
+        let (header, bundle) =
+            decode_bundle(&read_file(file)?).map_err(|e| format!("{}: {}", file, e))?;
+        if header.key_id != ck_header.key_id {
+            return error(format!("{} was encrypted under a different key", file));
+        }
+        if bundle.len() != port.width {
+            return error(format!(
+                "input port {} is {} bits wide, but {} has {} ciphertexts",
+                port.name,
+                port.width,
+                file,
+                bundle.len()
+            ));
+        }
+        inputs.extend(bundle.ciphertexts);
+    }
+
+    let (circuit, report) = optimize(&netlist.circuit);
+    println!(
+        "circuit: {} inputs, {} outputs, {}",
+        circuit.inputs().len(),
+        circuit.outputs().len(),
+        report
+    );
+
+    let start = Instant::now();
+    let outputs = Scheduler::new(Arc::new(ck), threads).evaluate(&circuit, &inputs);
+    println!(
+        "evaluated on {} threads in {:.2?}",
+        threads,
+        start.elapsed()
+    );
+
+    // 出力ポートごとに書き出す. ファイルが 1 つならすべての出力をビット列として並べる
+    let out_files = args.values("out");
+    let write = |file: &str, bundle: &CiphertextBundle| -> CliResult {
+        write_file(
+            file,
+            &encode_bundle(bundle, ck_header.params, ck_header.key_id),
+        )?;
+        println!("{}: {} ciphertexts", file, bundle.len());
+        Ok(())
+    };
+    if out_files.len() == 1 && !out_files[0].contains('=') && netlist.outputs.len() != 1 {
+        let bundle = CiphertextBundle {
+            encoding: Encoding::Bits,
+            ciphertexts: outputs,
+        };
+        return write(out_files[0], &bundle);
+    }
+    let files = assign(&netlist.outputs, &out_files, "output")?;
+    let mut rest = &outputs[..];
+    for (port, file) in netlist.outputs.iter().zip(files) {
+        let (bits, tail) = rest.split_at(port.width);
+        rest = tail;
+        let bundle = CiphertextBundle {
+            encoding: Encoding::for_width(port.width),
+            ciphertexts: bits.to_vec(),
+        };
+        write(file, &bundle)?;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_assign() {
+    let port = |name: &str| Port {
+        name: name.to_string(),
+        width: 1,
+    };
+    let ports = vec![port("a"), port("b")];
+
+    assert_eq!(
+        assign(&ports, &["x", "y"], "input").unwrap(),
+        vec!["x", "y"]
+    );
+    assert_eq!(
+        assign(&ports, &["b=y", "a=x"], "input").unwrap(),
+        vec!["x", "y"]
+    );
+    assert!(assign(&ports, &["x"], "input").is_err());
+    assert!(assign(&ports, &["a=x", "y"], "input").is_err());
+    assert!(assign(&ports, &["a=x", "c=y"], "input").is_err());
+}
+
+#[test]
+fn test_eval_bristol() {
+    use super::strings;
+    use kfhe::bundle::Plaintext;
+    use kfhe::params::ParamSet;
+    use kfhe::serialize::{encode_cloud_key, new_key_id};
+    use kfhe::tlwe::TLWE;
+
+    let dir = std::env::temp_dir().join(format!("kfhe-test-eval-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+
+    let params = ParamSet::Binary;
+    let sk = params.secret_key();
+    let tlwe = TLWE::new(sk);
+    let ck = params.cloud_key(sk);
+    let key_id = new_key_id();
+    std::fs::write(path("ck.bin"), encode_cloud_key(&ck, params, key_id)).unwrap();
+
+    // 1 ビット入力 2 つの AND と XOR (2 ビット出力)
+    let circuit = "2 4\n2 1 1\n1 2\n2 1 0 1 2 AND\n2 1 0 1 3 XOR\n";
+    std::fs::write(path("c.txt"), circuit).unwrap();
+    for (name, b) in [("a.ct", true), ("b.ct", false)] {
+        let bundle = CiphertextBundle::encrypt(&Plaintext::Bits(vec![b]), &tlwe);
+        std::fs::write(path(name), encode_bundle(&bundle, params, key_id)).unwrap();
+    }
+
+    let (ck_path, c_path, out) = (path("ck.bin"), path("c.txt"), path("out.ct"));
+    let (a, b) = (path("a.ct"), path("b.ct"));
+    let args = [
+        "eval",
+        "--cloud",
+        &ck_path,
+        "--circuit",
+        &c_path,
+        "--in",
+        &a,
+        &b,
+        "--out",
+        &out,
+        "--threads",
+        "2",
+    ];
+    super::run(&strings(&args)).unwrap();
+
+    let (_, bundle) = decode_bundle(&std::fs::read(&out).unwrap()).unwrap();
+    assert_eq!(
+        bundle.decrypt(&tlwe),
+        Plaintext::Integers {
+            width: 2,
+            values: vec![0b10]
+        }
+    );
+
+    // ストリームなら 2 ビットずつ同じ回路に通す
+    let mut w = kfhe::stream::StreamWriter::new(
+        Vec::new(),
+        StreamHeader::new(params, key_id, Encoding::Bits, 4),
+    )
+    .unwrap();
+    w.extend([true, true, false, true].map(|b| tlwe.encrypt(b)))
+        .unwrap();
+    std::fs::write(path("in.st"), w.finish().unwrap()).unwrap();
+    let (input, out) = (path("in.st"), path("out.st"));
+    let args = [
+        "eval",
+        "--cloud",
+        &ck_path,
+        "--circuit",
+        &c_path,
+        "--in",
+        &input,
+        "--out",
+        &out,
+    ];
+    super::run(&strings(&args)).unwrap();
+    let reader = super::open_stream(&out).unwrap();
+    assert_eq!(reader.header().encoding, Encoding::Integers { width: 2 });
+    let bits: Vec<bool> = reader.map(|c| tlwe.decrypt(c.unwrap())).collect();
+    assert_eq!(bits, vec![true, false, false, true]);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_eval_yosys_port_order() {
+    use super::strings;
+    use kfhe::bundle::Plaintext;
+    use kfhe::params::ParamSet;
+    use kfhe::serialize::{encode_cloud_key, new_key_id};
+    use kfhe::tlwe::TLWE;
+
+    let dir = std::env::temp_dir().join(format!("kfhe-test-eval-order-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+
+    let params = ParamSet::Binary;
+    let sk = params.secret_key();
+    let tlwe = TLWE::new(sk);
+    let ck = params.cloud_key(sk);
+    let key_id = new_key_id();
+    std::fs::write(path("ck.bin"), encode_cloud_key(&ck, params, key_id)).unwrap();
+
+    // ポートは名前順でない. y = b & !a, x = !b
+    let circuit = r#"{
+      "modules": {
+        "m": {
+          "ports": {
+            "b": { "direction": "input", "bits": [ 2 ] },
+            "a": { "direction": "input", "bits": [ 3 ] },
+            "y": { "direction": "output", "bits": [ 4 ] },
+            "x": { "direction": "output", "bits": [ 5 ] }
+          },
+          "cells": {
+            "andnot": { "type": "$_ANDNOT_", "connections": { "A": [ 2 ], "B": [ 3 ], "Y": [ 4 ] } },
+            "not": { "type": "$_NOT_", "connections": { "A": [ 2 ], "Y": [ 5 ] } }
+          }
+        }
+      }
+    }"#;
+    std::fs::write(path("c.json"), circuit).unwrap();
+    for (name, b) in [("b.ct", true), ("a.ct", false)] {
+        let bundle = CiphertextBundle::encrypt(&Plaintext::Bits(vec![b]), &tlwe);
+        std::fs::write(path(name), encode_bundle(&bundle, params, key_id)).unwrap();
+    }
+
+    // ファイルは宣言された順 (b, a と y, x) に割り当てられる
+    let (ck_path, c_path) = (path("ck.bin"), path("c.json"));
+    let (b, a, y, x) = (path("b.ct"), path("a.ct"), path("y.ct"), path("x.ct"));
+    let args = [
+        "eval",
+        "--cloud",
+        &ck_path,
+        "--circuit",
+        &c_path,
+        "--in",
+        &b,
+        &a,
+        "--out",
+        &y,
+        &x,
+    ];
+    super::run(&strings(&args)).unwrap();
+
+    let decrypt = |file: &str| {
+        let (_, bundle) = decode_bundle(&std::fs::read(file).unwrap()).unwrap();
+        bundle.decrypt(&tlwe)
+    };
+    assert_eq!(decrypt(&y), Plaintext::Bits(vec![true]));
+    assert_eq!(decrypt(&x), Plaintext::Bits(vec![false]));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,83 @@
+use kfhe::serialize::{inspect, ObjectKind};
+
+use super::{error, format_bytes, read_file, Args, CliResult};
+
+pub fn run(args: &Args) -> CliResult {
+    if args.positional.is_empty() {
+        return error("usage: kfhe inspect <file>...");
+    }
+    let mut broken = Vec::new();
+    for (i, path) in args.positional.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        let bytes = read_file(path)?;
+        let info = inspect(&bytes).map_err(|e| format!("{}: {}", path, e))?;
+        let header = info.header;
+
+        println!("{}: {}", path, format_bytes(bytes.len()));
+        println!("  {:<16} {}", "format version", header.version);
+        println!("  {:<16} {}", "object", header.kind.name());
+        println!("  {:<16} {}", "params", header.params.name());
+        println!("  {:<16} {:016x}", "key id", header.key_id);
+        if header.kind == ObjectKind::CiphertextStream {
+            println!("  {:<16} chunked", "payload");
+        } else {
+            println!(
+                "  {:<16} {}",
+                "payload",
+                format_bytes(header.payload_len as usize)
+            );
+        }
+        match &info.checksum {
+            Ok(()) => println!("  {:<16} ok", "checksum"),
+            Err(e) => {
+                println!("  {:<16} {}", "checksum", e);
+                broken.push(path.as_str());
+            }
+        }
+        match info.contents {
+            Ok(contents) => {
+                for (name, value) in contents {
+                    println!("  {:<16} {}", name, value);
+                }
+            }
+            Err(e) => println!("  {:<16} {}", "contents", e),
+        }
+    }
+    if !broken.is_empty() {
+        return error(format!("corrupted: {}", broken.join(", ")));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_inspect_files() {
+    use super::strings;
+    use kfhe::bundle::{CiphertextBundle, Plaintext};
+    use kfhe::params::ParamSet;
+    use kfhe::serialize::{encode_bundle, new_key_id};
+    use kfhe::tlwe::TLWE;
+
+    let dir = std::env::temp_dir().join(format!("kfhe-test-inspect-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+
+    let params = ParamSet::Binary;
+    let sk = params.secret_key();
+    let bundle = CiphertextBundle::encrypt(&Plaintext::Bytes(b"ok".to_vec()), &TLWE::new(sk));
+    let bytes = encode_bundle(&bundle, params, new_key_id());
+    std::fs::write(path("good.ct"), &bytes).unwrap();
+    super::run(&strings(&["inspect", &path("good.ct")])).unwrap();
+
+    let mut bytes = bytes;
+    let last = bytes.len() - 1;
+    bytes[last] ^= 1;
+    std::fs::write(path("bad.ct"), &bytes).unwrap();
+    assert!(super::run(&strings(&["inspect", &path("good.ct"), &path("bad.ct")])).is_err());
+
+    std::fs::write(path("text"), "hello").unwrap();
+    assert!(super::run(&strings(&["inspect", &path("text")])).is_err());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
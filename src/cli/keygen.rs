@@ -0,0 +1,64 @@
+use std::mem::size_of;
+use std::time::Instant;
+
+use kfhe::bootstrapping::bootstrapping_key_with_layout;
+use kfhe::key::CloudKey;
+use kfhe::key_switching::KeySwitchingKey;
+use kfhe::serialize::{encode_cloud_key, encode_secret_key, new_key_id};
+use kfhe::tlwe::CipherTLWELv0;
+use kfhe::trgsw::TRGSWMatrix;
+
+use super::{format_bytes, write_file, write_secret_file, Args, CliResult};
+
+pub fn run(args: &Args) -> CliResult {
+    let params = args.params()?;
+    let out_secret = args.required("out-secret")?;
+    let out_cloud = args.required("out-cloud")?;
+
+    let start = Instant::now();
+    let sk = params.secret_key();
+
+    let t = Instant::now();
+    let bk = bootstrapping_key_with_layout(sk, params.bootstrapping_layout());
+    let bk_time = t.elapsed();
+
+    let t = Instant::now();
+    let ks = KeySwitchingKey::new(sk);
+    let ks_time = t.elapsed();
+
+    let ck = CloudKey { bk, ks };
+    let total = start.elapsed();
+
+    let key_id = new_key_id();
+    let sk_bytes = encode_secret_key(&sk, params, key_id);
+    let ck_bytes = encode_cloud_key(&ck, params, key_id);
+    write_secret_file(out_secret, &sk_bytes)?;
+    write_file(out_cloud, &ck_bytes)?;
+
+    println!("params: {}", params.name());
+    println!("key id: {:016x}", key_id);
+    println!(
+        "bootstrapping key: {} TRGSW ({}), {:.2?}",
+        ck.bk.0.len(),
+        format_bytes(ck.bk.0.len() * size_of::<TRGSWMatrix>()),
+        bk_time
+    );
+    println!(
+        "key-switching key: {} TLWE lv0 ({}), {:.2?}",
+        ck.ks.0.len(),
+        format_bytes(ck.ks.0.len() * size_of::<CipherTLWELv0>()),
+        ks_time
+    );
+    println!(
+        "secret key: {} ({})",
+        out_secret,
+        format_bytes(sk_bytes.len())
+    );
+    println!(
+        "cloud key: {} ({})",
+        out_cloud,
+        format_bytes(ck_bytes.len())
+    );
+    println!("total: {:.2?}", total);
+    Ok(())
+}
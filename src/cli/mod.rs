@@ -0,0 +1,312 @@
+// kfhe コマンドの各サブコマンド
+// 引数は "--name value..." の形だけを扱う簡単なもの. 位置引数はオプションより前に書く
+
+mod bench;
+mod encrypt;
+mod eval;
+mod inspect;
+mod keygen;
+mod params;
+mod repl;
+mod serve;
+
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::thread;
+
+use kfhe::key::{CloudKey, SecretKey};
+use kfhe::params::ParamSet;
+use kfhe::serialize::{
+    decode_cloud_key, decode_secret_key, read_header, Header, ObjectKind, HEADER_LEN,
+};
+use kfhe::stream::{StreamHeader, StreamReader, StreamWriter, DEFAULT_CHUNK_LEN};
+
+pub type CliResult = Result<(), Box<dyn Error>>;
+
+const USAGE: &str = "usage: kfhe <command> [options]
+
+commands:
+  keygen --params <set> --out-secret <file> --out-cloud <file>
+      generate a secret key and a cloud key (bootstrapping + key-switching key)
+  encrypt --key <secret> (--bits <01..> | --int <v>... [--width <w>] | --file <file>) --out <file>
+          [--stream [--chunk <n>]]
+      encrypt bits, integers (default width 8) or a byte file into a ciphertext bundle
+      (--stream writes a chunked stream instead, reading --file piece by piece)
+  decrypt --key <secret> --in <file> [--out <file>]
+      decrypt a ciphertext bundle or stream (bytes are printed as hex without --out)
+  eval --cloud <cloud> --circuit <file> --in <[port=]file>... --out <[port=]file>...
+       [--format yosys|bristol] [--module <name>] [--threads <n>]
+      evaluate a Yosys JSON or Bristol circuit on ciphertext bundles, one per port
+      (a single --out file receives all outputs as bits)
+      with a single stream --in, the circuit is applied to each block of input-width
+      ciphertexts in turn and all outputs go to a stream --out
+  bench [--params <set>] [--iterations <n>] [--json]
+      time each stage of key generation and gate bootstrapping, and count gate errors
+  inspect <file>...
+      show the format version, object type, parameter set, element counts, key id and
+      checksum status of key and ciphertext files (no key needed)
+  repl [--params <set>] [--key <secret> --cloud <cloud>]
+      interactive session: encrypt named variables and evaluate expressions such as
+      c = (a & b) ^ !d, showing cleartext, decryption, timing and noise per gate
+  serve --listen <host:port|unix:path> --circuit <[id=]file>... [--format yosys|bristol]
+        [--module <name>] [--threads <n>]
+      serve evaluation requests: clients upload a cloud key once per connection, then send
+      a circuit id and one ciphertext bundle per input port
+  params show [<set>] [--json]
+      print a parameter set with key and ciphertext sizes, estimated noise per stage,
+      failure probability per gate and estimated security
+
+parameter sets: binary (default), ternary, gaussian, bundled";
+
+#[derive(Debug)]
+pub struct CliError(pub String);
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Error for CliError {}
+
+pub fn error<T>(msg: impl Into<String>) -> Result<T, Box<dyn Error>> {
+    Err(Box::new(CliError(msg.into())))
+}
+
+#[derive(Debug, Default)]
+pub struct Args {
+    pub positional: Vec<String>,
+    options: Vec<(String, Vec<String>)>,
+}
+
+impl Args {
+    pub fn parse(args: &[String]) -> Self {
+        let mut parsed = Self::default();
+        for arg in args {
+            if let Some(name) = arg.strip_prefix("--") {
+                match name.split_once('=') {
+                    Some((name, value)) => parsed
+                        .options
+                        .push((name.to_string(), vec![value.to_string()])),
+                    None => parsed.options.push((name.to_string(), vec![])),
+                }
+            } else {
+                match parsed.options.last_mut() {
+                    Some((_, values)) => values.push(arg.clone()),
+                    None => parsed.positional.push(arg.clone()),
+                }
+            }
+        }
+        parsed
+    }
+
+    pub fn flag(&self, name: &str) -> bool {
+        self.options.iter().any(|(n, _)| n == name)
+    }
+
+    // 同じオプションを何度書いても、値は順にまとめて返す
+    pub fn values(&self, name: &str) -> Vec<&str> {
+        self.options
+            .iter()
+            .filter(|(n, _)| n == name)
+            .flat_map(|(_, vs)| vs.iter().map(|v| v.as_str()))
+            .collect()
+    }
+
+    pub fn value(&self, name: &str) -> Result<Option<&str>, Box<dyn Error>> {
+        match self.values(name)[..] {
+            [] if self.flag(name) => error(format!("--{} needs a value", name)),
+            [] => Ok(None),
+            [v] => Ok(Some(v)),
+            _ => error(format!("--{} takes a single value", name)),
+        }
+    }
+
+    pub fn required(&self, name: &str) -> Result<&str, Box<dyn Error>> {
+        match self.value(name)? {
+            Some(v) => Ok(v),
+            None => error(format!("missing --{}", name)),
+        }
+    }
+
+    pub fn params(&self) -> Result<ParamSet, Box<dyn Error>> {
+        match self.value("params")? {
+            None => Ok(ParamSet::default()),
+            Some(name) => match ParamSet::from_name(name) {
+                Some(p) => Ok(p),
+                None => error(format!("unknown parameter set: {}", name)),
+            },
+        }
+    }
+
+    // ストリームの 1 チャンクの暗号文の数
+    pub fn chunk_len(&self) -> Result<usize, Box<dyn Error>> {
+        match self.value("chunk")? {
+            Some(n) => match n.parse::<usize>() {
+                Ok(n) if n > 0 => Ok(n),
+                _ => error(format!("invalid chunk length: {}", n)),
+            },
+            None => Ok(DEFAULT_CHUNK_LEN),
+        }
+    }
+
+    // 指定がなければ使えるだけ使う
+    pub fn threads(&self) -> Result<usize, Box<dyn Error>> {
+        match self.value("threads")? {
+            Some(t) => match t.parse::<usize>() {
+                Ok(t) if t > 0 => Ok(t),
+                _ => error(format!("invalid number of threads: {}", t)),
+            },
+            None => Ok(thread::available_parallelism().map_or(1, |n| n.get())),
+        }
+    }
+}
+
+pub fn read_file(path: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    fs::read(path).map_err(|e| CliError(format!("{}: {}", path, e)).into())
+}
+
+pub fn write_file(path: &str, bytes: &[u8]) -> CliResult {
+    fs::write(path, bytes).map_err(|e| CliError(format!("{}: {}", path, e)).into())
+}
+
+// 秘密鍵は所有者だけが読み書きできるように作る. 既にあるファイルも書き込む前に権限を絞る
+pub fn write_secret_file(path: &str, bytes: &[u8]) -> CliResult {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let write = || -> std::io::Result<()> {
+        let mut file = options.open(path)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            file.set_permissions(fs::Permissions::from_mode(0o600))?;
+        }
+        file.write_all(bytes)
+    };
+    write().map_err(|e| CliError(format!("{}: {}", path, e)).into())
+}
+
+// ストリームは全体を読み込まずに扱う
+pub fn is_stream(path: &str) -> Result<bool, Box<dyn Error>> {
+    let file = File::open(path).map_err(|e| CliError(format!("{}: {}", path, e)))?;
+    let mut buf = Vec::with_capacity(HEADER_LEN);
+    file.take(HEADER_LEN as u64).read_to_end(&mut buf)?;
+    Ok(read_header(&buf).is_ok_and(|h| h.kind == ObjectKind::CiphertextStream))
+}
+
+pub fn open_stream(path: &str) -> Result<StreamReader<BufReader<File>>, Box<dyn Error>> {
+    let file = File::open(path).map_err(|e| CliError(format!("{}: {}", path, e)))?;
+    StreamReader::new(BufReader::new(file)).map_err(|e| CliError(format!("{}: {}", path, e)).into())
+}
+
+pub fn create_stream(
+    path: &str,
+    header: StreamHeader,
+) -> Result<StreamWriter<BufWriter<File>>, Box<dyn Error>> {
+    let file = File::create(path).map_err(|e| CliError(format!("{}: {}", path, e)))?;
+    StreamWriter::new(BufWriter::new(file), header)
+        .map_err(|e| CliError(format!("{}: {}", path, e)).into())
+}
+
+pub fn load_secret_key(path: &str) -> Result<(Header, SecretKey), Box<dyn Error>> {
+    decode_secret_key(&read_file(path)?).map_err(|e| CliError(format!("{}: {}", path, e)).into())
+}
+
+pub fn load_cloud_key(path: &str) -> Result<(Header, CloudKey), Box<dyn Error>> {
+    decode_cloud_key(&read_file(path)?).map_err(|e| CliError(format!("{}: {}", path, e)).into())
+}
+
+pub fn format_bytes(n: usize) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut x = n as f64;
+    let mut unit = 0;
+    while x >= 1024. && unit + 1 < UNITS.len() {
+        x /= 1024.;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", n)
+    } else {
+        format!("{:.1} {}", x, UNITS[unit])
+    }
+}
+
+pub fn run(args: &[String]) -> CliResult {
+    let (command, rest) = match args.split_first() {
+        Some((c, rest)) => (c.as_str(), rest),
+        None => {
+            println!("{}", USAGE);
+            return Ok(());
+        }
+    };
+    let args = Args::parse(rest);
+    match command {
+        "keygen" => keygen::run(&args),
+        "encrypt" => encrypt::encrypt(&args),
+        "decrypt" => encrypt::decrypt(&args),
+        "eval" => eval::run(&args),
+        "bench" => bench::run(&args),
+        "params" => params::run(&args),
+        "serve" => serve::run(&args),
+        "inspect" => inspect::run(&args),
+        "repl" => repl::run(&args),
+        "help" | "--help" | "-h" => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        _ => error(format!("unknown command: {}\n\n{}", command, USAGE)),
+    }
+}
+
+#[cfg(test)]
+pub fn strings(args: &[&str]) -> Vec<String> {
+    args.iter().map(|s| s.to_string()).collect()
+}
+
+#[test]
+fn test_args() {
+    let args = ["file", "--in", "a.ct", "b.ct", "--threads=4", "--json"];
+    let args = Args::parse(&strings(&args));
+
+    assert_eq!(args.positional, vec!["file"]);
+    assert_eq!(args.values("in"), vec!["a.ct", "b.ct"]);
+    assert_eq!(args.value("threads").unwrap(), Some("4"));
+    assert!(args.flag("json"));
+    assert!(!args.flag("out"));
+    assert!(args.value("in").is_err());
+    assert!(args.value("json").is_err());
+    assert!(args.required("out").is_err());
+    assert_eq!(args.params().unwrap(), ParamSet::Binary);
+}
+
+#[test]
+fn test_format_bytes() {
+    assert_eq!(format_bytes(10), "10 B");
+    assert_eq!(format_bytes(1536), "1.5 KiB");
+    assert_eq!(format_bytes(31_211_520), "29.8 MiB");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_write_secret_file() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let path = std::env::temp_dir().join(format!("kfhe-test-secret-{}", std::process::id()));
+    let path = path.to_str().unwrap();
+    // 誰でも読めるファイルを上書きしても権限が絞られる
+    fs::write(path, b"old").unwrap();
+    fs::set_permissions(path, fs::Permissions::from_mode(0o644)).unwrap();
+    write_secret_file(path, b"secret").unwrap();
+
+    assert_eq!(fs::read(path).unwrap(), b"secret");
+    let mode = fs::metadata(path).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+    fs::remove_file(path).unwrap();
+}
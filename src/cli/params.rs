@@ -0,0 +1,225 @@
+use serde_json::{json, Value};
+
+use kfhe::estimate::{failure_probability, noise, security, sizes};
+use kfhe::params::{tlwe, trgsw, trlwe, ParamSet};
+
+use super::{error, format_bytes, Args, CliResult};
+
+// 分散を log2 の標準偏差で表す
+fn log2_stddev(v: f64) -> f64 {
+    0.5 * v.log2()
+}
+
+fn name(x: &dyn std::fmt::Debug) -> String {
+    format!("{:?}", x).to_lowercase()
+}
+
+pub fn run(args: &Args) -> CliResult {
+    let params = match args
+        .positional
+        .iter()
+        .map(|s| s.as_str())
+        .collect::<Vec<_>>()[..]
+    {
+        ["show"] => ParamSet::default(),
+        ["show", name] => match ParamSet::from_name(name) {
+            Some(p) => p,
+            None => return error(format!("unknown parameter set: {}", name)),
+        },
+        _ => return error("usage: kfhe params show [<set>] [--json]"),
+    };
+    if args.flag("json") {
+        println!("{}", report_json(params));
+    } else {
+        print!("{}", report_text(params));
+    }
+    Ok(())
+}
+
+fn report_json(params: ParamSet) -> Value {
+    let (lv0_dist, lv1_dist) = params.key_distribution();
+    let layout = params.bootstrapping_layout();
+    let sizes = sizes(params);
+    let noise = noise(params);
+    let failure = failure_probability(params);
+    let security = security(params);
+
+    json!({
+        "params": params.name(),
+        "tlwe": {
+            "n": tlwe::N,
+            "alpha": tlwe::ALPHA,
+            "key_distribution": name(&lv0_dist),
+        },
+        "trlwe": {
+            "N": trlwe::N,
+            "alpha": trlwe::ALPHA,
+            "key_distribution": name(&lv1_dist),
+        },
+        "trgsw": {
+            "L": trgsw::L,
+            "Bgbit": trgsw::BGBIT,
+            "Bg": trgsw::BG,
+        },
+        "key_switching": {
+            "t": trgsw::T,
+            "basebit": trgsw::BASEBIT,
+        },
+        "bootstrapping_layout": name(&layout),
+        "sizes": {
+            "tlwe_lv0": sizes.tlwe_lv0,
+            "tlwe_lv1": sizes.tlwe_lv1,
+            "trlwe": sizes.trlwe,
+            "trgsw": sizes.trgsw,
+            "secret_key": sizes.secret_key,
+            "bootstrapping_key": sizes.bootstrapping_key,
+            "bootstrapping_key_entries": sizes.bootstrapping_key_entries,
+            "key_switching_key": sizes.key_switching_key,
+            "key_switching_key_entries": sizes.key_switching_key_entries,
+        },
+        "noise_variance": {
+            "fresh": noise.fresh,
+            "modulus_switching": noise.modulus_switching,
+            "blind_rotation": noise.blind_rotation,
+            "key_switching": noise.key_switching,
+            "bootstrapped": noise.bootstrapped,
+        },
+        "failure_probability": {
+            "nand": failure.nand,
+            "xor": failure.xor,
+        },
+        "security_bits": {
+            "tlwe": security.lv0,
+            "trlwe": security.lv1,
+            "min": security.bits(),
+        },
+    })
+}
+
+fn report_text(params: ParamSet) -> String {
+    let (lv0_dist, lv1_dist) = params.key_distribution();
+    let layout = params.bootstrapping_layout();
+    let sizes = sizes(params);
+    let noise = noise(params);
+    let failure = failure_probability(params);
+    let security = security(params);
+
+    let mut lines = Vec::new();
+    lines.push(format!("params: {}", params.name()));
+    lines.push(String::new());
+    lines.push(format!(
+        "TLWE lv0      n = {}, alpha = 2^{:.1}, key: {}",
+        tlwe::N,
+        tlwe::ALPHA.log2(),
+        name(&lv0_dist)
+    ));
+    lines.push(format!(
+        "TRLWE         N = {}, alpha = 2^{:.1}, key: {}",
+        trlwe::N,
+        trlwe::ALPHA.log2(),
+        name(&lv1_dist)
+    ));
+    lines.push(format!(
+        "TRGSW         L = {}, Bg = 2^{}",
+        trgsw::L,
+        trgsw::BGBIT
+    ));
+    lines.push(format!(
+        "key switching t = {}, basebit = {}",
+        trgsw::T,
+        trgsw::BASEBIT
+    ));
+    lines.push(format!("bootstrapping key layout: {}", name(&layout)));
+    lines.push(String::new());
+
+    lines.push("sizes:".to_string());
+    let size = |label: &str, n: usize| format!("  {:<20} {:>12}", label, format_bytes(n));
+    lines.push(size("TLWE lv0", sizes.tlwe_lv0));
+    lines.push(size("TLWE lv1", sizes.tlwe_lv1));
+    lines.push(size("TRLWE", sizes.trlwe));
+    lines.push(size("TRGSW", sizes.trgsw));
+    lines.push(size("secret key", sizes.secret_key));
+    lines.push(format!(
+        "{}  ({} TRGSW)",
+        size("bootstrapping key", sizes.bootstrapping_key),
+        sizes.bootstrapping_key_entries
+    ));
+    lines.push(format!(
+        "{}  ({} TLWE lv0)",
+        size("key switching key", sizes.key_switching_key),
+        sizes.key_switching_key_entries
+    ));
+    lines.push(String::new());
+
+    lines.push("noise (stddev on the torus):".to_string());
+    let stage = |label: &str, v: f64| format!("  {:<20} 2^{:.2}", label, log2_stddev(v));
+    lines.push(stage("fresh", noise.fresh));
+    lines.push(stage("modulus switching", noise.modulus_switching));
+    lines.push(stage("blind rotation", noise.blind_rotation));
+    lines.push(stage("key switching", noise.key_switching));
+    lines.push(stage("bootstrapped", noise.bootstrapped));
+    lines.push(String::new());
+
+    lines.push("failure probability per gate:".to_string());
+    lines.push(format!(
+        "  {:<20} 2^{:.1}",
+        "NAND/AND/OR/...",
+        failure.nand.log2()
+    ));
+    lines.push(format!("  {:<20} 2^{:.1}", "XOR/XNOR", failure.xor.log2()));
+    lines.push(String::new());
+
+    lines.push("estimated security (primal uSVP, core-SVP):".to_string());
+    lines.push(format!("  {:<20} {:.0} bits", "TLWE lv0", security.lv0));
+    lines.push(format!("  {:<20} {:.0} bits", "TRLWE", security.lv1));
+    lines.push(String::new());
+    lines.join("\n")
+}
+
+#[test]
+fn test_params_show() {
+    use super::strings;
+    use kfhe::bootstrapping::BootstrappingKeyLayout;
+    use kfhe::trgsw::TRGSWMatrix;
+    use std::mem::size_of;
+
+    // 鍵切り替え鍵は (K - 1) T N 個 (K = 2^basebit)
+    let ks_entries = ((1 << trgsw::BASEBIT) - 1) * trgsw::T * trlwe::N;
+
+    for params in ParamSet::ALL {
+        let report = report_json(params);
+        assert_eq!(report["params"], params.name());
+        assert_eq!(report["sizes"]["key_switching_key_entries"], ks_entries);
+
+        let bk_entries = match params.bootstrapping_layout() {
+            BootstrappingKeyLayout::Binary => tlwe::N,
+            BootstrappingKeyLayout::Ternary => 2 * tlwe::N,
+            BootstrappingKeyLayout::Bundled => 3 * (tlwe::N / 2) + tlwe::N % 2,
+        };
+        assert_eq!(report["sizes"]["bootstrapping_key_entries"], bk_entries);
+        assert_eq!(
+            report["sizes"]["bootstrapping_key"],
+            bk_entries * size_of::<TRGSWMatrix>()
+        );
+
+        // 有限でない値は JSON では null になる
+        for gate in ["nand", "xor"] {
+            let p = report["failure_probability"][gate].as_f64();
+            assert!(p.is_some_and(|p| (0.0..1.0).contains(&p)), "{}", gate);
+        }
+        for level in ["tlwe", "trlwe", "min"] {
+            let bits = report["security_bits"][level].as_f64();
+            assert!(bits.is_some_and(|b| b.is_finite() && b > 0.), "{}", level);
+        }
+
+        let text = report_text(params);
+        assert!(text.contains(&format!("({} TRGSW)", bk_entries)));
+        assert!(text.contains(&format!("({} TLWE lv0)", ks_entries)));
+        assert!(text.contains("failure probability per gate:"));
+        assert!(text.contains("estimated security"));
+
+        super::run(&strings(&["params", "show", params.name(), "--json"])).unwrap();
+    }
+    assert!(super::run(&strings(&["params", "show", "unknown"])).is_err());
+    assert!(super::run(&strings(&["params"])).is_err());
+}
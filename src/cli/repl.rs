@@ -0,0 +1,506 @@
+// 対話的に暗号化と評価を試すためのもの
+//   a = 1              a を新しく暗号化する
+//   c = (a & b) ^ !d   式を評価して c に入れる (ゲートごとに結果と時間とノイズを表示する)
+//   nand(a, b)         式だけなら評価して表示する
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::time::{Duration, Instant};
+
+use kfhe::gates::{Cleartext, Gates};
+use kfhe::key::CloudKey;
+use kfhe::params::ParamSet;
+use kfhe::tlwe::{CipherTLWELv0, TLWE};
+use kfhe::util::{bool_normalization, float_to_torus};
+
+use super::{error, load_cloud_key, load_secret_key, Args, CliResult};
+
+const HELP: &str = "  <name> = 0|1        encrypt a fresh variable
+  <name> = <expr>     evaluate an expression and store the result
+  <expr>              evaluate an expression
+  expressions: a & b, a | b, a ^ b, !a (or ~a), (...), 0, 1,
+               nand(a, b), andny(a, b), oryn(a, b), mux(c, a, b)
+  :vars               list variables
+  :help               show this help
+  :quit               exit";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Op {
+    Not,
+    And,
+    Or,
+    Xor,
+    Nand,
+    AndNY,
+    OrYN,
+    Mux,
+}
+
+impl Op {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "nand" => Some(Op::Nand),
+            "andny" => Some(Op::AndNY),
+            "oryn" => Some(Op::OrYN),
+            "mux" => Some(Op::Mux),
+            _ => None,
+        }
+    }
+
+    fn arity(self) -> usize {
+        match self {
+            Op::Not => 1,
+            Op::Mux => 3,
+            _ => 2,
+        }
+    }
+
+    fn apply<G: Gates>(self, g: &G, xs: &[G::Bit]) -> G::Bit {
+        match (self, xs) {
+            (Op::Not, [x]) => g.not(x),
+            (Op::And, [x, y]) => g.and(x, y),
+            (Op::Or, [x, y]) => g.or(x, y),
+            (Op::Xor, [x, y]) => g.xor(x, y),
+            (Op::Nand, [x, y]) => g.nand(x, y),
+            (Op::AndNY, [x, y]) => g.andny(x, y),
+            (Op::OrYN, [x, y]) => g.oryn(x, y),
+            (Op::Mux, [c, x, y]) => g.mux(c, x, y),
+            _ => panic!("{:?} takes {} operands", self, self.arity()),
+        }
+    }
+
+    fn describe(self, xs: &[String]) -> String {
+        match self {
+            Op::Not => format!("!{}", xs[0]),
+            Op::And => format!("{} & {}", xs[0], xs[1]),
+            Op::Or => format!("{} | {}", xs[0], xs[1]),
+            Op::Xor => format!("{} ^ {}", xs[0], xs[1]),
+            _ => format!("{:?}({})", self, xs.join(", ")).to_lowercase(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Expr {
+    Var(String),
+    Const(bool),
+    Op(Op, Vec<Expr>),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Ident(String),
+    Symbol(char),
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, Box<dyn Error>> {
+    let mut tokens = Vec::new();
+    let mut chars = s.chars().peekable();
+    while let Some(&ch) = chars.peek() {
+        if ch.is_whitespace() {
+            chars.next();
+        } else if ch.is_ascii_alphanumeric() || ch == '_' {
+            let mut ident = String::new();
+            while let Some(&ch) = chars.peek() {
+                if !(ch.is_ascii_alphanumeric() || ch == '_') {
+                    break;
+                }
+                ident.push(ch);
+                chars.next();
+            }
+            tokens.push(Token::Ident(ident));
+        } else if "&|^!~(),=".contains(ch) {
+            tokens.push(Token::Symbol(ch));
+            chars.next();
+        } else {
+            return error(format!("unexpected character: {}", ch));
+        }
+    }
+    Ok(tokens)
+}
+
+fn is_name(s: &str) -> bool {
+    s.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+}
+
+// 優先順位は ! > & > ^ > |
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn eat(&mut self, ch: char) -> bool {
+        if self.peek() == Some(&Token::Symbol(ch)) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, ch: char) -> Result<(), Box<dyn Error>> {
+        if self.eat(ch) {
+            Ok(())
+        } else {
+            error(format!("expected '{}'", ch))
+        }
+    }
+
+    fn binary(
+        &mut self,
+        ch: char,
+        op: Op,
+        next: fn(&mut Self) -> Result<Expr, Box<dyn Error>>,
+    ) -> Result<Expr, Box<dyn Error>> {
+        let mut x = next(self)?;
+        while self.eat(ch) {
+            let y = next(self)?;
+            x = Expr::Op(op, vec![x, y]);
+        }
+        Ok(x)
+    }
+
+    fn or(&mut self) -> Result<Expr, Box<dyn Error>> {
+        self.binary('|', Op::Or, Self::xor)
+    }
+
+    fn xor(&mut self) -> Result<Expr, Box<dyn Error>> {
+        self.binary('^', Op::Xor, Self::and)
+    }
+
+    fn and(&mut self) -> Result<Expr, Box<dyn Error>> {
+        self.binary('&', Op::And, Self::unary)
+    }
+
+    fn unary(&mut self) -> Result<Expr, Box<dyn Error>> {
+        if self.eat('!') || self.eat('~') {
+            return Ok(Expr::Op(Op::Not, vec![self.unary()?]));
+        }
+        self.atom()
+    }
+
+    fn atom(&mut self) -> Result<Expr, Box<dyn Error>> {
+        if self.eat('(') {
+            let x = self.or()?;
+            self.expect(')')?;
+            return Ok(x);
+        }
+        let ident = match self.peek() {
+            Some(Token::Ident(ident)) => ident.clone(),
+            Some(Token::Symbol(ch)) => return error(format!("unexpected '{}'", ch)),
+            None => return error("unexpected end of expression"),
+        };
+        self.pos += 1;
+        match ident.as_str() {
+            "0" | "false" => return Ok(Expr::Const(false)),
+            "1" | "true" => return Ok(Expr::Const(true)),
+            _ => {}
+        }
+        if !is_name(&ident) {
+            return error(format!("not a variable: {}", ident));
+        }
+        if !self.eat('(') {
+            return Ok(Expr::Var(ident));
+        }
+        let op = match Op::from_name(&ident) {
+            Some(op) => op,
+            None => return error(format!("unknown function: {}", ident)),
+        };
+        let mut args = vec![self.or()?];
+        while self.eat(',') {
+            args.push(self.or()?);
+        }
+        self.expect(')')?;
+        if args.len() != op.arity() {
+            return error(format!("{} takes {} arguments", ident, op.arity()));
+        }
+        Ok(Expr::Op(op, args))
+    }
+}
+
+// "name = expr" なら名前も返す
+fn parse_statement(line: &str) -> Result<(Option<String>, Expr), Box<dyn Error>> {
+    let mut tokens = tokenize(line)?;
+    let mut name = None;
+    if let [Token::Ident(ident), Token::Symbol('='), ..] = &tokens[..] {
+        if !is_name(ident) || Op::from_name(ident).is_some() {
+            return error(format!("cannot assign to {}", ident));
+        }
+        name = Some(ident.clone());
+        tokens.drain(..2);
+    }
+    let mut parser = Parser { tokens, pos: 0 };
+    let expr = parser.or()?;
+    if parser.pos != parser.tokens.len() {
+        return error("unexpected input after expression");
+    }
+    Ok((name, expr))
+}
+
+struct Step {
+    label: String,
+    text: String,
+    clear: bool,
+    decrypted: bool,
+    time: Option<Duration>,
+    noise: f64,
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let time = self.time.map_or(String::new(), |t| format!("{:.2?}", t));
+        write!(
+            f,
+            "  {:<6} = {:<20} clear {}  decrypted {}  {:>10}  noise {:+.3e}",
+            self.label, self.text, self.clear as u8, self.decrypted as u8, time, self.noise
+        )?;
+        if self.decrypted != self.clear {
+            write!(f, "  MISMATCH")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Value {
+    clear: bool,
+    cipher: CipherTLWELv0,
+}
+
+struct Repl {
+    params: ParamSet,
+    tlwe: TLWE,
+    ck: CloudKey,
+    vars: BTreeMap<String, Value>,
+}
+
+impl Repl {
+    // ±1/8 からのずれ (トーラス上)
+    fn noise(&self, c: CipherTLWELv0, expected: bool) -> f64 {
+        let phase = self.tlwe.decrypt_torus(c);
+        let mu = float_to_torus(bool_normalization(expected));
+        phase.wrapping_sub(mu) as i32 as f64 / 2f64.powi(32)
+    }
+
+    fn show(&self, label: &str, text: &str, v: &Value, time: Option<Duration>) -> String {
+        let step = Step {
+            label: label.to_string(),
+            text: text.to_string(),
+            clear: v.clear,
+            decrypted: self.tlwe.decrypt(v.cipher),
+            time,
+            noise: self.noise(v.cipher, v.clear),
+        };
+        step.to_string()
+    }
+
+    fn eval(&self, expr: &Expr, steps: &mut Vec<Step>) -> Result<(String, Value), Box<dyn Error>> {
+        match expr {
+            Expr::Var(name) => match self.vars.get(name) {
+                Some(&v) => Ok((name.clone(), v)),
+                None => error(format!("unknown variable: {}", name)),
+            },
+            Expr::Const(b) => Ok((
+                (*b as u8).to_string(),
+                Value {
+                    clear: *b,
+                    cipher: self.ck.constant(*b),
+                },
+            )),
+            Expr::Op(op, args) => {
+                let mut labels = Vec::new();
+                let mut clears = Vec::new();
+                let mut ciphers = Vec::new();
+                for arg in args {
+                    let (label, v) = self.eval(arg, steps)?;
+                    labels.push(label);
+                    clears.push(v.clear);
+                    ciphers.push(v.cipher);
+                }
+                let clear = op.apply(&Cleartext, &clears);
+                let start = Instant::now();
+                let cipher = op.apply(&self.ck, &ciphers);
+                let time = start.elapsed();
+
+                let label = format!("t{}", steps.len());
+                steps.push(Step {
+                    label: label.clone(),
+                    text: op.describe(&labels),
+                    clear,
+                    decrypted: self.tlwe.decrypt(cipher),
+                    time: Some(time),
+                    noise: self.noise(cipher, clear),
+                });
+                Ok((label, Value { clear, cipher }))
+            }
+        }
+    }
+
+    fn execute(&mut self, line: &str) -> Result<Vec<String>, Box<dyn Error>> {
+        let line = line.trim();
+        match line {
+            "" => return Ok(vec![]),
+            ":help" => return Ok(vec![HELP.to_string()]),
+            ":vars" => {
+                return Ok(self
+                    .vars
+                    .iter()
+                    .map(|(name, v)| self.show(name, "", v, None))
+                    .collect())
+            }
+            _ if line.starts_with(':') => return error(format!("unknown command: {}", line)),
+            _ => {}
+        }
+
+        let (name, expr) = parse_statement(line)?;
+        let mut out = Vec::new();
+        // 定数を代入するときは新しく暗号化する
+        if let (Some(name), Expr::Const(b)) = (&name, &expr) {
+            let start = Instant::now();
+            let v = Value {
+                clear: *b,
+                cipher: self.tlwe.encrypt(*b),
+            };
+            out.push(self.show(name, "encrypt", &v, Some(start.elapsed())));
+            self.vars.insert(name.clone(), v);
+            return Ok(out);
+        }
+
+        let mut steps = Vec::new();
+        let start = Instant::now();
+        let (label, v) = self.eval(&expr, &mut steps)?;
+        let total = start.elapsed();
+        out.extend(steps.iter().map(|s| s.to_string()));
+        let name = name.unwrap_or_else(|| "_".to_string());
+        out.push(self.show(&name, &label, &v, Some(total)));
+        self.vars.insert(name, v);
+        Ok(out)
+    }
+}
+
+pub fn run(args: &Args) -> CliResult {
+    let (params, sk, ck) = match (args.value("key")?, args.value("cloud")?) {
+        (Some(key), Some(cloud)) => {
+            let (header, sk) = load_secret_key(key)?;
+            let (ck_header, ck) = load_cloud_key(cloud)?;
+            if ck_header.key_id != header.key_id {
+                return error(format!("{} does not belong to {}", cloud, key));
+            }
+            (header.params, sk, ck)
+        }
+        (None, None) => {
+            let params = args.params()?;
+            let start = Instant::now();
+            let sk = params.secret_key();
+            let ck = params.cloud_key(sk);
+            println!(
+                "generated {} keys in {:.2?}",
+                params.name(),
+                start.elapsed()
+            );
+            (params, sk, ck)
+        }
+        _ => return error("give both --key and --cloud, or neither"),
+    };
+    let mut repl = Repl {
+        params,
+        tlwe: TLWE::new(sk),
+        ck,
+        vars: BTreeMap::new(),
+    };
+    println!("params: {}. type :help for help", repl.params.name());
+
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        print!("> ");
+        io::stdout().flush()?;
+        let line = match lines.next() {
+            Some(line) => line?,
+            None => break,
+        };
+        if matches!(line.trim(), ":quit" | ":q" | ":exit") {
+            break;
+        }
+        match repl.execute(&line) {
+            Ok(out) => {
+                for l in out {
+                    println!("{}", l);
+                }
+            }
+            Err(e) => println!("error: {}", e),
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_parse_statement() {
+    let var = |s: &str| Expr::Var(s.to_string());
+    let (name, expr) = parse_statement("c = (a & b) ^ !d").unwrap();
+    assert_eq!(name.as_deref(), Some("c"));
+    assert_eq!(
+        expr,
+        Expr::Op(
+            Op::Xor,
+            vec![
+                Expr::Op(Op::And, vec![var("a"), var("b")]),
+                Expr::Op(Op::Not, vec![var("d")])
+            ]
+        )
+    );
+
+    // & は | より強い
+    let (_, expr) = parse_statement("a | b & 1").unwrap();
+    assert_eq!(
+        expr,
+        Expr::Op(
+            Op::Or,
+            vec![
+                var("a"),
+                Expr::Op(Op::And, vec![var("b"), Expr::Const(true)])
+            ]
+        )
+    );
+    let (_, expr) = parse_statement("mux(s, ~a, nand(a, b))").unwrap();
+    assert!(matches!(expr, Expr::Op(Op::Mux, ref xs) if xs.len() == 3));
+
+    assert!(parse_statement("a &").is_err());
+    assert!(parse_statement("(a").is_err());
+    assert!(parse_statement("a b").is_err());
+    assert!(parse_statement("nand(a)").is_err());
+    assert!(parse_statement("f(a, b)").is_err());
+    assert!(parse_statement("1 = a").is_err());
+    assert!(parse_statement("a + b").is_err());
+}
+
+#[test]
+fn test_repl() {
+    let params = ParamSet::Binary;
+    let sk = params.secret_key();
+    let mut repl = Repl {
+        params,
+        tlwe: TLWE::new(sk),
+        ck: params.cloud_key(sk),
+        vars: BTreeMap::new(),
+    };
+    repl.execute("a = 1").unwrap();
+    repl.execute("b = 0").unwrap();
+    let out = repl.execute("c = !(a & b)").unwrap();
+    // and と not の 2 段と結果
+    assert_eq!(out.len(), 3);
+    assert!(out.iter().all(|l| !l.contains("MISMATCH")));
+    assert!(repl.vars["c"].clear);
+    assert!(repl.tlwe.decrypt(repl.vars["c"].cipher));
+
+    assert_eq!(repl.execute(":vars").unwrap().len(), 3);
+    assert!(repl.execute("d = a & x").is_err());
+    assert!(repl.execute(":unknown").is_err());
+}
@@ -0,0 +1,81 @@
+use std::net::TcpListener;
+#[cfg(unix)]
+use std::os::unix::net::UnixListener;
+use std::path::Path;
+use std::sync::Arc;
+
+use kfhe::server::{serve, Server};
+
+use super::eval::load_netlist;
+use super::{error, Args, CliResult};
+
+// "id=file" でなければファイル名 (拡張子を除く) を id にする
+fn circuit_id(arg: &str) -> (String, &str) {
+    match arg.split_once('=') {
+        Some((id, file)) => (id.to_string(), file),
+        None => {
+            let stem = Path::new(arg).file_stem().and_then(|s| s.to_str());
+            (stem.unwrap_or(arg).to_string(), arg)
+        }
+    }
+}
+
+// "unix:path" か '/' を含むものは Unix ソケット、それ以外は TCP のアドレス
+fn unix_socket_path(listen: &str) -> Option<&str> {
+    match listen.strip_prefix("unix:") {
+        Some(path) => Some(path),
+        None if listen.contains('/') => Some(listen),
+        None => None,
+    }
+}
+
+pub fn run(args: &Args) -> CliResult {
+    let listen = args.required("listen")?;
+    let mut server = Server::new(args.threads()?);
+    let circuits = args.values("circuit");
+    if circuits.is_empty() {
+        return error("give at least one --circuit");
+    }
+    for arg in circuits {
+        let (id, file) = circuit_id(arg);
+        let netlist = load_netlist(file, args.value("format")?, args.value("module")?)?;
+        println!(
+            "circuit {}: {} ({} inputs, {} outputs)",
+            id,
+            file,
+            netlist.inputs.len(),
+            netlist.outputs.len()
+        );
+        server.add_circuit(&id, netlist);
+    }
+    let server = Arc::new(server);
+
+    match unix_socket_path(listen) {
+        #[cfg(unix)]
+        Some(path) => {
+            let listener = UnixListener::bind(path).map_err(|e| format!("{}: {}", path, e))?;
+            println!("listening on unix:{}", path);
+            serve(server, listener.incoming())?;
+        }
+        #[cfg(not(unix))]
+        Some(_) => return error("unix sockets are not supported on this platform"),
+        None => {
+            let listener = TcpListener::bind(listen).map_err(|e| format!("{}: {}", listen, e))?;
+            println!("listening on {}", listener.local_addr()?);
+            serve(server, listener.incoming())?;
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_listen_args() {
+    assert_eq!(
+        circuit_id("adder=c/add.json"),
+        ("adder".to_string(), "c/add.json")
+    );
+    assert_eq!(circuit_id("c/add.txt"), ("add".to_string(), "c/add.txt"));
+    assert_eq!(unix_socket_path("unix:kfhe.sock"), Some("kfhe.sock"));
+    assert_eq!(unix_socket_path("/tmp/kfhe.sock"), Some("/tmp/kfhe.sock"));
+    assert_eq!(unix_socket_path("127.0.0.1:7878"), None);
+}
@@ -0,0 +1,56 @@
+// 不正な引数に対してライブラリが返すエラー
+// panic する公開関数には、これを返す try_ 版がある (サーバなど外から来た値を扱うとき用)
+
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum KfheError {
+    // index が 0..len に入っていない
+    IndexOutOfRange {
+        what: &'static str,
+        index: usize,
+        len: usize,
+    },
+    // 無限大や NaN はトーラスに載せられない
+    NotFinite(f64),
+    // [-0.5, 0.5) の外
+    OutOfTorusRange(f64),
+    // 正規分布の標準偏差が負か有限でない
+    InvalidStddev(f64),
+    // 鍵の暗号文の個数が合わない
+    KeySize {
+        what: &'static str,
+        expected: usize,
+        found: usize,
+    },
+}
+
+impl fmt::Display for KfheError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KfheError::IndexOutOfRange { what, index, len } => {
+                write!(f, "{} {} is out of range 0..{}", what, index, len)
+            }
+            KfheError::NotFinite(x) => write!(f, "{} is not a finite number", x),
+            KfheError::OutOfTorusRange(x) => write!(f, "{} is not in [-0.5, 0.5)", x),
+            KfheError::InvalidStddev(s) => {
+                write!(
+                    f,
+                    "invalid standard deviation {} for a normal distribution",
+                    s
+                )
+            }
+            KfheError::KeySize {
+                what,
+                expected,
+                found,
+            } => write!(
+                f,
+                "{} should have {} ciphertexts, but has {}",
+                what, expected, found
+            ),
+        }
+    }
+}
+
+impl std::error::Error for KfheError {}
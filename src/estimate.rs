@@ -0,0 +1,243 @@
+// パラメータから導かれる量の見積もり (サイズ、ノイズ、復号失敗確率、安全性)
+// ノイズはトーラス上の分散で、各段の誤差は独立とみなす
+
+use std::f64::consts::{E, PI};
+use std::mem::size_of;
+
+use super::bootstrapping::BootstrappingKeyLayout;
+use super::key::KeyDistribution;
+use super::key_switching::KeySwitchingKey;
+use super::params::{tlwe, trgsw, trlwe, ParamSet};
+use super::tlwe::{CipherTLWELv0, CipherTLWELv1};
+use super::trgsw::TRGSWMatrix;
+use super::trlwe::CipherTRLWE;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sizes {
+    // すべてバイト
+    pub tlwe_lv0: usize,
+    pub tlwe_lv1: usize,
+    pub trlwe: usize,
+    pub trgsw: usize,
+    pub secret_key: usize,
+    pub bootstrapping_key: usize,
+    pub key_switching_key: usize,
+    // 個数
+    pub bootstrapping_key_entries: usize,
+    pub key_switching_key_entries: usize,
+}
+
+pub fn sizes(params: ParamSet) -> Sizes {
+    let bk_entries = params.bootstrapping_layout().size();
+    let ks_entries = KeySwitchingKey::SIZE;
+    Sizes {
+        tlwe_lv0: size_of::<CipherTLWELv0>(),
+        tlwe_lv1: size_of::<CipherTLWELv1>(),
+        trlwe: size_of::<CipherTRLWE>(),
+        trgsw: size_of::<TRGSWMatrix>(),
+        secret_key: 4 * (tlwe::N + trlwe::N),
+        bootstrapping_key: bk_entries * size_of::<TRGSWMatrix>(),
+        key_switching_key: ks_entries * size_of::<CipherTLWELv0>(),
+        bootstrapping_key_entries: bk_entries,
+        key_switching_key_entries: ks_entries,
+    }
+}
+
+// 秘密鍵の係数の二乗平均
+fn key_second_moment(dist: KeyDistribution) -> f64 {
+    match dist {
+        KeyDistribution::Binary => 0.5,
+        KeyDistribution::Ternary => 2. / 3.,
+        KeyDistribution::Gaussian => trlwe::KEY_SIGMA * trlwe::KEY_SIGMA,
+    }
+}
+
+// 秘密鍵の係数の標準偏差 (平均を引いたもの)
+fn key_stddev(dist: KeyDistribution) -> f64 {
+    match dist {
+        KeyDistribution::Binary => 0.5,
+        KeyDistribution::Ternary => (2f64 / 3.).sqrt(),
+        KeyDistribution::Gaussian => trlwe::KEY_SIGMA,
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Noise {
+    // 新しく暗号化した TLWE lv0
+    pub fresh: f64,
+    // blind rotation の前に 2N へ丸める誤差
+    pub modulus_switching: f64,
+    // blind rotation (sample extract はノイズを増やさない)
+    pub blind_rotation: f64,
+    // identity key switching で加わる分
+    pub key_switching: f64,
+    // gate bootstrapping の出力 (TLWE lv0)
+    pub bootstrapped: f64,
+}
+
+pub fn noise(params: ParamSet) -> Noise {
+    let (lv0_dist, lv1_dist) = params.key_distribution();
+    let n = tlwe::N as f64;
+    let big_n = trlwe::N as f64;
+    let l = trgsw::L as f64;
+    let half_bg = trgsw::BG as f64 / 2.;
+
+    let fresh = tlwe::ALPHA * tlwe::ALPHA;
+    let modulus_switching = (1. + n * key_second_moment(lv0_dist)) / (48. * big_n * big_n);
+
+    // 外積 1 回: 鍵のノイズ (2 L N (Bg/2)^2 V_bk) と分解の丸め誤差 ((1 + N) ε^2)
+    let v_bk = 2. * l * big_n * half_bg * half_bg * trlwe::ALPHA * trlwe::ALPHA;
+    let eps = 1. / (2. * (trgsw::BG as f64).powi(trgsw::L as i32));
+    let v_round = (1. + big_n) * eps * eps;
+    // (X^a - 1) を掛けた TRGSW はノイズが 2 倍になる
+    let blind_rotation = match params.bootstrapping_layout() {
+        BootstrappingKeyLayout::Binary => n * (v_bk + v_round),
+        BootstrappingKeyLayout::Ternary => n * (4. * v_bk + v_round),
+        BootstrappingKeyLayout::Bundled => {
+            let pairs = (tlwe::N / 2) as f64;
+            let odd = (tlwe::N % 2) as f64;
+            pairs * (6. * v_bk + v_round) + odd * (v_bk + v_round)
+        }
+    };
+
+    let t = trgsw::T as f64;
+    let ks_eps = 2f64.powi(-((trgsw::T as i32) * (trgsw::BASEBIT as i32) + 1));
+    let key_switching = big_n * t * tlwe::ALPHA * tlwe::ALPHA
+        + big_n * key_second_moment(lv1_dist) * ks_eps * ks_eps / 3.;
+
+    Noise {
+        fresh,
+        modulus_switching,
+        blind_rotation,
+        key_switching,
+        bootstrapped: blind_rotation + key_switching,
+    }
+}
+
+// 相補誤差関数. Numerical Recipes の erfcc (相対誤差 1.2e-7 以下)
+pub fn erfc(x: f64) -> f64 {
+    let z = x.abs();
+    let t = 1. / (1. + 0.5 * z);
+    let r = t
+        * (-z * z - 1.265_512_23
+            + t * (1.000_023_68
+                + t * (0.374_091_96
+                    + t * (0.096_784_18
+                        + t * (-0.186_288_06
+                            + t * (0.278_868_07
+                                + t * (-1.135_203_98
+                                    + t * (1.488_515_87
+                                        + t * (-0.822_152_23 + t * 0.170_872_77)))))))))
+            .exp();
+    if x >= 0. {
+        r
+    } else {
+        2. - r
+    }
+}
+
+// 分散 v の誤差が bound を超える確率
+fn tail_probability(v: f64, bound: f64) -> f64 {
+    erfc(bound / (2. * v).sqrt())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FailureProbability {
+    // NAND, AND, OR など: 入力 2 つの和
+    pub nand: f64,
+    // XOR: 2 (x + y) なので分散は 4 倍になるが、出力の判定の余裕も 1/4 と倍になる
+    pub xor: f64,
+}
+
+// bootstrapping 済みの入力を使ったとき、ゲートの出力が誤る確率
+pub fn failure_probability(params: ParamSet) -> FailureProbability {
+    let noise = noise(params);
+    let nand = 2. * noise.bootstrapped + noise.modulus_switching;
+    let xor = 8. * noise.bootstrapped + noise.modulus_switching;
+    FailureProbability {
+        nand: tail_probability(nand, 0.125),
+        xor: tail_probability(xor, 0.25),
+    }
+}
+
+// primal uSVP 攻撃の core-SVP 見積もり (ADPS16). BKZ のブロックサイズ β に対して 0.292 β ビット
+// n 次元, 法 2^32, 誤差の標準偏差 alpha (トーラス上), 秘密鍵の標準偏差 secret_stddev
+pub fn security_bits(n: usize, alpha: f64, secret_stddev: f64) -> f64 {
+    let ln_q = 32. * 2f64.ln();
+    let sigma = alpha * 2f64.powi(32);
+    // 秘密鍵を誤差と同じ大きさに拡大した埋め込み
+    let ln_scale = (sigma / secret_stddev).ln();
+
+    for beta in 40..=(2 * n) {
+        let b = beta as f64;
+        let ln_delta = ((PI * b).ln() / b + (b / (2. * PI * E)).ln()) / (2. * (b - 1.));
+        for m in (1..=2 * n).step_by(4) {
+            let d = (m + n + 1) as f64;
+            if b > d {
+                continue;
+            }
+            let ln_vol = m as f64 * ln_q + n as f64 * ln_scale;
+            if sigma.ln() + 0.5 * b.ln() <= (2. * b - d) * ln_delta + ln_vol / d {
+                return 0.292 * b;
+            }
+        }
+    }
+    0.292 * (2 * n) as f64
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Security {
+    pub lv0: f64,
+    pub lv1: f64,
+}
+
+impl Security {
+    pub fn bits(&self) -> f64 {
+        self.lv0.min(self.lv1)
+    }
+}
+
+pub fn security(params: ParamSet) -> Security {
+    let (lv0_dist, lv1_dist) = params.key_distribution();
+    Security {
+        lv0: security_bits(tlwe::N, tlwe::ALPHA, key_stddev(lv0_dist)),
+        lv1: security_bits(trlwe::N, trlwe::ALPHA, key_stddev(lv1_dist)),
+    }
+}
+
+#[test]
+fn test_erfc() {
+    let close = |x: f64, y: f64| ((x - y) / y).abs() < 1e-6;
+    assert!(close(erfc(0.), 1.));
+    assert!(close(erfc(1.), 0.157_299_207_050_285_1));
+    assert!(close(erfc(-1.), 1.842_700_792_949_715));
+    assert!(close(erfc(5.), 1.537_459_794_428_035e-12));
+}
+
+#[test]
+fn test_estimates() {
+    let sizes = sizes(ParamSet::Binary);
+    assert_eq!(sizes.tlwe_lv0, 4 * (tlwe::N + 1));
+    assert_eq!(
+        sizes.bootstrapping_key,
+        tlwe::N * 2 * trgsw::L * 2 * trgsw::N * 4
+    );
+    assert_eq!(sizes.key_switching_key_entries, 3 * trgsw::T * trlwe::N);
+
+    // 三値や束ねた鍵は blind rotation のノイズが大きい
+    let binary = noise(ParamSet::Binary);
+    assert!(noise(ParamSet::Ternary).blind_rotation > binary.blind_rotation);
+    assert!(noise(ParamSet::Bundled).blind_rotation > binary.blind_rotation);
+
+    for params in ParamSet::ALL {
+        let p = failure_probability(params);
+        // XOR は余裕が倍になる分、modulus switching のノイズが相対的に小さくなるので NAND より誤りにくい
+        assert!(p.xor < p.nand && p.nand < 1e-9, "{:?}: {:?}", params, p);
+    }
+
+    // 次元が大きい方が安全で、鍵の分布が広い方が安全
+    assert!(security_bits(1024, tlwe::ALPHA, 0.5) > security_bits(635, tlwe::ALPHA, 0.5));
+    let s = security(ParamSet::Gaussian);
+    assert!(s.lv1 > security(ParamSet::Binary).lv1);
+    assert!(s.bits() > 80.);
+}
@@ -0,0 +1,170 @@
+use super::bootstrapping::gate_bootstrapping_with_key;
+use super::key::CloudKey;
+use super::key_switching::identity_key_switching_with_key;
+use super::params::trlwe;
+use super::tlwe::{CipherTLWELv0, CipherTLWELv1};
+use super::util::float_to_torus;
+
+// 論理ゲートの組. 同じ回路を平文 (Cleartext) と暗号文 (CloudKey) の両方で評価できるようにする
+pub trait Gates {
+    type Bit: Clone;
+
+    fn constant(&self, b: bool) -> Self::Bit;
+    fn nand(&self, x: &Self::Bit, y: &Self::Bit) -> Self::Bit;
+    fn and(&self, x: &Self::Bit, y: &Self::Bit) -> Self::Bit;
+    fn or(&self, x: &Self::Bit, y: &Self::Bit) -> Self::Bit;
+    fn xor(&self, x: &Self::Bit, y: &Self::Bit) -> Self::Bit;
+    fn not(&self, x: &Self::Bit) -> Self::Bit;
+    // c ? x : y
+    fn mux(&self, c: &Self::Bit, x: &Self::Bit, y: &Self::Bit) -> Self::Bit;
+
+    // !x & y
+    fn andny(&self, x: &Self::Bit, y: &Self::Bit) -> Self::Bit {
+        self.and(&self.not(x), y)
+    }
+
+    // x | !y
+    fn oryn(&self, x: &Self::Bit, y: &Self::Bit) -> Self::Bit {
+        self.or(x, &self.not(y))
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Cleartext;
+
+impl Gates for Cleartext {
+    type Bit = bool;
+
+    fn constant(&self, b: bool) -> bool {
+        b
+    }
+
+    fn nand(&self, x: &bool, y: &bool) -> bool {
+        !(*x && *y)
+    }
+
+    fn and(&self, x: &bool, y: &bool) -> bool {
+        *x && *y
+    }
+
+    fn or(&self, x: &bool, y: &bool) -> bool {
+        *x || *y
+    }
+
+    fn xor(&self, x: &bool, y: &bool) -> bool {
+        *x ^ *y
+    }
+
+    fn not(&self, x: &bool) -> bool {
+        !*x
+    }
+
+    fn mux(&self, c: &bool, x: &bool, y: &bool) -> bool {
+        if *c {
+            *x
+        } else {
+            *y
+        }
+    }
+}
+
+impl CloudKey {
+    fn bootstrap(&self, c: CipherTLWELv0) -> CipherTLWELv0 {
+        identity_key_switching_with_key(gate_bootstrapping_with_key(c, &self.bk), &self.ks)
+    }
+}
+
+// 真偽値は ±1/8 で符号化されている
+impl Gates for CloudKey {
+    type Bit = CipherTLWELv0;
+
+    fn constant(&self, b: bool) -> CipherTLWELv0 {
+        if b {
+            CipherTLWELv0::clearly_true()
+        } else {
+            -CipherTLWELv0::clearly_true()
+        }
+    }
+
+    fn nand(&self, x: &CipherTLWELv0, y: &CipherTLWELv0) -> CipherTLWELv0 {
+        let c_true = CipherTLWELv0::clearly_true();
+        self.bootstrap(c_true - (*x + *y))
+    }
+
+    fn and(&self, x: &CipherTLWELv0, y: &CipherTLWELv0) -> CipherTLWELv0 {
+        let c_true = CipherTLWELv0::clearly_true();
+        self.bootstrap(*x + *y - c_true)
+    }
+
+    fn or(&self, x: &CipherTLWELv0, y: &CipherTLWELv0) -> CipherTLWELv0 {
+        let c_true = CipherTLWELv0::clearly_true();
+        self.bootstrap(*x + *y + c_true)
+    }
+
+    fn xor(&self, x: &CipherTLWELv0, y: &CipherTLWELv0) -> CipherTLWELv0 {
+        // 2(x + y) + 1/4
+        let quarter = CipherTLWELv0::trivial(float_to_torus(0.25));
+        let s = *x + *y;
+        self.bootstrap(s + s + quarter)
+    }
+
+    fn not(&self, x: &CipherTLWELv0) -> CipherTLWELv0 {
+        -*x
+    }
+
+    fn mux(&self, c: &CipherTLWELv0, x: &CipherTLWELv0, y: &CipherTLWELv0) -> CipherTLWELv0 {
+        // AND(c, x) + AND(!c, y) + 1/8 を lv1 のまま足してから 1 回だけ key switching する
+        let c_true = CipherTLWELv0::clearly_true();
+        let cx = gate_bootstrapping_with_key(*c + *x - c_true, &self.bk);
+        let cy = gate_bootstrapping_with_key(*y - *c - c_true, &self.bk);
+        let offset = CipherTLWELv1([0; trlwe::N], float_to_torus(0.125));
+        identity_key_switching_with_key(cx + cy + offset, &self.ks)
+    }
+
+    // NOT は符号を反転するだけなので、足し合わせる前の線形結合に含められる
+    fn andny(&self, x: &CipherTLWELv0, y: &CipherTLWELv0) -> CipherTLWELv0 {
+        let c_true = CipherTLWELv0::clearly_true();
+        self.bootstrap(*y - *x - c_true)
+    }
+
+    fn oryn(&self, x: &CipherTLWELv0, y: &CipherTLWELv0) -> CipherTLWELv0 {
+        let c_true = CipherTLWELv0::clearly_true();
+        self.bootstrap(*x - *y + c_true)
+    }
+}
+
+#[test]
+fn test_cloud_key_gates() {
+    use super::key::SecretKey;
+    use super::tlwe::TLWE;
+
+    let sk = SecretKey::new();
+    let tlwe = TLWE::new(sk);
+    let ck = CloudKey::new(sk);
+
+    for x in [true, false] {
+        for y in [true, false] {
+            let (cx, cy) = (tlwe.encrypt(x), tlwe.encrypt(y));
+            assert_eq!(tlwe.decrypt(ck.nand(&cx, &cy)), Cleartext.nand(&x, &y));
+            assert_eq!(tlwe.decrypt(ck.and(&cx, &cy)), Cleartext.and(&x, &y));
+            assert_eq!(tlwe.decrypt(ck.or(&cx, &cy)), Cleartext.or(&x, &y));
+            assert_eq!(tlwe.decrypt(ck.xor(&cx, &cy)), Cleartext.xor(&x, &y));
+            assert_eq!(tlwe.decrypt(ck.andny(&cx, &cy)), Cleartext.andny(&x, &y));
+            assert_eq!(tlwe.decrypt(ck.oryn(&cx, &cy)), Cleartext.oryn(&x, &y));
+        }
+        let cx = tlwe.encrypt(x);
+        assert_eq!(tlwe.decrypt(ck.not(&cx)), Cleartext.not(&x));
+    }
+
+    for c in [true, false] {
+        for x in [true, false] {
+            for y in [true, false] {
+                let (cc, cx, cy) = (tlwe.encrypt(c), tlwe.encrypt(x), tlwe.encrypt(y));
+                assert_eq!(
+                    tlwe.decrypt(ck.mux(&cc, &cx, &cy)),
+                    Cleartext.mux(&c, &x, &y)
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,1033 @@
+use std::sync::Arc;
+
+use super::gates::Gates;
+use super::key::CloudKey;
+use super::tlwe::{CipherTLWELv0, TLWE};
+
+// ビット列は下位ビットから並べる (bits[0] が LSB)
+
+// x + y + carry (carry が None なら 0)
+// carry_out が true なら最上位からの桁上がりも返し、false なら捨てる
+fn ripple_carry_add<G: Gates>(
+    g: &G,
+    x: &[G::Bit],
+    y: &[G::Bit],
+    carry: Option<G::Bit>,
+    carry_out: bool,
+) -> (Vec<G::Bit>, Option<G::Bit>) {
+    assert_eq!(x.len(), y.len());
+
+    let n = x.len();
+    let mut sum = Vec::with_capacity(n);
+    let mut carry = carry;
+    for i in 0..n {
+        let t = g.xor(&x[i], &y[i]);
+        let need_carry = carry_out || i + 1 < n;
+        match carry.take() {
+            None => {
+                sum.push(t);
+                if need_carry {
+                    carry = Some(g.and(&x[i], &y[i]));
+                }
+            }
+            Some(c) => {
+                sum.push(g.xor(&t, &c));
+                if need_carry {
+                    // x ^ y == 0 なら x == y なので、桁上がりは x と同じ
+                    carry = Some(g.mux(&t, &c, &x[i]));
+                }
+            }
+        }
+    }
+    (sum, carry)
+}
+
+// x - y と、x >= y なら真になるビットを返す
+fn sub_with_carry<G: Gates>(g: &G, x: &[G::Bit], y: &[G::Bit]) -> (Vec<G::Bit>, G::Bit) {
+    let not_y: Vec<G::Bit> = y.iter().map(|b| g.not(b)).collect();
+    let (diff, carry) = ripple_carry_add(g, x, &not_y, Some(g.constant(true)), true);
+    (diff, carry.unwrap())
+}
+
+// 平文の定数 k について x - k と、x >= k なら真になるビットを返す
+// k のビットが既知なので、1 ビットあたり高々 2 回の bootstrapping で済む
+fn sub_const_with_carry<G: Gates>(g: &G, x: &[G::Bit], k: u64) -> (Vec<G::Bit>, G::Bit) {
+    let n = x.len();
+    let mut diff = Vec::with_capacity(n);
+    // x + !k + 1. carry が None の間は定数 1
+    let mut carry: Option<G::Bit> = None;
+    for (i, xi) in x.iter().enumerate() {
+        let kb = (k >> i) & 1 == 0;
+        match carry.take() {
+            None => {
+                // x ^ kb ^ 1, maj(x, kb, 1) = x | kb
+                diff.push(if kb { xi.clone() } else { g.not(xi) });
+                if !kb {
+                    carry = Some(xi.clone());
+                }
+            }
+            Some(c) => {
+                let s = g.xor(xi, &c);
+                diff.push(if kb { g.not(&s) } else { s });
+                carry = Some(if kb { g.or(xi, &c) } else { g.and(xi, &c) });
+            }
+        }
+    }
+    let ge = carry.unwrap_or_else(|| g.constant(true));
+    (diff, ge)
+}
+
+fn mux_bits<G: Gates>(g: &G, c: &G::Bit, x: &[G::Bit], y: &[G::Bit]) -> Vec<G::Bit> {
+    assert_eq!(x.len(), y.len());
+    x.iter().zip(y).map(|(xi, yi)| g.mux(c, xi, yi)).collect()
+}
+
+pub fn add<G: Gates>(g: &G, x: &[G::Bit], y: &[G::Bit]) -> Vec<G::Bit> {
+    ripple_carry_add(g, x, y, None, false).0
+}
+
+// x - y = x + !y + 1
+pub fn sub<G: Gates>(g: &G, x: &[G::Bit], y: &[G::Bit]) -> Vec<G::Bit> {
+    let not_y: Vec<G::Bit> = y.iter().map(|b| g.not(b)).collect();
+    ripple_carry_add(g, x, &not_y, Some(g.constant(true)), false).0
+}
+
+// -x = !x + 1
+pub fn neg<G: Gates>(g: &G, x: &[G::Bit]) -> Vec<G::Bit> {
+    let n = x.len();
+    let mut ret = Vec::with_capacity(n);
+    // carry が None の間は定数 1
+    let mut carry: Option<G::Bit> = None;
+    for (i, xi) in x.iter().enumerate() {
+        let b = g.not(xi);
+        let last = i + 1 == n;
+        match carry.take() {
+            None => {
+                ret.push(g.not(&b));
+                if !last {
+                    carry = Some(b);
+                }
+            }
+            Some(c) => {
+                ret.push(g.xor(&b, &c));
+                if !last {
+                    carry = Some(g.and(&b, &c));
+                }
+            }
+        }
+    }
+    ret
+}
+
+// 下位 n ビットだけを求める筆算 (shift-and-add)
+pub fn mul<G: Gates>(g: &G, x: &[G::Bit], y: &[G::Bit]) -> Vec<G::Bit> {
+    assert_eq!(x.len(), y.len());
+
+    let n = x.len();
+    let mut acc: Vec<G::Bit> = x.iter().map(|xj| g.and(xj, &y[0])).collect();
+    for i in 1..n {
+        // x * y_i * 2^i のうち、下位 n ビットに入る部分だけを足す
+        let row: Vec<G::Bit> = x[..(n - i)].iter().map(|xj| g.and(xj, &y[i])).collect();
+        let upper = add(g, &acc[i..], &row);
+        acc.truncate(i);
+        acc.extend(upper);
+    }
+    acc
+}
+
+// 引き戻し法による除算. (商, 余り) を返す
+// d == 0 のときは商が全ビット 1、余りが x になる
+pub fn div_rem<G: Gates>(g: &G, x: &[G::Bit], d: &[G::Bit]) -> (Vec<G::Bit>, Vec<G::Bit>) {
+    assert_eq!(x.len(), d.len());
+
+    let n = x.len();
+    let mut q = vec![g.constant(false); n];
+    let mut r: Vec<G::Bit> = Vec::with_capacity(n + 1);
+    for i in (0..n).rev() {
+        // r = (r << 1) | x_i
+        r.insert(0, x[i].clone());
+
+        // 幅を揃える. 足りない方の上位は 0
+        let mut dd = d.to_vec();
+        while r.len() < dd.len() {
+            r.push(g.constant(false));
+        }
+        while dd.len() < r.len() {
+            dd.push(g.constant(false));
+        }
+
+        let (t, ge) = sub_with_carry(g, &r, &dd);
+        r = mux_bits(g, &ge, &t, &r);
+        q[i] = ge;
+
+        // r < d なので n ビットに収まる
+        r.truncate(n);
+    }
+    (q, r)
+}
+
+// 平文の定数 d による除算. (商, 余り) を返す
+pub fn div_rem_scalar<G: Gates>(g: &G, x: &[G::Bit], d: u64) -> (Vec<G::Bit>, Vec<G::Bit>) {
+    assert!(d != 0, "division by zero");
+
+    let n = x.len();
+
+    // 2 の冪ならビットを並べ替えるだけ
+    if d.is_power_of_two() {
+        let k = (d.trailing_zeros() as usize).min(n);
+        let mut q: Vec<G::Bit> = x[k..].to_vec();
+        q.resize(n, g.constant(false));
+        let mut r: Vec<G::Bit> = x[..k].to_vec();
+        r.resize(n, g.constant(false));
+        return (q, r);
+    }
+
+    // d のビット幅
+    let width = 64 - d.leading_zeros() as usize;
+    let mut q = vec![g.constant(false); n];
+    let mut r: Vec<G::Bit> = Vec::with_capacity(width + 1);
+    for i in (0..n).rev() {
+        r.insert(0, x[i].clone());
+
+        // r のビット幅が d より小さい間は商が 0 と分かっている
+        if r.len() < width {
+            continue;
+        }
+
+        let (t, ge) = sub_const_with_carry(g, &r, d);
+        r = mux_bits(g, &ge, &t, &r);
+        q[i] = ge;
+
+        // r < d なので width ビットに収まる
+        r.truncate(width);
+    }
+    r.resize(n, g.constant(false));
+    (q, r)
+}
+
+// 2 つずつまとめていく木で (x == y, x > y) を求める. 段数は log2(n) + 1
+// 0 ビット同士は eq と同じく等しいとみなす
+fn compare_tree<G: Gates>(g: &G, x: &[G::Bit], y: &[G::Bit]) -> (G::Bit, G::Bit) {
+    assert_eq!(x.len(), y.len());
+    if x.is_empty() {
+        return (g.constant(true), g.constant(false));
+    }
+
+    // 各ビットの (x_i == y_i, x_i > y_i)
+    let mut level: Vec<(G::Bit, G::Bit)> = x
+        .iter()
+        .zip(y)
+        .map(|(xi, yi)| (g.not(&g.xor(xi, yi)), g.and(xi, &g.not(yi))))
+        .collect();
+
+    while level.len() > 1 {
+        let mut next = Vec::with_capacity(level.len().div_ceil(2));
+        for pair in level.chunks(2) {
+            match pair {
+                [(eq_lo, gt_lo), (eq_hi, gt_hi)] => {
+                    // 上位が等しいときだけ下位の比較結果を使う
+                    let eq = g.and(eq_hi, eq_lo);
+                    let gt = g.mux(eq_hi, gt_lo, gt_hi);
+                    next.push((eq, gt));
+                }
+                [single] => next.push(single.clone()),
+                _ => unreachable!(),
+            }
+        }
+        level = next;
+    }
+    level.pop().unwrap()
+}
+
+// AND の木で x == y を求める
+pub fn eq<G: Gates>(g: &G, x: &[G::Bit], y: &[G::Bit]) -> G::Bit {
+    assert_eq!(x.len(), y.len());
+
+    let mut level: Vec<G::Bit> = x
+        .iter()
+        .zip(y)
+        .map(|(xi, yi)| g.not(&g.xor(xi, yi)))
+        .collect();
+    while level.len() > 1 {
+        level = level
+            .chunks(2)
+            .map(|pair| match pair {
+                [lo, hi] => g.and(lo, hi),
+                [single] => single.clone(),
+                _ => unreachable!(),
+            })
+            .collect();
+    }
+    level.pop().unwrap_or_else(|| g.constant(true))
+}
+
+pub fn ne<G: Gates>(g: &G, x: &[G::Bit], y: &[G::Bit]) -> G::Bit {
+    g.not(&eq(g, x, y))
+}
+
+pub fn gt<G: Gates>(g: &G, x: &[G::Bit], y: &[G::Bit]) -> G::Bit {
+    compare_tree(g, x, y).1
+}
+
+pub fn lt<G: Gates>(g: &G, x: &[G::Bit], y: &[G::Bit]) -> G::Bit {
+    compare_tree(g, y, x).1
+}
+
+pub fn ge<G: Gates>(g: &G, x: &[G::Bit], y: &[G::Bit]) -> G::Bit {
+    g.not(&lt(g, x, y))
+}
+
+pub fn le<G: Gates>(g: &G, x: &[G::Bit], y: &[G::Bit]) -> G::Bit {
+    g.not(&gt(g, x, y))
+}
+
+pub fn min<G: Gates>(g: &G, x: &[G::Bit], y: &[G::Bit]) -> Vec<G::Bit> {
+    mux_bits(g, &lt(g, x, y), x, y)
+}
+
+pub fn max<G: Gates>(g: &G, x: &[G::Bit], y: &[G::Bit]) -> Vec<G::Bit> {
+    mux_bits(g, &gt(g, x, y), x, y)
+}
+
+// 出力の i ビット目を x の src(i, a) ビット目にする (None なら 0)
+fn permute<G: Gates, F: Fn(usize, usize) -> Option<usize>>(
+    g: &G,
+    x: &[G::Bit],
+    a: usize,
+    src: F,
+) -> Vec<G::Bit> {
+    (0..x.len())
+        .map(|i| match src(i, a) {
+            Some(j) => x[j].clone(),
+            None => g.constant(false),
+        })
+        .collect()
+}
+
+// バレルシフタ. s の k ビット目が立っていれば 2^k だけ動かす
+fn barrel_shift<G: Gates, F: Fn(usize, usize) -> Option<usize>>(
+    g: &G,
+    x: &[G::Bit],
+    s: &[G::Bit],
+    src: F,
+) -> Vec<G::Bit> {
+    let mut cur = x.to_vec();
+    for (k, sk) in s.iter().enumerate() {
+        let a = 1usize.checked_shl(k as u32).unwrap_or(usize::MAX);
+        cur = (0..cur.len())
+            .map(|i| match src(i, a) {
+                Some(j) if j == i => cur[i].clone(),
+                Some(j) => g.mux(sk, &cur[j], &cur[i]),
+                // 0 が入ってくるところは mux より安い AND で済む
+                None => g.and(&g.not(sk), &cur[i]),
+            })
+            .collect();
+    }
+    cur
+}
+
+fn shl_src(n: usize) -> impl Fn(usize, usize) -> Option<usize> {
+    move |i, a| if a < n && i >= a { Some(i - a) } else { None }
+}
+
+fn shr_src(n: usize) -> impl Fn(usize, usize) -> Option<usize> {
+    move |i, a| {
+        if a < n && i + a < n {
+            Some(i + a)
+        } else {
+            None
+        }
+    }
+}
+
+fn rotl_src(n: usize) -> impl Fn(usize, usize) -> Option<usize> {
+    move |i, a| Some((i + n - a % n) % n)
+}
+
+fn rotr_src(n: usize) -> impl Fn(usize, usize) -> Option<usize> {
+    move |i, a| Some((i + a % n) % n)
+}
+
+// 平文のシフト量によるシフトはビットを並べ替えるだけで、bootstrapping を使わない
+pub fn shl<G: Gates>(g: &G, x: &[G::Bit], k: usize) -> Vec<G::Bit> {
+    permute(g, x, k, shl_src(x.len()))
+}
+
+pub fn shr<G: Gates>(g: &G, x: &[G::Bit], k: usize) -> Vec<G::Bit> {
+    permute(g, x, k, shr_src(x.len()))
+}
+
+pub fn rotl<G: Gates>(g: &G, x: &[G::Bit], k: usize) -> Vec<G::Bit> {
+    permute(g, x, k, rotl_src(x.len()))
+}
+
+pub fn rotr<G: Gates>(g: &G, x: &[G::Bit], k: usize) -> Vec<G::Bit> {
+    permute(g, x, k, rotr_src(x.len()))
+}
+
+// 暗号化されたシフト量 s によるシフト. s がビット幅以上なら 0 になる
+pub fn shl_encrypted<G: Gates>(g: &G, x: &[G::Bit], s: &[G::Bit]) -> Vec<G::Bit> {
+    barrel_shift(g, x, s, shl_src(x.len()))
+}
+
+pub fn shr_encrypted<G: Gates>(g: &G, x: &[G::Bit], s: &[G::Bit]) -> Vec<G::Bit> {
+    barrel_shift(g, x, s, shr_src(x.len()))
+}
+
+// 暗号化された回転量 s による回転. s はビット幅で割った余りとして扱われる
+pub fn rotl_encrypted<G: Gates>(g: &G, x: &[G::Bit], s: &[G::Bit]) -> Vec<G::Bit> {
+    barrel_shift(g, x, s, rotl_src(x.len()))
+}
+
+pub fn rotr_encrypted<G: Gates>(g: &G, x: &[G::Bit], s: &[G::Bit]) -> Vec<G::Bit> {
+    barrel_shift(g, x, s, rotr_src(x.len()))
+}
+
+#[derive(Clone)]
+pub struct FheUint<const BITS: usize> {
+    bits: Vec<CipherTLWELv0>,
+    key: Arc<CloudKey>,
+}
+
+impl<const BITS: usize> FheUint<BITS> {
+    pub fn new(bits: Vec<CipherTLWELv0>, key: Arc<CloudKey>) -> Self {
+        assert_eq!(bits.len(), BITS);
+        Self { bits, key }
+    }
+
+    pub fn encrypt(v: u64, tlwe: &TLWE, key: Arc<CloudKey>) -> Self {
+        assert!(BITS <= 64);
+        let bits = (0..BITS).map(|i| tlwe.encrypt((v >> i) & 1 == 1)).collect();
+        Self::new(bits, key)
+    }
+
+    // 別の cloud key で作った値とは演算できない
+    fn check_key(&self, key: &Arc<CloudKey>) {
+        assert!(
+            Arc::ptr_eq(&self.key, key),
+            "operands were created with different cloud keys"
+        );
+    }
+
+    pub fn decrypt(&self, tlwe: &TLWE) -> u64 {
+        let mut v = 0;
+        for (i, &c) in self.bits.iter().enumerate() {
+            v |= (tlwe.decrypt(c) as u64) << i;
+        }
+        v
+    }
+
+    pub fn div_rem(&self, rhs: &Self) -> (Self, Self) {
+        self.check_key(&rhs.key);
+        let (q, r) = div_rem(&*self.key, &self.bits, &rhs.bits);
+        (
+            Self::new(q, self.key.clone()),
+            Self::new(r, self.key.clone()),
+        )
+    }
+
+    pub fn div_rem_scalar(&self, d: u64) -> (Self, Self) {
+        let (q, r) = div_rem_scalar(&*self.key, &self.bits, d);
+        (
+            Self::new(q, self.key.clone()),
+            Self::new(r, self.key.clone()),
+        )
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn eq(&self, rhs: &Self) -> CipherTLWELv0 {
+        self.check_key(&rhs.key);
+        eq(&*self.key, &self.bits, &rhs.bits)
+    }
+
+    pub fn ne(&self, rhs: &Self) -> CipherTLWELv0 {
+        self.check_key(&rhs.key);
+        ne(&*self.key, &self.bits, &rhs.bits)
+    }
+
+    pub fn lt(&self, rhs: &Self) -> CipherTLWELv0 {
+        self.check_key(&rhs.key);
+        lt(&*self.key, &self.bits, &rhs.bits)
+    }
+
+    pub fn le(&self, rhs: &Self) -> CipherTLWELv0 {
+        self.check_key(&rhs.key);
+        le(&*self.key, &self.bits, &rhs.bits)
+    }
+
+    pub fn gt(&self, rhs: &Self) -> CipherTLWELv0 {
+        self.check_key(&rhs.key);
+        gt(&*self.key, &self.bits, &rhs.bits)
+    }
+
+    pub fn ge(&self, rhs: &Self) -> CipherTLWELv0 {
+        self.check_key(&rhs.key);
+        ge(&*self.key, &self.bits, &rhs.bits)
+    }
+
+    pub fn min(&self, rhs: &Self) -> Self {
+        self.check_key(&rhs.key);
+        Self::new(min(&*self.key, &self.bits, &rhs.bits), self.key.clone())
+    }
+
+    pub fn max(&self, rhs: &Self) -> Self {
+        self.check_key(&rhs.key);
+        Self::new(max(&*self.key, &self.bits, &rhs.bits), self.key.clone())
+    }
+
+    pub fn rotl(&self, k: usize) -> Self {
+        Self::new(rotl(&*self.key, &self.bits, k), self.key.clone())
+    }
+
+    pub fn rotr(&self, k: usize) -> Self {
+        Self::new(rotr(&*self.key, &self.bits, k), self.key.clone())
+    }
+
+    pub fn rotl_encrypted<const S: usize>(&self, s: &FheUint<S>) -> Self {
+        self.check_key(&s.key);
+        let bits = rotl_encrypted(&*self.key, &self.bits, &s.bits);
+        Self::new(bits, self.key.clone())
+    }
+
+    pub fn rotr_encrypted<const S: usize>(&self, s: &FheUint<S>) -> Self {
+        self.check_key(&s.key);
+        let bits = rotr_encrypted(&*self.key, &self.bits, &s.bits);
+        Self::new(bits, self.key.clone())
+    }
+
+    pub fn bits(&self) -> &[CipherTLWELv0] {
+        &self.bits
+    }
+
+    pub fn key(&self) -> &Arc<CloudKey> {
+        &self.key
+    }
+}
+
+impl<const BITS: usize> std::ops::Add<&FheUint<BITS>> for &FheUint<BITS> {
+    type Output = FheUint<BITS>;
+    fn add(self, rhs: &FheUint<BITS>) -> FheUint<BITS> {
+        self.check_key(&rhs.key);
+        let bits = add(&*self.key, &self.bits, &rhs.bits);
+        FheUint::new(bits, self.key.clone())
+    }
+}
+
+impl<const BITS: usize> std::ops::Sub<&FheUint<BITS>> for &FheUint<BITS> {
+    type Output = FheUint<BITS>;
+    fn sub(self, rhs: &FheUint<BITS>) -> FheUint<BITS> {
+        self.check_key(&rhs.key);
+        let bits = sub(&*self.key, &self.bits, &rhs.bits);
+        FheUint::new(bits, self.key.clone())
+    }
+}
+
+impl<const BITS: usize> std::ops::Neg for &FheUint<BITS> {
+    type Output = FheUint<BITS>;
+    fn neg(self) -> FheUint<BITS> {
+        let bits = neg(&*self.key, &self.bits);
+        FheUint::new(bits, self.key.clone())
+    }
+}
+
+impl<const BITS: usize> std::ops::Mul<&FheUint<BITS>> for &FheUint<BITS> {
+    type Output = FheUint<BITS>;
+    fn mul(self, rhs: &FheUint<BITS>) -> FheUint<BITS> {
+        self.check_key(&rhs.key);
+        let bits = mul(&*self.key, &self.bits, &rhs.bits);
+        FheUint::new(bits, self.key.clone())
+    }
+}
+
+impl<const BITS: usize> std::ops::Div<&FheUint<BITS>> for &FheUint<BITS> {
+    type Output = FheUint<BITS>;
+    fn div(self, rhs: &FheUint<BITS>) -> FheUint<BITS> {
+        self.div_rem(rhs).0
+    }
+}
+
+impl<const BITS: usize> std::ops::Rem<&FheUint<BITS>> for &FheUint<BITS> {
+    type Output = FheUint<BITS>;
+    fn rem(self, rhs: &FheUint<BITS>) -> FheUint<BITS> {
+        self.div_rem(rhs).1
+    }
+}
+
+impl<const BITS: usize> std::ops::Shl<usize> for &FheUint<BITS> {
+    type Output = FheUint<BITS>;
+    fn shl(self, k: usize) -> FheUint<BITS> {
+        let bits = shl(&*self.key, &self.bits, k);
+        FheUint::new(bits, self.key.clone())
+    }
+}
+
+impl<const BITS: usize> std::ops::Shr<usize> for &FheUint<BITS> {
+    type Output = FheUint<BITS>;
+    fn shr(self, k: usize) -> FheUint<BITS> {
+        let bits = shr(&*self.key, &self.bits, k);
+        FheUint::new(bits, self.key.clone())
+    }
+}
+
+impl<const BITS: usize, const S: usize> std::ops::Shl<&FheUint<S>> for &FheUint<BITS> {
+    type Output = FheUint<BITS>;
+    fn shl(self, s: &FheUint<S>) -> FheUint<BITS> {
+        self.check_key(&s.key);
+        let bits = shl_encrypted(&*self.key, &self.bits, &s.bits);
+        FheUint::new(bits, self.key.clone())
+    }
+}
+
+impl<const BITS: usize, const S: usize> std::ops::Shr<&FheUint<S>> for &FheUint<BITS> {
+    type Output = FheUint<BITS>;
+    fn shr(self, s: &FheUint<S>) -> FheUint<BITS> {
+        self.check_key(&s.key);
+        let bits = shr_encrypted(&*self.key, &self.bits, &s.bits);
+        FheUint::new(bits, self.key.clone())
+    }
+}
+
+#[cfg(test)]
+fn to_bits(v: u64, n: usize) -> Vec<bool> {
+    (0..n).map(|i| (v >> i) & 1 == 1).collect()
+}
+
+#[cfg(test)]
+fn from_bits(bs: &[bool]) -> u64 {
+    bs.iter()
+        .enumerate()
+        .fold(0, |v, (i, &b)| v | ((b as u64) << i))
+}
+
+#[test]
+fn test_add_sub_neg_cleartext() {
+    use super::gates::Cleartext;
+
+    const BITS: usize = 4;
+    const MASK: u64 = (1 << BITS) - 1;
+
+    for x in 0..(1 << BITS) {
+        let xs = to_bits(x, BITS);
+        assert_eq!(from_bits(&neg(&Cleartext, &xs)), x.wrapping_neg() & MASK);
+
+        for y in 0..(1 << BITS) {
+            let ys = to_bits(y, BITS);
+            assert_eq!(from_bits(&add(&Cleartext, &xs, &ys)), (x + y) & MASK);
+            assert_eq!(
+                from_bits(&sub(&Cleartext, &xs, &ys)),
+                x.wrapping_sub(y) & MASK
+            );
+        }
+    }
+}
+
+#[test]
+fn test_mul_div_rem_cleartext() {
+    use super::gates::Cleartext;
+
+    const BITS: usize = 4;
+    const MASK: u64 = (1 << BITS) - 1;
+
+    for x in 0..(1 << BITS) {
+        let xs = to_bits(x, BITS);
+        for y in 0..(1 << BITS) {
+            let ys = to_bits(y, BITS);
+            assert_eq!(from_bits(&mul(&Cleartext, &xs, &ys)), (x * y) & MASK);
+
+            let (q, r) = div_rem(&Cleartext, &xs, &ys);
+            let (q, r) = (from_bits(&q), from_bits(&r));
+            match (x.checked_div(y), x.checked_rem(y)) {
+                (Some(qq), Some(rr)) => {
+                    assert_eq!((q, r), (qq, rr), "x: {}, y: {}", x, y);
+
+                    let (q, r) = div_rem_scalar(&Cleartext, &xs, y);
+                    let (q, r) = (from_bits(&q), from_bits(&r));
+                    assert_eq!((q, r), (qq, rr), "x: {}, d: {}", x, y);
+                }
+                _ => assert_eq!((q, r), (MASK, x)),
+            }
+        }
+    }
+}
+
+#[test]
+fn test_compare_cleartext() {
+    use super::gates::Cleartext;
+
+    const BITS: usize = 4;
+
+    for x in 0..(1 << BITS) {
+        let xs = to_bits(x, BITS);
+        for y in 0..(1 << BITS) {
+            let ys = to_bits(y, BITS);
+            let g = &Cleartext;
+            assert_eq!(eq(g, &xs, &ys), x == y);
+            assert_eq!(ne(g, &xs, &ys), x != y);
+            assert_eq!(lt(g, &xs, &ys), x < y);
+            assert_eq!(le(g, &xs, &ys), x <= y);
+            assert_eq!(gt(g, &xs, &ys), x > y);
+            assert_eq!(ge(g, &xs, &ys), x >= y);
+            assert_eq!(from_bits(&min(g, &xs, &ys)), x.min(y));
+            assert_eq!(from_bits(&max(g, &xs, &ys)), x.max(y));
+        }
+    }
+
+    // 0 ビットの値は等しい
+    let g = &Cleartext;
+    assert!(eq(g, &[], &[]) && le(g, &[], &[]) && ge(g, &[], &[]));
+    assert!(!ne(g, &[], &[]) && !lt(g, &[], &[]) && !gt(g, &[], &[]));
+}
+
+#[test]
+fn test_compare_depth() {
+    // Bit に bootstrapping の段数を持たせて数える
+    struct Depth;
+
+    impl Gates for Depth {
+        type Bit = usize;
+
+        fn constant(&self, _: bool) -> usize {
+            0
+        }
+
+        fn nand(&self, x: &usize, y: &usize) -> usize {
+            x.max(y) + 1
+        }
+
+        fn and(&self, x: &usize, y: &usize) -> usize {
+            x.max(y) + 1
+        }
+
+        fn or(&self, x: &usize, y: &usize) -> usize {
+            x.max(y) + 1
+        }
+
+        fn xor(&self, x: &usize, y: &usize) -> usize {
+            x.max(y) + 1
+        }
+
+        fn not(&self, x: &usize) -> usize {
+            *x
+        }
+
+        fn mux(&self, c: &usize, x: &usize, y: &usize) -> usize {
+            c.max(x).max(y) + 1
+        }
+    }
+
+    const BITS: usize = 16;
+    let xs = [0; BITS];
+    let ys = [0; BITS];
+    assert_eq!(eq(&Depth, &xs, &ys), 1 + 4);
+    assert_eq!(lt(&Depth, &xs, &ys), 1 + 4);
+}
+
+#[test]
+fn test_shift_rotate_cleartext() {
+    use super::gates::Cleartext;
+
+    const BITS: usize = 4;
+    const MASK: u64 = (1 << BITS) - 1;
+
+    let shl_ref = |x: u64, k: u64| if k < 4 { (x << k) & MASK } else { 0 };
+    let shr_ref = |x: u64, k: u64| if k < 4 { x >> k } else { 0 };
+    let rotl_ref = |x: u64, k: u64| ((x << (k % 4)) | (x >> (4 - k % 4))) & MASK;
+    let rotr_ref = |x: u64, k: u64| ((x >> (k % 4)) | (x << (4 - k % 4))) & MASK;
+
+    for x in 0..(1 << BITS) {
+        let xs = to_bits(x, BITS);
+        for k in 0..(1 << BITS) {
+            let ks = to_bits(k, BITS);
+            let g = &Cleartext;
+
+            assert_eq!(from_bits(&shl(g, &xs, k as usize)), shl_ref(x, k));
+            assert_eq!(from_bits(&shr(g, &xs, k as usize)), shr_ref(x, k));
+            assert_eq!(from_bits(&rotl(g, &xs, k as usize)), rotl_ref(x, k));
+            assert_eq!(from_bits(&rotr(g, &xs, k as usize)), rotr_ref(x, k));
+
+            assert_eq!(from_bits(&shl_encrypted(g, &xs, &ks)), shl_ref(x, k));
+            assert_eq!(from_bits(&shr_encrypted(g, &xs, &ks)), shr_ref(x, k));
+            assert_eq!(from_bits(&rotl_encrypted(g, &xs, &ks)), rotl_ref(x, k));
+            assert_eq!(from_bits(&rotr_encrypted(g, &xs, &ks)), rotr_ref(x, k));
+        }
+    }
+}
+
+#[test]
+fn test_fhe_uint() {
+    use super::key::SecretKey;
+
+    const BITS: usize = 2;
+
+    let sk = SecretKey::new();
+    let tlwe = TLWE::new(sk);
+    let ck = Arc::new(CloudKey::new(sk));
+
+    let (x, y) = (3, 2);
+    let cx = FheUint::<BITS>::encrypt(x, &tlwe, ck.clone());
+    let cy = FheUint::<BITS>::encrypt(y, &tlwe, ck);
+
+    assert_eq!(cx.decrypt(&tlwe), x);
+    assert_eq!((&cx + &cy).decrypt(&tlwe), (x + y) % 4);
+    assert_eq!((-&cx).decrypt(&tlwe), x.wrapping_neg() % 4);
+    assert_eq!((&cx * &cy).decrypt(&tlwe), (x * y) % 4);
+}
+
+#[test]
+fn test_fhe_uint_add_sub_neg() {
+    use super::key::SecretKey;
+
+    let sk = SecretKey::new();
+    let tlwe = TLWE::new(sk);
+    let ck = Arc::new(CloudKey::new(sk));
+
+    // 桁上がりと桁借りで 2 ビットを一周する組
+    for (x, y) in [(3, 1), (1, 2), (0, 3)] {
+        let cx = FheUint::<2>::encrypt(x, &tlwe, ck.clone());
+        let cy = FheUint::<2>::encrypt(y, &tlwe, ck.clone());
+        assert_eq!((&cx + &cy).decrypt(&tlwe), (x + y) % 4, "{} + {}", x, y);
+        assert_eq!((&cx - &cy).decrypt(&tlwe), (x + 4 - y) % 4, "{} - {}", x, y);
+        assert_eq!((-&cx).decrypt(&tlwe), (4 - x) % 4, "-{}", x);
+    }
+}
+
+#[test]
+#[should_panic(expected = "different cloud keys")]
+fn test_fhe_uint_different_keys() {
+    use super::key::SecretKey;
+
+    // 鍵生成は重いので、別の Arc に複製したものを別の鍵とみなす
+    let sk = SecretKey::new();
+    let tlwe = TLWE::new(sk);
+    let ck = CloudKey::new(sk);
+    let cx = FheUint::<1>::encrypt(0, &tlwe, Arc::new(ck.clone()));
+    let cy = FheUint::<1>::encrypt(0, &tlwe, Arc::new(ck));
+    let _ = &cx + &cy;
+}
+
+// 4 ビットのすべての組を u8 の wrapping 演算と比べる. FFT を使っていないので時間がかかる
+// (cargo test --release -- --ignored test_fhe_uint_add_sub_neg_exhaustive)
+#[test]
+#[ignore]
+fn test_fhe_uint_add_sub_neg_exhaustive() {
+    use super::key::SecretKey;
+
+    const BITS: usize = 4;
+    const MASK: u8 = (1 << BITS) - 1;
+
+    let sk = SecretKey::new();
+    let tlwe = TLWE::new(sk);
+    let ck = Arc::new(CloudKey::new(sk));
+
+    let cs: Vec<FheUint<BITS>> = (0..(1 << BITS))
+        .map(|v| FheUint::encrypt(v, &tlwe, ck.clone()))
+        .collect();
+    for x in 0..(1u8 << BITS) {
+        let cx = &cs[x as usize];
+        let neg = x.wrapping_neg() & MASK;
+        assert_eq!((-cx).decrypt(&tlwe), neg as u64, "-{}", x);
+        for y in 0..(1u8 << BITS) {
+            let cy = &cs[y as usize];
+            let sum = x.wrapping_add(y) & MASK;
+            let diff = x.wrapping_sub(y) & MASK;
+            assert_eq!((cx + cy).decrypt(&tlwe), sum as u64, "{} + {}", x, y);
+            assert_eq!((cx - cy).decrypt(&tlwe), diff as u64, "{} - {}", x, y);
+        }
+    }
+}
+
+#[test]
+fn test_fhe_uint_mul_div_rem() {
+    use super::gates::Cleartext;
+    use super::key::SecretKey;
+
+    let sk = SecretKey::new();
+    let tlwe = TLWE::new(sk);
+    let ck = Arc::new(CloudKey::new(sk));
+
+    // 0 での除算は平文の回路と同じ結果になる. 除数が被除数より大きい組も含める
+    for (x, y) in [(3, 2), (2, 0), (1, 3)] {
+        let (xs, ys) = (to_bits(x, 2), to_bits(y, 2));
+        let cx = FheUint::<2>::encrypt(x, &tlwe, ck.clone());
+        let cy = FheUint::<2>::encrypt(y, &tlwe, ck.clone());
+        assert_eq!((&cx * &cy).decrypt(&tlwe), (x * y) % 4, "{} * {}", x, y);
+
+        let (q, r) = div_rem(&Cleartext, &xs, &ys);
+        let (cq, cr) = cx.div_rem(&cy);
+        assert_eq!(
+            (cq.decrypt(&tlwe), cr.decrypt(&tlwe)),
+            (from_bits(&q), from_bits(&r)),
+            "{} / {}",
+            x,
+            y
+        );
+    }
+
+    // 2 の冪とそれ以外. 被除数より大きい 2 の冪も含める
+    for (x, d) in [(3, 2), (3, 3), (1, 2)] {
+        let cx = FheUint::<2>::encrypt(x, &tlwe, ck.clone());
+        let (cq, cr) = cx.div_rem_scalar(d);
+        assert_eq!(
+            (cq.decrypt(&tlwe), cr.decrypt(&tlwe)),
+            (x / d, x % d),
+            "{} / {} (scalar)",
+            x,
+            d
+        );
+    }
+}
+
+// 4 ビットのすべての組を平文の回路と比べる. 0 での除算も含む
+// 1 回の bootstrapping に数秒かかるので数日かかる. 既定では走らないので、暗号の実装を変えたときに
+// cargo test --release -- --ignored test_fhe_uint_mul_div_rem_exhaustive で確かめる
+#[test]
+#[ignore]
+fn test_fhe_uint_mul_div_rem_exhaustive() {
+    use super::gates::Cleartext;
+    use super::key::SecretKey;
+
+    const BITS: usize = 4;
+
+    let sk = SecretKey::new();
+    let tlwe = TLWE::new(sk);
+    let ck = Arc::new(CloudKey::new(sk));
+
+    let cs: Vec<FheUint<BITS>> = (0..(1 << BITS))
+        .map(|v| FheUint::encrypt(v, &tlwe, ck.clone()))
+        .collect();
+    for x in 0..(1u64 << BITS) {
+        let (cx, xs) = (&cs[x as usize], to_bits(x, BITS));
+        for y in 0..(1u64 << BITS) {
+            let (cy, ys) = (&cs[y as usize], to_bits(y, BITS));
+
+            let expected = from_bits(&mul(&Cleartext, &xs, &ys));
+            assert_eq!((cx * cy).decrypt(&tlwe), expected, "{} * {}", x, y);
+
+            let (q, r) = div_rem(&Cleartext, &xs, &ys);
+            let (cq, cr) = cx.div_rem(cy);
+            assert_eq!(
+                (cq.decrypt(&tlwe), cr.decrypt(&tlwe)),
+                (from_bits(&q), from_bits(&r)),
+                "{} / {}",
+                x,
+                y
+            );
+
+            if y != 0 {
+                let (q, r) = div_rem_scalar(&Cleartext, &xs, y);
+                let (cq, cr) = cx.div_rem_scalar(y);
+                assert_eq!(
+                    (cq.decrypt(&tlwe), cr.decrypt(&tlwe)),
+                    (from_bits(&q), from_bits(&r)),
+                    "{} / {} (scalar)",
+                    x,
+                    y
+                );
+            }
+        }
+    }
+}
+
+// 2 の冪による除算は bootstrapping を使わないので、4 ビットのすべての被除数で確かめる
+#[test]
+fn test_fhe_uint_div_rem_scalar_power_of_two_exhaustive() {
+    use super::key::SecretKey;
+
+    const BITS: usize = 4;
+
+    let sk = SecretKey::new();
+    let tlwe = TLWE::new(sk);
+    let ck = Arc::new(CloudKey::new(sk));
+
+    for x in 0..(1u64 << BITS) {
+        let cx = FheUint::<BITS>::encrypt(x, &tlwe, ck.clone());
+        for d in [1, 2, 4, 8, 16, 32] {
+            let (cq, cr) = cx.div_rem_scalar(d);
+            assert_eq!(
+                (cq.decrypt(&tlwe), cr.decrypt(&tlwe)),
+                (x / d, x % d),
+                "{} / {}",
+                x,
+                d
+            );
+        }
+    }
+}
+
+#[test]
+fn test_fhe_uint_compare() {
+    use super::key::SecretKey;
+
+    let sk = SecretKey::new();
+    let tlwe = TLWE::new(sk);
+    let ck = Arc::new(CloudKey::new(sk));
+
+    // 3 ビットで木の組み合わせが効くように、上位が等しく下位で決まる組と等しい組
+    for (x, y) in [(5, 6), (6, 6)] {
+        let cx = FheUint::<3>::encrypt(x, &tlwe, ck.clone());
+        let cy = FheUint::<3>::encrypt(y, &tlwe, ck.clone());
+
+        assert_eq!(tlwe.decrypt(cx.eq(&cy)), x == y, "{} == {}", x, y);
+        assert_eq!(tlwe.decrypt(cx.lt(&cy)), x < y, "{} < {}", x, y);
+        assert_eq!(tlwe.decrypt(cx.gt(&cy)), x > y, "{} > {}", x, y);
+        assert_eq!(cx.max(&cy).decrypt(&tlwe), x.max(y));
+    }
+}
+
+#[test]
+fn test_fhe_uint_shift() {
+    use super::key::SecretKey;
+
+    let sk = SecretKey::new();
+    let tlwe = TLWE::new(sk);
+    let ck = Arc::new(CloudKey::new(sk));
+
+    const MASK: u64 = 0b11;
+    let rotl_ref = |x: u64, k: u64| ((x << k) | (x >> (2 - k))) & MASK;
+    let rotr_ref = |x: u64, k: u64| ((x >> k) | (x << (2 - k))) & MASK;
+
+    // 2 ビットのすべての値と、平文および 1 ビットの暗号文で表せるすべての移動量
+    for x in 0..4 {
+        let cx = FheUint::<2>::encrypt(x, &tlwe, ck.clone());
+        for k in 0..3 {
+            let n = k as usize;
+            assert_eq!((&cx << n).decrypt(&tlwe), (x << k) & MASK, "{} << {}", x, k);
+            assert_eq!((&cx >> n).decrypt(&tlwe), x >> k, "{} >> {}", x, k);
+            assert_eq!(cx.rotl(n).rotr(n).decrypt(&tlwe), x, "rot {} {}", x, k);
+        }
+        for k in 0..2 {
+            let cs = FheUint::<1>::encrypt(k, &tlwe, ck.clone());
+            assert_eq!(
+                (&cx << &cs).decrypt(&tlwe),
+                (x << k) & MASK,
+                "{} << {}",
+                x,
+                k
+            );
+            assert_eq!((&cx >> &cs).decrypt(&tlwe), x >> k, "{} >> {}", x, k);
+            assert_eq!(cx.rotl_encrypted(&cs).decrypt(&tlwe), rotl_ref(x, k));
+            assert_eq!(cx.rotr_encrypted(&cs).decrypt(&tlwe), rotr_ref(x, k));
+        }
+    }
+
+    // 2 ビットの移動量 3 で、バレルシフタの両方の段を通す
+    let cx = FheUint::<4>::encrypt(0b1011, &tlwe, ck.clone());
+    let cs = FheUint::<2>::encrypt(3, &tlwe, ck);
+    assert_eq!((&cx << &cs).decrypt(&tlwe), 0b1000);
+    assert_eq!((&cx >> &cs).decrypt(&tlwe), 0b0001);
+    assert_eq!(cx.rotl_encrypted(&cs).decrypt(&tlwe), 0b1101);
+    assert_eq!(cx.rotr_encrypted(&cs).decrypt(&tlwe), 0b0111);
+}
+
+#[test]
+#[should_panic(expected = "different cloud keys")]
+fn test_fhe_uint_shift_different_keys() {
+    use super::key::SecretKey;
+
+    let sk = SecretKey::new();
+    let tlwe = TLWE::new(sk);
+    let ck = CloudKey::new(sk);
+    let cx = FheUint::<2>::encrypt(1, &tlwe, Arc::new(ck.clone()));
+    let cs = FheUint::<1>::encrypt(1, &tlwe, Arc::new(ck));
+    let _ = &cx << &cs;
+}
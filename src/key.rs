@@ -1,17 +1,30 @@
-use super::sampling::ndim_bin_uniform;
-use super::util::{RingLv0, RingLv1};
+use super::bootstrapping::{bootstrapping_key, BootstrappingKey};
+use super::key_switching::KeySwitchingKey;
+use super::params::trlwe;
+use super::sampling::{ndim_bin_uniform, ndim_discrete_normal_dist, ndim_ternary_uniform};
+use super::util::{RingLv0, RingLv1, Torus};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyDistribution {
+    // {0, 1}
+    Binary,
+    // {-1, 0, 1}
+    Ternary,
+    // 丸めた正規分布 (標準偏差 params::trlwe::KEY_SIGMA)
+    Gaussian,
+}
 
 #[derive(Clone, Copy, Debug)]
 pub struct SecretKey {
     pub lv0: RingLv0,
     pub lv1: RingLv1,
+    pub lv0_dist: KeyDistribution,
+    pub lv1_dist: KeyDistribution,
 }
 
 impl Default for SecretKey {
     fn default() -> Self {
-        let lv0 = ndim_bin_uniform();
-        let lv1 = ndim_bin_uniform();
-        Self { lv0, lv1 }
+        Self::with_distribution(KeyDistribution::Binary, KeyDistribution::Binary)
     }
 }
 
@@ -19,4 +32,61 @@ impl SecretKey {
     pub fn new() -> Self {
         Self::default()
     }
+
+    pub fn with_distribution(lv0_dist: KeyDistribution, lv1_dist: KeyDistribution) -> Self {
+        // blind rotation は lv0 の係数ごとに TRGSW を持つので、有限個の値しか扱えない
+        assert!(
+            lv0_dist != KeyDistribution::Gaussian,
+            "lv0 key must be binary or ternary"
+        );
+
+        let lv0 = sample_key(lv0_dist);
+        let lv1 = sample_key(lv1_dist);
+        Self {
+            lv0,
+            lv1,
+            lv0_dist,
+            lv1_dist,
+        }
+    }
+}
+
+// 評価側に渡す公開可能な鍵
+#[derive(Clone, Debug)]
+pub struct CloudKey {
+    pub bk: BootstrappingKey,
+    pub ks: KeySwitchingKey,
+}
+
+impl CloudKey {
+    pub fn new(sk: SecretKey) -> Self {
+        let bk = bootstrapping_key(sk);
+        let ks = KeySwitchingKey::new(sk);
+        Self { bk, ks }
+    }
+}
+
+fn sample_key<const N: usize>(dist: KeyDistribution) -> [Torus; N] {
+    match dist {
+        KeyDistribution::Binary => ndim_bin_uniform(),
+        KeyDistribution::Ternary => ndim_ternary_uniform(),
+        KeyDistribution::Gaussian => ndim_discrete_normal_dist(trlwe::KEY_SIGMA),
+    }
+}
+
+#[test]
+fn test_key_distribution() {
+    let sk = SecretKey::with_distribution(KeyDistribution::Ternary, KeyDistribution::Gaussian);
+
+    for s in sk.lv0 {
+        let s = s as i32;
+        assert!((-1..=1).contains(&s), "lv0: {}", s);
+    }
+
+    // 6σ を超える係数はまず出ない
+    let bound = (6. * trlwe::KEY_SIGMA) as i32;
+    for s in sk.lv1 {
+        let s = s as i32;
+        assert!(-bound <= s && s <= bound, "lv1: {}", s);
+    }
 }
@@ -1,7 +1,9 @@
+use super::error::KfheError;
 use super::key::SecretKey;
 use super::params::{tlwe, trgsw};
 use super::tlwe::{CipherTLWELv0, CipherTLWELv1, TLWE};
-use super::util::{float_to_torus, RingLv0, Torus};
+use super::trlwe::{CipherTRLWE, TRLWE};
+use super::util::{float_to_torus, torus_negative, RingLv0, RingLv1, Torus};
 
 const N: usize = trgsw::N;
 const T: usize = trgsw::T;
@@ -13,17 +15,20 @@ const K: usize = 1 << BASEBIT;
 pub struct KeySwitchingKey(pub Vec<CipherTLWELv0>);
 
 impl KeySwitchingKey {
-    fn new(sk: SecretKey) -> Self {
+    // 暗号文の個数
+    pub const SIZE: usize = (K - 1) * T * N;
+
+    pub fn new(sk: SecretKey) -> Self {
         let s = sk.lv1;
         let tlwe = TLWE::new(sk);
 
-        let mut v = vec![CipherTLWELv0::empty(); (K - 1) * T * N];
+        let mut v = vec![CipherTLWELv0::empty(); Self::SIZE];
 
         for k in 1..K {
             for j in 0..T {
                 for i in 0..N {
-                    let base = 1 << (j as Torus + 1) * BASEBIT;
-                    let msg = (k as f64 * s[i] as f64) / base as f64;
+                    let base = 1 << ((j as Torus + 1) * BASEBIT);
+                    let msg = (k as f64 * s[i] as i32 as f64) / base as f64;
                     // let msg = if msg >= 0.5 { msg - 1.0 } else { msg };
                     let msg = float_to_torus(msg);
                     v[i + j * N + (k - 1) * N * T] = tlwe.encrypt_torus(msg);
@@ -31,23 +36,61 @@ impl KeySwitchingKey {
             }
         }
 
-        assert_eq!(v.len(), (K - 1) * T * N);
+        assert_eq!(v.len(), Self::SIZE);
 
         Self(v)
     }
 
-    fn access(&self, i: usize, j: usize, k: usize) -> CipherTLWELv0 {
-        assert!(i < N);
-        assert!(j < T);
-        assert!(0 < k && k < K);
-
-        self.0[i + j * N + (k - 1) * N * T]
+    fn access(&self, i: usize, j: usize, k: usize) -> Result<CipherTLWELv0, KfheError> {
+        check_indices(i, N, j, k)?;
+        self.0
+            .get(i + j * N + (k - 1) * N * T)
+            .copied()
+            .ok_or(KfheError::KeySize {
+                what: "key-switching key",
+                expected: Self::SIZE,
+                found: self.0.len(),
+            })
     }
 }
 
+// i は係数, j は桁, k はその桁の値 (0 は鍵に含めない)
+fn check_indices(i: usize, n: usize, j: usize, k: usize) -> Result<(), KfheError> {
+    let check = |what, index, ok: bool, len| {
+        if ok {
+            Ok(())
+        } else {
+            Err(KfheError::IndexOutOfRange { what, index, len })
+        }
+    };
+    check("key-switching coefficient", i, i < n, n)?;
+    check("key-switching digit", j, j < T, T)?;
+    check("key-switching digit value", k, 0 < k && k < K, K)
+}
+
 pub fn identity_key_switching(c: CipherTLWELv1, sk: SecretKey) -> CipherTLWELv0 {
-    let (a, b) = c.describe();
     let ks = KeySwitchingKey::new(sk);
+    identity_key_switching_with_key(c, &ks)
+}
+
+pub fn identity_key_switching_with_key(c: CipherTLWELv1, ks: &KeySwitchingKey) -> CipherTLWELv0 {
+    try_identity_key_switching_with_key(c, ks).unwrap_or_else(|e| panic!("{}", e))
+}
+
+// 鍵の大きさが合わなければエラー
+#[allow(clippy::needless_range_loop)]
+pub fn try_identity_key_switching_with_key(
+    c: CipherTLWELv1,
+    ks: &KeySwitchingKey,
+) -> Result<CipherTLWELv0, KfheError> {
+    if ks.0.len() != KeySwitchingKey::SIZE {
+        return Err(KfheError::KeySize {
+            what: "key-switching key",
+            expected: KeySwitchingKey::SIZE,
+            found: ks.0.len(),
+        });
+    }
+    let (a, b) = c.describe();
 
     let a0: RingLv0 = [0; tlwe::N];
     let mut c0 = CipherTLWELv0(a0, b);
@@ -60,12 +103,94 @@ pub fn identity_key_switching(c: CipherTLWELv1, sk: SecretKey) -> CipherTLWELv0
             let shift = 32 - (j + 1) * BASEBIT as usize;
             let k = (ai_ >> shift) as usize % K;
             if k != 0 {
-                c0 = c0 - ks.access(i, j, k);
+                c0 = c0 - ks.access(i, j, k)?;
+            }
+        }
+    }
+
+    Ok(c0)
+}
+
+// f は Z 線形 (f(k * x) = k * f(x)) である必要がある
+#[derive(Clone, Debug)]
+pub struct PrivateKeySwitchingKey(pub Vec<CipherTRLWE>);
+
+impl PrivateKeySwitchingKey {
+    pub fn new<F: Fn(Torus) -> RingLv1>(sk: SecretKey, f: F) -> Self {
+        let s = sk.lv1;
+        let trlwe = TRLWE::new(sk);
+
+        let zero = CipherTRLWE([0; N], [0; N]);
+        let mut v = vec![zero; Self::SIZE];
+
+        for k in 1..K {
+            for j in 0..T {
+                // i == N は b に対応し、その秘密鍵を -1 とみなす
+                for i in 0..=N {
+                    let si = if i < N { s[i] } else { torus_negative(1) };
+                    let shift = 32 - (j as Torus + 1) * BASEBIT;
+                    let msg = (k as Torus).wrapping_mul(si).wrapping_mul(1 << shift);
+                    v[i + j * (N + 1) + (k - 1) * (N + 1) * T] = trlwe.encrypt_torus(f(msg));
+                }
+            }
+        }
+
+        assert_eq!(v.len(), Self::SIZE);
+
+        Self(v)
+    }
+
+    // 暗号文の個数
+    pub const SIZE: usize = (K - 1) * T * (N + 1);
+
+    fn access(&self, i: usize, j: usize, k: usize) -> Result<CipherTRLWE, KfheError> {
+        check_indices(i, N + 1, j, k)?;
+        self.0
+            .get(i + j * (N + 1) + (k - 1) * (N + 1) * T)
+            .copied()
+            .ok_or(KfheError::KeySize {
+                what: "private key-switching key",
+                expected: Self::SIZE,
+                found: self.0.len(),
+            })
+    }
+}
+
+pub fn private_key_switching(c: CipherTLWELv1, pks: &PrivateKeySwitchingKey) -> CipherTRLWE {
+    try_private_key_switching(c, pks).unwrap_or_else(|e| panic!("{}", e))
+}
+
+#[allow(clippy::needless_range_loop)]
+pub fn try_private_key_switching(
+    c: CipherTLWELv1,
+    pks: &PrivateKeySwitchingKey,
+) -> Result<CipherTRLWE, KfheError> {
+    if pks.0.len() != PrivateKeySwitchingKey::SIZE {
+        return Err(KfheError::KeySize {
+            what: "private key-switching key",
+            expected: PrivateKeySwitchingKey::SIZE,
+            found: pks.0.len(),
+        });
+    }
+    let (a, b) = c.describe();
+
+    let mut c0 = CipherTRLWE([0; N], [0; N]);
+
+    let offset: Torus = 1 << (31 - T * BASEBIT as usize);
+
+    for i in 0..=N {
+        let ai = if i < N { a[i] } else { b };
+        let ai_ = ai.wrapping_add(offset);
+        for j in 0..T {
+            let shift = 32 - (j + 1) * BASEBIT as usize;
+            let k = (ai_ >> shift) as usize % K;
+            if k != 0 {
+                c0 = c0 - pks.access(i, j, k)?;
             }
         }
     }
 
-    c0
+    Ok(c0)
 }
 
 #[test]
@@ -90,3 +215,88 @@ fn test_identity_key_switching() {
 
     assert!(count == 0, "count: {}", count);
 }
+
+#[test]
+fn test_private_key_switching() {
+    use super::tlwe::TLWELv1;
+    use super::util::torus_to_float;
+
+    let sk = SecretKey::new();
+    let tlwe1 = TLWELv1::new(sk);
+    let trlwe = TRLWE::new(sk);
+
+    // f(x) = (1 + X^3) * x. 定数項と X^3 の係数で恒等写像と単項式の両方を確かめる
+    fn identity_and_monomial(x: Torus) -> RingLv1 {
+        let mut r = [0; N];
+        r[0] = x;
+        r[3] = x;
+        r
+    }
+
+    // f(x) = -s(X) * x (回路ブートストラッピングで使う)
+    let s = sk.lv1;
+    let minus_secret = move |x: Torus| {
+        let mut r = [0; N];
+        for i in 0..N {
+            r[i] = torus_negative(s[i].wrapping_mul(x));
+        }
+        r
+    };
+
+    // 鍵の生成が重いので、鍵ごとにいくつかの入力を試す
+    let funcs: [&dyn Fn(Torus) -> RingLv1; 2] = [&identity_and_monomial, &minus_secret];
+    for f in funcs {
+        let pks = PrivateKeySwitchingKey::new(sk, f);
+
+        for x in [0.125, -0.125, 0.25, -0.375] {
+            let m = float_to_torus(x);
+            let c = tlwe1.encrypt_torus(m);
+            let c_ = private_key_switching(c, &pks);
+
+            let expected = f(m);
+            let actual = trlwe.decrypt_torus(c_);
+            for i in 0..N {
+                let diff = torus_to_float(actual[i].wrapping_sub(expected[i]));
+                assert!(
+                    diff.abs() < 1. / 64.,
+                    "x: {}, index: {}, diff: {}",
+                    x,
+                    i,
+                    diff
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn test_try_key_switching() {
+    use super::trlwe::try_sample_extract_index;
+
+    // 外から受け取った大きさの合わない鍵
+    let c = CipherTLWELv1([1; N], 2);
+    let ks = KeySwitchingKey(vec![CipherTLWELv0::empty(); 10]);
+    assert_eq!(
+        try_identity_key_switching_with_key(c, &ks).err(),
+        Some(KfheError::KeySize {
+            what: "key-switching key",
+            expected: KeySwitchingKey::SIZE,
+            found: 10,
+        })
+    );
+    let pks = PrivateKeySwitchingKey(vec![]);
+    assert!(try_private_key_switching(c, &pks).is_err());
+    assert!(check_indices(N, N, 0, 1).is_err());
+    assert!(check_indices(0, N, 0, K).is_err());
+
+    let c = CipherTRLWE([0; N], [0; N]);
+    assert!(try_sample_extract_index(c, N - 1).is_ok());
+    assert_eq!(
+        try_sample_extract_index(c, N).err(),
+        Some(KfheError::IndexOutOfRange {
+            what: "TRLWE coefficient",
+            index: N,
+            len: N,
+        })
+    );
+}
@@ -1,11 +1,27 @@
+pub mod bit;
+pub mod boolean;
 pub mod bootstrapping;
+pub mod bristol;
+pub mod bundle;
+pub mod circuit;
+pub mod error;
+pub mod estimate;
+pub mod gates;
 pub mod homnand;
+pub mod integer;
 pub mod key;
 pub mod key_switching;
 pub mod ops;
+pub mod optimizer;
 pub mod params;
 pub mod sampling;
+pub mod scheduler;
+pub mod serialize;
+pub mod server;
+pub mod stream;
 pub mod tlwe;
+pub mod trace;
 pub mod trgsw;
 pub mod trlwe;
 pub mod util;
+pub mod yosys;
@@ -1,12 +1,10 @@
-use kfhe::tlwe::tlwe_nand;
+mod cli;
 
 fn main() {
-    for i in 0..10 {
-        println!("> loop {}", i);
-        println!("{}", tlwe_nand(true, true));
-        println!("{}", tlwe_nand(true, false));
-        println!("{}", tlwe_nand(false, true));
-        println!("{}", tlwe_nand(false, false));
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Err(e) = cli::run(&args) {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
     }
 }
 
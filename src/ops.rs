@@ -51,6 +51,19 @@ pub fn rmadd<const L: usize, const M: usize, const N: usize>(
     c
 }
 
+pub fn rmsub<const L: usize, const M: usize, const N: usize>(
+    a: [[[Torus; N]; M]; L],
+    b: [[[Torus; N]; M]; L],
+) -> [[[Torus; N]; M]; L] {
+    let mut c = [[[0; N]; M]; L];
+    for l in 0..L {
+        for m in 0..M {
+            c[l][m] = vsub(a[l][m], b[l][m]);
+        }
+    }
+    c
+}
+
 pub fn rdot<const L: usize, const N: usize>(v: [[Torus; N]; L], w: [[Torus; N]; L]) -> [Torus; N] {
     let mut s: [Torus; N] = [0; N];
     for l in 0..L {
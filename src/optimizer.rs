@@ -0,0 +1,413 @@
+// 評価の前に回路を小さくする. コストはほぼ bootstrapping の回数で決まるのでそれを減らしたい
+//
+// simplify: 定数の畳み込み、共通部分式の削除、NOT を次のゲートの線形結合に吸収 (ANDNY/ORYN)
+// remove_dead_gates: 出力に繋がらないゲートの削除
+
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Not;
+
+use super::circuit::{Circuit, Gate, NodeId};
+
+// 書き換え中の値. 定数か、新しい回路のノード (とその否定)
+// 否定はなるべくノードにせず、使う側のゲートに吸収させる
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Lit {
+    Const(bool),
+    Node(NodeId, bool),
+}
+
+impl Not for Lit {
+    type Output = Lit;
+
+    fn not(self) -> Lit {
+        match self {
+            Lit::Const(b) => Lit::Const(!b),
+            Lit::Node(x, neg) => Lit::Node(x, !neg),
+        }
+    }
+}
+
+fn add_gate(circuit: &mut Circuit, gate: Gate) -> NodeId {
+    match gate {
+        Gate::Input(_) => circuit.input(),
+        Gate::Const(b) => circuit.constant(b),
+        Gate::Nand(x, y) => circuit.nand(x, y),
+        Gate::And(x, y) => circuit.and(x, y),
+        Gate::Or(x, y) => circuit.or(x, y),
+        Gate::Xor(x, y) => circuit.xor(x, y),
+        Gate::AndNY(x, y) => circuit.andny(x, y),
+        Gate::OrYN(x, y) => circuit.oryn(x, y),
+        Gate::Not(x) => circuit.not(x),
+        Gate::Mux(c, x, y) => circuit.mux(c, x, y),
+    }
+}
+
+struct Builder {
+    circuit: Circuit,
+    // 同じゲートは一度だけ作る
+    cache: HashMap<Gate, NodeId>,
+}
+
+impl Builder {
+    fn emit(&mut self, gate: Gate) -> NodeId {
+        if let Some(&id) = self.cache.get(&gate) {
+            return id;
+        }
+        let id = add_gate(&mut self.circuit, gate);
+        self.cache.insert(gate, id);
+        id
+    }
+
+    fn lit(&mut self, gate: Gate) -> Lit {
+        Lit::Node(self.emit(gate), false)
+    }
+
+    // 否定や定数をノードとして実体化する
+    fn node(&mut self, l: Lit) -> NodeId {
+        match l {
+            Lit::Const(b) => self.emit(Gate::Const(b)),
+            Lit::Node(x, false) => x,
+            Lit::Node(x, true) => self.emit(Gate::Not(x)),
+        }
+    }
+
+    fn and(&mut self, x: Lit, y: Lit) -> Lit {
+        match (x, y) {
+            (Lit::Const(false), _) | (_, Lit::Const(false)) => Lit::Const(false),
+            (Lit::Const(true), z) | (z, Lit::Const(true)) => z,
+            (Lit::Node(a, _), Lit::Node(b, _)) if a == b => {
+                if x == y {
+                    x
+                } else {
+                    Lit::Const(false)
+                }
+            }
+            // 可換なので番号順に並べておくと共通部分式が見つかりやすい
+            (Lit::Node(a, false), Lit::Node(b, false)) => self.lit(Gate::And(a.min(b), a.max(b))),
+            (Lit::Node(a, true), Lit::Node(b, false)) => self.lit(Gate::AndNY(a, b)),
+            (Lit::Node(a, false), Lit::Node(b, true)) => self.lit(Gate::AndNY(b, a)),
+            // !a & !b = !(a | b)
+            (Lit::Node(..), Lit::Node(..)) => !self.or(!x, !y),
+        }
+    }
+
+    fn or(&mut self, x: Lit, y: Lit) -> Lit {
+        match (x, y) {
+            (Lit::Const(true), _) | (_, Lit::Const(true)) => Lit::Const(true),
+            (Lit::Const(false), z) | (z, Lit::Const(false)) => z,
+            (Lit::Node(a, _), Lit::Node(b, _)) if a == b => {
+                if x == y {
+                    x
+                } else {
+                    Lit::Const(true)
+                }
+            }
+            (Lit::Node(a, false), Lit::Node(b, false)) => self.lit(Gate::Or(a.min(b), a.max(b))),
+            (Lit::Node(a, false), Lit::Node(b, true)) => self.lit(Gate::OrYN(a, b)),
+            (Lit::Node(a, true), Lit::Node(b, false)) => self.lit(Gate::OrYN(b, a)),
+            // !a | !b = !(a & b)
+            (Lit::Node(..), Lit::Node(..)) => !self.and(!x, !y),
+        }
+    }
+
+    fn xor(&mut self, x: Lit, y: Lit) -> Lit {
+        match (x, y) {
+            (Lit::Const(b), z) | (z, Lit::Const(b)) => {
+                if b {
+                    !z
+                } else {
+                    z
+                }
+            }
+            (Lit::Node(a, na), Lit::Node(b, nb)) => {
+                if a == b {
+                    return Lit::Const(na != nb);
+                }
+                // 否定は外に出せる
+                let l = self.lit(Gate::Xor(a.min(b), a.max(b)));
+                if na != nb {
+                    !l
+                } else {
+                    l
+                }
+            }
+        }
+    }
+
+    // c ? x : y
+    fn mux(&mut self, c: Lit, x: Lit, y: Lit) -> Lit {
+        match c {
+            Lit::Const(b) => return if b { x } else { y },
+            Lit::Node(_, true) => return self.mux(!c, y, x),
+            Lit::Node(_, false) => {}
+        }
+        if x == y {
+            return x;
+        }
+        // c ? x : !x = !(c ^ x)
+        if x == !y {
+            return !self.xor(c, x);
+        }
+
+        // MUX は bootstrapping 2 回なので、1 回で済む形にできるならそうする
+        match (x, y) {
+            (Lit::Const(true), _) => return self.or(c, y),
+            (Lit::Const(false), _) => return self.and(!c, y),
+            (_, Lit::Const(true)) => return self.or(!c, x),
+            (_, Lit::Const(false)) => return self.and(c, x),
+            _ => {}
+        }
+        if x == c {
+            return self.or(c, y);
+        }
+        if x == !c {
+            return self.and(!c, y);
+        }
+        if y == c {
+            return self.and(c, x);
+        }
+        if y == !c {
+            return self.or(!c, x);
+        }
+
+        if let (Lit::Node(a, true), Lit::Node(b, true)) = (x, y) {
+            return !self.mux(c, Lit::Node(a, false), Lit::Node(b, false));
+        }
+        let (c, x, y) = (self.node(c), self.node(x), self.node(y));
+        self.lit(Gate::Mux(c, x, y))
+    }
+}
+
+pub fn simplify(circuit: &Circuit) -> Circuit {
+    let mut b = Builder {
+        circuit: Circuit::new(),
+        cache: HashMap::new(),
+    };
+
+    let mut lits: Vec<Lit> = Vec::with_capacity(circuit.nodes().len());
+    for gate in circuit.nodes().iter() {
+        let l = match *gate {
+            // 入力は順番を保ったまま全て残す
+            Gate::Input(_) => Lit::Node(b.circuit.input(), false),
+            Gate::Const(v) => Lit::Const(v),
+            Gate::Nand(x, y) => !b.and(lits[x], lits[y]),
+            Gate::And(x, y) => b.and(lits[x], lits[y]),
+            Gate::Or(x, y) => b.or(lits[x], lits[y]),
+            Gate::Xor(x, y) => b.xor(lits[x], lits[y]),
+            Gate::AndNY(x, y) => b.and(!lits[x], lits[y]),
+            Gate::OrYN(x, y) => b.or(lits[x], !lits[y]),
+            Gate::Not(x) => !lits[x],
+            Gate::Mux(c, x, y) => b.mux(lits[c], lits[x], lits[y]),
+        };
+        lits.push(l);
+    }
+
+    for &x in circuit.outputs().iter() {
+        let id = b.node(lits[x]);
+        b.circuit.output(id);
+    }
+    b.circuit
+}
+
+pub fn remove_dead_gates(circuit: &Circuit) -> Circuit {
+    let nodes = circuit.nodes();
+    let mut live = vec![false; nodes.len()];
+    for &x in circuit.outputs().iter() {
+        live[x] = true;
+    }
+    // オペランドは必ず前にあるので、後ろから一度なめれば十分
+    for id in (0..nodes.len()).rev() {
+        if live[id] {
+            for x in nodes[id].operands() {
+                live[x] = true;
+            }
+        }
+    }
+
+    let mut result = Circuit::new();
+    let mut map: Vec<Option<NodeId>> = vec![None; nodes.len()];
+    for (id, gate) in nodes.iter().enumerate() {
+        if !live[id] && !matches!(gate, Gate::Input(_)) {
+            continue;
+        }
+        let m = |x: NodeId| map[x].unwrap();
+        let gate = match *gate {
+            Gate::Input(i) => Gate::Input(i),
+            Gate::Const(v) => Gate::Const(v),
+            Gate::Nand(x, y) => Gate::Nand(m(x), m(y)),
+            Gate::And(x, y) => Gate::And(m(x), m(y)),
+            Gate::Or(x, y) => Gate::Or(m(x), m(y)),
+            Gate::Xor(x, y) => Gate::Xor(m(x), m(y)),
+            Gate::AndNY(x, y) => Gate::AndNY(m(x), m(y)),
+            Gate::OrYN(x, y) => Gate::OrYN(m(x), m(y)),
+            Gate::Not(x) => Gate::Not(m(x)),
+            Gate::Mux(c, x, y) => Gate::Mux(m(c), m(x), m(y)),
+        };
+        map[id] = Some(add_gate(&mut result, gate));
+    }
+    for &x in circuit.outputs().iter() {
+        result.output(map[x].unwrap());
+    }
+    result
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OptimizeReport {
+    pub nodes_before: usize,
+    pub nodes_after: usize,
+    pub bootstraps_before: usize,
+    pub bootstraps_after: usize,
+}
+
+impl OptimizeReport {
+    pub fn bootstraps_saved(&self) -> usize {
+        self.bootstraps_before.saturating_sub(self.bootstraps_after)
+    }
+}
+
+impl fmt::Display for OptimizeReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "nodes: {} -> {}, bootstraps: {} -> {} ({} saved)",
+            self.nodes_before,
+            self.nodes_after,
+            self.bootstraps_before,
+            self.bootstraps_after,
+            self.bootstraps_saved()
+        )
+    }
+}
+
+pub fn optimize(circuit: &Circuit) -> (Circuit, OptimizeReport) {
+    let optimized = remove_dead_gates(&simplify(circuit));
+    let report = OptimizeReport {
+        nodes_before: circuit.nodes().len(),
+        nodes_after: optimized.nodes().len(),
+        bootstraps_before: circuit.bootstrapping_count(),
+        bootstraps_after: optimized.bootstrapping_count(),
+    };
+    (optimized, report)
+}
+
+#[cfg(test)]
+fn assert_equivalent(c0: &Circuit, c1: &Circuit) {
+    let n = c0.inputs().len();
+    assert_eq!(c1.inputs().len(), n);
+    for i in 0..(1usize << n) {
+        let bs: Vec<bool> = (0..n).map(|k| (i >> k) & 1 == 1).collect();
+        assert_eq!(
+            c0.evaluate_cleartext(&bs),
+            c1.evaluate_cleartext(&bs),
+            "{:?}",
+            bs
+        );
+    }
+}
+
+#[test]
+fn test_constant_folding() {
+    let mut c = Circuit::new();
+    let (x, y) = (c.input(), c.input());
+    let (t, f) = (c.constant(true), c.constant(false));
+    let a = c.and(x, t);
+    let o = c.or(a, f);
+    let m = c.mux(t, o, y);
+    let n = c.xor(m, t);
+    let z = c.nand(y, f);
+    c.output(n);
+    c.output(z);
+
+    let (opt, report) = optimize(&c);
+    assert_equivalent(&c, &opt);
+    assert_eq!(report.bootstraps_before, 6);
+    assert_eq!(report.bootstraps_after, 0);
+    assert_eq!(report.bootstraps_saved(), 6);
+}
+
+#[test]
+fn test_common_subexpression() {
+    let mut c = Circuit::new();
+    let (x, y) = (c.input(), c.input());
+    let a0 = c.and(x, y);
+    let a1 = c.and(y, x);
+    let n = c.nand(x, y);
+    let a2 = c.not(n);
+    let s0 = c.xor(a0, a1);
+    let s1 = c.or(a1, a2);
+    c.output(s0);
+    c.output(s1);
+
+    let (opt, report) = optimize(&c);
+    assert_equivalent(&c, &opt);
+    // s0 は常に false, s1 は x & y
+    assert_eq!(report.bootstraps_after, 1);
+}
+
+#[test]
+fn test_absorb_not() {
+    let mut c = Circuit::new();
+    let (x, y) = (c.input(), c.input());
+    let nx = c.not(x);
+    let ny = c.not(y);
+    let a = c.and(nx, y);
+    let o = c.or(x, ny);
+    c.output(a);
+    c.output(o);
+
+    let (opt, report) = optimize(&c);
+    assert_equivalent(&c, &opt);
+    assert_eq!(report.nodes_after, 4);
+    assert_eq!(opt.nodes()[2..], [Gate::AndNY(0, 1), Gate::OrYN(0, 1)]);
+}
+
+#[test]
+fn test_remove_dead_gates() {
+    let mut c = Circuit::new();
+    let (x, y, z) = (c.input(), c.input(), c.input());
+    let a = c.and(x, y);
+    c.mux(a, y, z);
+    c.output(a);
+
+    let opt = remove_dead_gates(&c);
+    assert_equivalent(&c, &opt);
+    // 使われない入力も残す
+    assert_eq!(opt.inputs().len(), 3);
+    assert_eq!(opt.bootstrapping_count(), 1);
+}
+
+#[test]
+fn test_optimize_random() {
+    use rand::Rng;
+
+    let mut rng = rand::thread_rng();
+    for _ in 0..50 {
+        let mut c = Circuit::new();
+        let mut ids: Vec<NodeId> = (0..4).map(|_| c.input()).collect();
+        ids.push(c.constant(true));
+        ids.push(c.constant(false));
+        for _ in 0..30 {
+            let mut pick = || ids[rng.gen_range(0..ids.len())];
+            let (x, y, z) = (pick(), pick(), pick());
+            let id = match rng.gen_range(0..9) {
+                0 => c.nand(x, y),
+                1 => c.and(x, y),
+                2 => c.or(x, y),
+                3 => c.xor(x, y),
+                4 => c.andny(x, y),
+                5 => c.oryn(x, y),
+                6 => c.not(x),
+                _ => c.mux(x, y, z),
+            };
+            ids.push(id);
+        }
+        for &x in ids[ids.len() - 4..].iter() {
+            c.output(x);
+        }
+
+        let (opt, report) = optimize(&c);
+        assert_equivalent(&c, &opt);
+        assert!(report.bootstraps_after <= report.bootstraps_before);
+    }
+}
@@ -1,3 +1,7 @@
+use super::bootstrapping::{bootstrapping_key_with_layout, BootstrappingKeyLayout};
+use super::key::{CloudKey, KeyDistribution, SecretKey};
+use super::key_switching::KeySwitchingKey;
+
 pub mod tlwe {
     pub const N: usize = 635;
     pub const ALPHA: f64 = 3.051_757_812_5e-5;
@@ -6,6 +10,9 @@ pub mod tlwe {
 pub mod trlwe {
     pub const N: usize = 1024;
     pub const ALPHA: f64 = 2.980_232_238_769_531_3e-8;
+
+    // Gaussian 秘密鍵の標準偏差 (整数係数)
+    pub const KEY_SIGMA: f64 = 3.2;
 }
 
 pub mod trgsw {
@@ -21,3 +28,83 @@ pub mod trgsw {
     pub const SIGN_MIN: i8 = -((BG / 2) as i8);
     pub const SIGN_MAX: i8 = (BG / 2) as i8 - 1;
 }
+
+// 数値のパラメータは共通で、秘密鍵の分布と bootstrapping key の持ち方だけが違う
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ParamSet {
+    // lv0, lv1 とも二値
+    #[default]
+    Binary,
+    // lv0, lv1 とも三値
+    Ternary,
+    // lv0 は三値, lv1 は Gaussian
+    Gaussian,
+    // Binary と同じ鍵で、bootstrapping key を 2 ビットずつ束ねる
+    Bundled,
+}
+
+impl ParamSet {
+    pub const ALL: [ParamSet; 4] = [
+        ParamSet::Binary,
+        ParamSet::Ternary,
+        ParamSet::Gaussian,
+        ParamSet::Bundled,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ParamSet::Binary => "binary",
+            ParamSet::Ternary => "ternary",
+            ParamSet::Gaussian => "gaussian",
+            ParamSet::Bundled => "bundled",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|p| p.name() == name)
+    }
+
+    // ファイルに書き込むときの番号
+    pub fn id(self) -> u8 {
+        match self {
+            ParamSet::Binary => 1,
+            ParamSet::Ternary => 2,
+            ParamSet::Gaussian => 3,
+            ParamSet::Bundled => 4,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Option<Self> {
+        Self::ALL.iter().copied().find(|p| p.id() == id)
+    }
+
+    // (lv0, lv1)
+    pub fn key_distribution(self) -> (KeyDistribution, KeyDistribution) {
+        match self {
+            ParamSet::Binary | ParamSet::Bundled => {
+                (KeyDistribution::Binary, KeyDistribution::Binary)
+            }
+            ParamSet::Ternary => (KeyDistribution::Ternary, KeyDistribution::Ternary),
+            ParamSet::Gaussian => (KeyDistribution::Ternary, KeyDistribution::Gaussian),
+        }
+    }
+
+    pub fn bootstrapping_layout(self) -> BootstrappingKeyLayout {
+        match self {
+            ParamSet::Binary => BootstrappingKeyLayout::Binary,
+            ParamSet::Ternary | ParamSet::Gaussian => BootstrappingKeyLayout::Ternary,
+            ParamSet::Bundled => BootstrappingKeyLayout::Bundled,
+        }
+    }
+
+    pub fn secret_key(self) -> SecretKey {
+        let (lv0_dist, lv1_dist) = self.key_distribution();
+        SecretKey::with_distribution(lv0_dist, lv1_dist)
+    }
+
+    pub fn cloud_key(self, sk: SecretKey) -> CloudKey {
+        let bk = bootstrapping_key_with_layout(sk, self.bootstrapping_layout());
+        let ks = KeySwitchingKey::new(sk);
+        CloudKey { bk, ks }
+    }
+}
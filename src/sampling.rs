@@ -1,47 +1,106 @@
-use super::util::{float_to_torus, Torus};
+use super::error::KfheError;
+use super::util::{float_to_torus, try_float_to_torus, Torus};
 
 use rand_distr::{Distribution, Normal, Uniform};
 
+fn normal(mu: f64, sigma: f64) -> Result<Normal<f64>, KfheError> {
+    if !mu.is_finite() {
+        return Err(KfheError::NotFinite(mu));
+    }
+    if !sigma.is_finite() || sigma < 0. {
+        return Err(KfheError::InvalidStddev(sigma));
+    }
+    Normal::new(mu, sigma).map_err(|_| KfheError::InvalidStddev(sigma))
+}
+
 pub fn modular_normal_dist(mu: f64, alpha: f64) -> Torus {
-    let normal = Normal::new(mu, alpha).unwrap();
-    let sample = normal.sample(&mut rand::thread_rng());
-    float_to_torus(sample)
+    try_modular_normal_dist(mu, alpha).unwrap_or_else(|e| panic!("{}", e))
+}
+
+pub fn try_modular_normal_dist(mu: f64, alpha: f64) -> Result<Torus, KfheError> {
+    let sample = normal(mu, alpha)?.sample(&mut rand::thread_rng());
+    try_float_to_torus(sample)
 }
 
 pub fn ndim_modular_normal_dist<const N: usize>(mu: f64, alpha: f64) -> [Torus; N] {
-    let normal = Normal::new(mu, alpha).unwrap();
+    try_ndim_modular_normal_dist(mu, alpha).unwrap_or_else(|e| panic!("{}", e))
+}
+
+pub fn try_ndim_modular_normal_dist<const N: usize>(
+    mu: f64,
+    alpha: f64,
+) -> Result<[Torus; N], KfheError> {
+    let normal = normal(mu, alpha)?;
     let mut rng = rand::thread_rng();
     let mut ret = [0; N];
-    for i in 0..ret.len() {
-        ret[i] = float_to_torus(normal.sample(&mut rng));
+    for x in ret.iter_mut() {
+        *x = try_float_to_torus(normal.sample(&mut rng))?;
     }
-    ret
+    Ok(ret)
 }
 
 pub fn ndim_bin_uniform<const N: usize>() -> [Torus; N] {
     let bin_uni = Uniform::new_inclusive(0, 1);
     let mut rng = rand::thread_rng();
     let mut ret = [0; N];
-    for i in 0..ret.len() {
-        ret[i] = bin_uni.sample(&mut rng);
+    for x in ret.iter_mut() {
+        *x = bin_uni.sample(&mut rng);
+    }
+    ret
+}
+
+pub fn ndim_ternary_uniform<const N: usize>() -> [Torus; N] {
+    let ter_uni = Uniform::new_inclusive(-1, 1);
+    let mut rng = rand::thread_rng();
+    let mut ret = [0; N];
+    for x in ret.iter_mut() {
+        *x = ter_uni.sample(&mut rng) as i32 as Torus;
     }
     ret
 }
 
+pub fn ndim_discrete_normal_dist<const N: usize>(sigma: f64) -> [Torus; N] {
+    try_ndim_discrete_normal_dist(sigma).unwrap_or_else(|e| panic!("{}", e))
+}
+
+pub fn try_ndim_discrete_normal_dist<const N: usize>(sigma: f64) -> Result<[Torus; N], KfheError> {
+    let normal = normal(0., sigma)?;
+    let mut rng = rand::thread_rng();
+    let mut ret = [0; N];
+    for x in ret.iter_mut() {
+        *x = normal.sample(&mut rng).round() as i32 as Torus;
+    }
+    Ok(ret)
+}
+
 pub fn ndim_torus_uniform<const N: usize>() -> [Torus; N] {
     let torus_uni = Uniform::new_inclusive(-0.5, 0.5);
     let mut rng = rand::thread_rng();
     let mut ret = [0; N];
-    for i in 0..ret.len() {
-        ret[i] = float_to_torus(torus_uni.sample(&mut rng));
+    for x in ret.iter_mut() {
+        *x = float_to_torus(torus_uni.sample(&mut rng));
     }
     ret
 }
 
 pub fn random_bool_initialization<const N: usize>() -> [bool; N] {
     let mut ret = [true; N];
-    for i in 0..ret.len() {
-        ret[i] = rand::random::<bool>();
+    for x in ret.iter_mut() {
+        *x = rand::random::<bool>();
     }
     ret
 }
+
+#[test]
+fn test_try_normal_dist() {
+    assert!(try_modular_normal_dist(0.25, 1e-5).is_ok());
+    assert_eq!(
+        try_modular_normal_dist(0., -1.),
+        Err(KfheError::InvalidStddev(-1.))
+    );
+    assert!(try_ndim_modular_normal_dist::<4>(f64::NAN, 1e-5).is_err());
+    assert_eq!(
+        try_ndim_discrete_normal_dist::<4>(f64::INFINITY),
+        Err(KfheError::InvalidStddev(f64::INFINITY))
+    );
+}
@@ -0,0 +1,233 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+use super::circuit::{Circuit, Gate, NodeId};
+use super::gates::Gates;
+
+// bootstrapping を要するゲートの段数ごとにゲートをまとめる. 同じ段の bootstrapping は互いに依存しない
+// NOT と定数、入力は bootstrapping が要らないのでオペランドと同じ段に置く (段数 = bootstrapping の深さ)
+// 各段の中のゲートは番号順に並ぶ
+pub fn levels(circuit: &Circuit) -> Vec<Vec<NodeId>> {
+    let nodes = circuit.nodes();
+    let mut depth = vec![0; nodes.len()];
+    let mut levels: Vec<Vec<NodeId>> = vec![Vec::new()];
+    for (id, gate) in nodes.iter().enumerate() {
+        let d = gate.operands().iter().map(|&x| depth[x]).max().unwrap_or(0);
+        let d = if gate.bootstrapping_cost() > 0 {
+            d + 1
+        } else {
+            d
+        };
+        depth[id] = d;
+        if levels.len() <= d {
+            levels.resize(d + 1, Vec::new());
+        }
+        levels[d].push(id);
+    }
+    levels
+}
+
+// 回路を段ごとに複数スレッドで評価する
+// 各段の中ではスレッドが共有のカウンタから次のゲートを取っていくので、重いゲートが偏っても待ちが少ない
+// 結果はゲートの番号の位置に書き戻すので、スレッド数や実行順によらず同じ出力になる
+pub struct Scheduler<G: Gates> {
+    gates: Arc<G>,
+    workers: usize,
+}
+
+impl<G> Scheduler<G>
+where
+    G: Gates + Send + Sync,
+    G::Bit: Send + Sync,
+{
+    pub fn new(gates: Arc<G>, workers: usize) -> Self {
+        assert!(workers > 0);
+        Self { gates, workers }
+    }
+
+    pub fn workers(&self) -> usize {
+        self.workers
+    }
+
+    pub fn evaluate(&self, circuit: &Circuit, inputs: &[G::Bit]) -> Vec<G::Bit> {
+        assert_eq!(inputs.len(), circuit.inputs().len());
+
+        let nodes = circuit.nodes();
+        let mut values: Vec<Option<G::Bit>> = vec![None; nodes.len()];
+
+        for level in levels(circuit) {
+            // bootstrapping を要するゲートを先に並列で評価し、残りの NOT などは後から番号順に評価する
+            let (level, free): (Vec<NodeId>, Vec<NodeId>) = level
+                .into_iter()
+                .partition(|&id| nodes[id].bootstrapping_cost() > 0);
+            let next = AtomicUsize::new(0);
+            let results: Vec<Vec<(NodeId, G::Bit)>> = thread::scope(|s| {
+                let handles: Vec<_> = (0..self.workers.min(level.len()))
+                    .map(|_| {
+                        let g = Arc::clone(&self.gates);
+                        let (level, values, next) = (&level, &values, &next);
+                        s.spawn(move || {
+                            let mut done = Vec::new();
+                            loop {
+                                let i = next.fetch_add(1, Ordering::Relaxed);
+                                if i >= level.len() {
+                                    break;
+                                }
+                                let id = level[i];
+                                done.push((id, evaluate_gate(&*g, &nodes[id], values, inputs)));
+                            }
+                            done
+                        })
+                    })
+                    .collect();
+                handles.into_iter().map(|h| h.join().unwrap()).collect()
+            });
+
+            for (id, v) in results.into_iter().flatten() {
+                values[id] = Some(v);
+            }
+            for id in free {
+                let v = evaluate_gate(&*self.gates, &nodes[id], &values, inputs);
+                values[id] = Some(v);
+            }
+        }
+
+        circuit
+            .outputs()
+            .iter()
+            .map(|&x| values[x].clone().unwrap())
+            .collect()
+    }
+}
+
+fn evaluate_gate<G: Gates>(
+    g: &G,
+    gate: &Gate,
+    values: &[Option<G::Bit>],
+    inputs: &[G::Bit],
+) -> G::Bit {
+    // オペランドは前の段か、同じ段の先に評価したゲートで計算済み
+    let v = |x: NodeId| values[x].as_ref().unwrap();
+    match *gate {
+        Gate::Input(i) => inputs[i].clone(),
+        Gate::Const(b) => g.constant(b),
+        Gate::Nand(x, y) => g.nand(v(x), v(y)),
+        Gate::And(x, y) => g.and(v(x), v(y)),
+        Gate::Or(x, y) => g.or(v(x), v(y)),
+        Gate::Xor(x, y) => g.xor(v(x), v(y)),
+        Gate::AndNY(x, y) => g.andny(v(x), v(y)),
+        Gate::OrYN(x, y) => g.oryn(v(x), v(y)),
+        Gate::Not(x) => g.not(v(x)),
+        Gate::Mux(c, x, y) => g.mux(v(c), v(x), v(y)),
+    }
+}
+
+#[test]
+fn test_levels() {
+    let mut c = Circuit::new();
+    let (x, y, cin) = (c.input(), c.input(), c.input());
+    let t = c.xor(x, y);
+    let s = c.xor(t, cin);
+    let cout = c.mux(t, cin, x);
+    c.output(s);
+    c.output(cout);
+
+    assert_eq!(levels(&c), vec![vec![x, y, cin], vec![t], vec![s, cout]]);
+}
+
+#[test]
+fn test_levels_free_gates() {
+    // NOT と定数は段を増やさない
+    let mut c = Circuit::new();
+    let (x, y) = (c.input(), c.input());
+    let nx = c.not(x);
+    let t = c.and(nx, y);
+    let nt = c.not(t);
+    let nnt = c.not(nt);
+    let one = c.constant(true);
+    let u = c.xor(nnt, one);
+    c.output(u);
+
+    assert_eq!(
+        levels(&c),
+        vec![vec![x, y, nx, one], vec![t, nt, nnt], vec![u]]
+    );
+
+    // 段数は bootstrapping の深さに一致する
+    let mut c = Circuit::new();
+    let mut z = c.input();
+    for _ in 0..5 {
+        let w = c.not(z);
+        z = c.nand(w, z);
+        z = c.not(z);
+    }
+    c.output(z);
+    assert_eq!(levels(&c).len(), 1 + 5);
+
+    use super::gates::Cleartext;
+    let scheduler = Scheduler::new(Arc::new(Cleartext), 2);
+    for b in [false, true] {
+        assert_eq!(scheduler.evaluate(&c, &[b]), c.evaluate_cleartext(&[b]));
+    }
+}
+
+#[test]
+fn test_scheduler_cleartext() {
+    use super::gates::Cleartext;
+    use super::sampling::random_bool_initialization;
+
+    // 4 ビットの加算器を 2 つ並べたもの
+    let mut c = Circuit::new();
+    let xs: Vec<NodeId> = (0..16).map(|_| c.input()).collect();
+    for k in 0..2 {
+        let (a, b) = (&xs[8 * k..8 * k + 4], &xs[8 * k + 4..8 * k + 8]);
+        let mut carry = c.constant(false);
+        for i in 0..4 {
+            let t = c.xor(a[i], b[i]);
+            let s = c.xor(t, carry);
+            carry = c.mux(t, carry, a[i]);
+            c.output(s);
+        }
+        c.output(carry);
+    }
+
+    let bs: [bool; 16] = random_bool_initialization();
+    let expected = c.evaluate_cleartext(&bs);
+    for workers in 1..=4 {
+        let scheduler = Scheduler::new(Arc::new(Cleartext), workers);
+        assert_eq!(scheduler.evaluate(&c, &bs), expected);
+    }
+}
+
+#[test]
+fn test_scheduler_encrypted() {
+    use super::key::{CloudKey, SecretKey};
+    use super::sampling::random_bool_initialization;
+    use super::tlwe::TLWE;
+
+    let sk = SecretKey::new();
+    let tlwe = TLWE::new(sk);
+    let ck = Arc::new(CloudKey::new(sk));
+
+    let mut c = Circuit::new();
+    let (x, y) = (c.input(), c.input());
+    let s = c.xor(x, y);
+    let carry = c.and(x, y);
+    c.output(s);
+    c.output(carry);
+
+    let bs: [bool; 2] = random_bool_initialization();
+    let cs: Vec<_> = bs.iter().map(|&b| tlwe.encrypt(b)).collect();
+
+    let out1 = Scheduler::new(ck.clone(), 1).evaluate(&c, &cs);
+    let out2 = Scheduler::new(ck, 2).evaluate(&c, &cs);
+
+    let dec: Vec<bool> = out2.iter().map(|&o| tlwe.decrypt(o)).collect();
+    assert_eq!(dec, c.evaluate_cleartext(&bs));
+
+    // スレッド数によらず暗号文まで一致する
+    for (o1, o2) in out1.iter().zip(out2.iter()) {
+        assert_eq!(o1.describe(), o2.describe());
+    }
+}
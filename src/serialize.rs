@@ -0,0 +1,905 @@
+// 鍵や暗号文をファイルに書き出すためのバイナリ形式
+//
+// 数値はすべてリトルエンディアン
+//   magic "KFHE" (4) | version u16 | kind u8 | params u8 | key_id u64 | payload_len u64 | payload | checksum u64
+// checksum は payload の FNV-1a
+// key_id は鍵の生成時に乱数で決めて秘密鍵のファイルに保存する値で、同じ秘密鍵から作った鍵や暗号文では一致する
+// 秘密鍵の中身からは計算しないので、公開される cloud key や暗号文のヘッダに載せても秘密鍵の情報は漏れない
+// 暗号文や鍵の一部 (TLWE lv1, TRLWE, TRGSW, bootstrapping key, key-switching key) も単独で書き出せる
+
+use std::convert::TryFrom;
+use std::fmt;
+
+use super::bootstrapping::{BootstrappingKey, BootstrappingKeyLayout};
+use super::bundle::{CiphertextBundle, Encoding};
+use super::key::{CloudKey, KeyDistribution, SecretKey};
+use super::key_switching::KeySwitchingKey;
+use super::params::{trgsw, ParamSet};
+use super::tlwe::{CipherTLWELv0, CipherTLWELv1};
+use super::trgsw::{uninitialized_trgsw_matrix, TRGSWMatrix};
+use super::trlwe::CipherTRLWE;
+use super::util::Torus;
+
+pub const MAGIC: [u8; 4] = *b"KFHE";
+pub const VERSION: u16 = 1;
+pub const HEADER_LEN: usize = 24;
+const CHECKSUM_LEN: usize = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ObjectKind {
+    SecretKey,
+    CloudKey,
+    CiphertextBundle,
+    TLWELv1,
+    TRLWE,
+    TRGSW,
+    BootstrappingKey,
+    KeySwitchingKey,
+    // stream の形式で書いたもの. payload_len は 0 で、checksum はチャンクごと
+    CiphertextStream,
+}
+
+impl ObjectKind {
+    pub const ALL: [ObjectKind; 9] = [
+        ObjectKind::SecretKey,
+        ObjectKind::CloudKey,
+        ObjectKind::CiphertextBundle,
+        ObjectKind::TLWELv1,
+        ObjectKind::TRLWE,
+        ObjectKind::TRGSW,
+        ObjectKind::BootstrappingKey,
+        ObjectKind::KeySwitchingKey,
+        ObjectKind::CiphertextStream,
+    ];
+
+    pub fn id(self) -> u8 {
+        match self {
+            ObjectKind::SecretKey => 1,
+            ObjectKind::CloudKey => 2,
+            ObjectKind::CiphertextBundle => 3,
+            ObjectKind::TLWELv1 => 4,
+            ObjectKind::TRLWE => 5,
+            ObjectKind::TRGSW => 6,
+            ObjectKind::BootstrappingKey => 7,
+            ObjectKind::KeySwitchingKey => 8,
+            ObjectKind::CiphertextStream => 9,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Option<Self> {
+        Self::ALL.iter().copied().find(|k| k.id() == id)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ObjectKind::SecretKey => "secret key",
+            ObjectKind::CloudKey => "cloud key",
+            ObjectKind::CiphertextBundle => "ciphertext bundle",
+            ObjectKind::TLWELv1 => "TLWE lv1 ciphertexts",
+            ObjectKind::TRLWE => "TRLWE ciphertexts",
+            ObjectKind::TRGSW => "TRGSW ciphertexts",
+            ObjectKind::BootstrappingKey => "bootstrapping key",
+            ObjectKind::KeySwitchingKey => "key-switching key",
+            ObjectKind::CiphertextStream => "ciphertext stream",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Header {
+    pub version: u16,
+    pub kind: ObjectKind,
+    pub params: ParamSet,
+    pub key_id: u64,
+    pub payload_len: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FormatError {
+    BadMagic,
+    UnsupportedVersion(u16),
+    UnknownKind(u8),
+    UnknownParams(u8),
+    UnexpectedKind {
+        expected: ObjectKind,
+        found: ObjectKind,
+    },
+    Truncated,
+    ChecksumMismatch,
+    Invalid(String),
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FormatError::BadMagic => write!(f, "not a kfhe file"),
+            FormatError::UnsupportedVersion(v) => write!(f, "unsupported format version {}", v),
+            FormatError::UnknownKind(k) => write!(f, "unknown object kind {}", k),
+            FormatError::UnknownParams(p) => write!(f, "unknown parameter set {}", p),
+            FormatError::UnexpectedKind { expected, found } => {
+                write!(f, "expected {}, found {}", expected.name(), found.name())
+            }
+            FormatError::Truncated => write!(f, "unexpected end of data"),
+            FormatError::ChecksumMismatch => write!(f, "checksum mismatch"),
+            FormatError::Invalid(msg) => write!(f, "invalid data: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for FormatError {}
+
+// 少しずつ計算するとき用
+pub(crate) struct Fnv1a(pub u64);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Fnv1a {
+    pub fn update(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 ^= b as u64;
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+}
+
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut h = Fnv1a::default();
+    h.update(bytes);
+    h.0
+}
+
+// 鍵の生成時に新しい key_id を作る
+pub fn new_key_id() -> u64 {
+    rand::random()
+}
+
+#[derive(Default)]
+pub(crate) struct Encoder(pub Vec<u8>);
+
+impl Encoder {
+    pub fn u8(&mut self, x: u8) {
+        self.0.push(x);
+    }
+
+    pub fn u16(&mut self, x: u16) {
+        self.0.extend_from_slice(&x.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, x: u32) {
+        self.0.extend_from_slice(&x.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, x: u64) {
+        self.0.extend_from_slice(&x.to_le_bytes());
+    }
+
+    pub fn ring(&mut self, xs: &[Torus]) {
+        self.0.reserve(4 * xs.len());
+        for &x in xs {
+            self.u32(x);
+        }
+    }
+
+    // tag u8 | width u8
+    pub fn encoding(&mut self, encoding: Encoding) {
+        let (tag, width) = match encoding {
+            Encoding::Bits => (0, 1),
+            Encoding::Integers { width } => (1, width as u8),
+            Encoding::Bytes => (2, 8),
+        };
+        self.u8(tag);
+        self.u8(width);
+    }
+
+    pub fn tlwe_lv1(&mut self, c: &CipherTLWELv1) {
+        self.ring(&c.0);
+        self.u32(c.1);
+    }
+
+    pub fn trlwe(&mut self, c: &CipherTRLWE) {
+        self.ring(&c.0);
+        self.ring(&c.1);
+    }
+
+    pub fn trgsw(&mut self, matrix: &TRGSWMatrix) {
+        for row in matrix.iter() {
+            for ring in row.iter() {
+                self.ring(ring);
+            }
+        }
+    }
+
+    pub fn tlwe_lv0(&mut self, c: &CipherTLWELv0) {
+        self.ring(&c.0);
+        self.u32(c.1);
+    }
+}
+
+pub(crate) struct Decoder<'a>(pub &'a [u8]);
+
+impl<'a> Decoder<'a> {
+    pub fn bytes(&mut self, n: usize) -> Result<&'a [u8], FormatError> {
+        if self.0.len() < n {
+            return Err(FormatError::Truncated);
+        }
+        let (head, tail) = self.0.split_at(n);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, FormatError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16, FormatError> {
+        let b = self.bytes(2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, FormatError> {
+        let b = self.bytes(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, FormatError> {
+        let mut b = [0; 8];
+        b.copy_from_slice(self.bytes(8)?);
+        Ok(u64::from_le_bytes(b))
+    }
+
+    pub fn ring<const N: usize>(&mut self) -> Result<[Torus; N], FormatError> {
+        let b = self.bytes(4 * N)?;
+        let mut ring = [0; N];
+        for i in 0..N {
+            ring[i] = u32::from_le_bytes([b[4 * i], b[4 * i + 1], b[4 * i + 2], b[4 * i + 3]]);
+        }
+        Ok(ring)
+    }
+
+    pub fn tlwe_lv0(&mut self) -> Result<CipherTLWELv0, FormatError> {
+        let a = self.ring()?;
+        let b = self.u32()?;
+        Ok(CipherTLWELv0(a, b))
+    }
+
+    pub fn encoding(&mut self) -> Result<Encoding, FormatError> {
+        match (self.u8()?, self.u8()? as usize) {
+            (0, 1) => Ok(Encoding::Bits),
+            (1, width) if (1..=64).contains(&width) => Ok(Encoding::Integers { width }),
+            (2, 8) => Ok(Encoding::Bytes),
+            (tag, width) => Err(FormatError::Invalid(format!(
+                "unknown encoding {} (width {})",
+                tag, width
+            ))),
+        }
+    }
+
+    pub fn tlwe_lv1(&mut self) -> Result<CipherTLWELv1, FormatError> {
+        let a = self.ring()?;
+        let b = self.u32()?;
+        Ok(CipherTLWELv1(a, b))
+    }
+
+    pub fn trlwe(&mut self) -> Result<CipherTRLWE, FormatError> {
+        let a = self.ring()?;
+        let b = self.ring()?;
+        Ok(CipherTRLWE(a, b))
+    }
+
+    pub fn trgsw(&mut self) -> Result<TRGSWMatrix, FormatError> {
+        let mut matrix = uninitialized_trgsw_matrix();
+        for row in matrix.iter_mut() {
+            for ring in row.iter_mut() {
+                *ring = self.ring()?;
+            }
+        }
+        Ok(matrix)
+    }
+
+    // 要素数を読み、残りのデータに収まるか確かめる (巨大な確保を避けるため)
+    pub fn count(&mut self, elem_len: usize) -> Result<usize, FormatError> {
+        let n = self.u32()? as usize;
+        if n.saturating_mul(elem_len) > self.0.len() {
+            return Err(FormatError::Truncated);
+        }
+        Ok(n)
+    }
+
+    pub fn finish(self) -> Result<(), FormatError> {
+        if self.0.is_empty() {
+            Ok(())
+        } else {
+            Err(FormatError::Invalid(format!(
+                "{} trailing bytes",
+                self.0.len()
+            )))
+        }
+    }
+}
+
+pub(crate) fn encode_header(
+    e: &mut Encoder,
+    kind: ObjectKind,
+    params: ParamSet,
+    key_id: u64,
+    payload_len: usize,
+) {
+    e.0.extend_from_slice(&MAGIC);
+    e.u16(VERSION);
+    e.u8(kind.id());
+    e.u8(params.id());
+    e.u64(key_id);
+    e.u64(payload_len as u64);
+}
+
+pub fn encode_object(kind: ObjectKind, params: ParamSet, key_id: u64, payload: &[u8]) -> Vec<u8> {
+    let mut e = Encoder(Vec::with_capacity(
+        HEADER_LEN + payload.len() + CHECKSUM_LEN,
+    ));
+    encode_header(&mut e, kind, params, key_id, payload.len());
+    e.0.extend_from_slice(payload);
+    e.u64(fnv1a(payload));
+    e.0
+}
+
+pub fn read_header(bytes: &[u8]) -> Result<Header, FormatError> {
+    let mut d = Decoder(bytes);
+    if d.bytes(4)? != MAGIC {
+        return Err(FormatError::BadMagic);
+    }
+    let version = d.u16()?;
+    if version != VERSION {
+        return Err(FormatError::UnsupportedVersion(version));
+    }
+    let kind = d.u8()?;
+    let kind = ObjectKind::from_id(kind).ok_or(FormatError::UnknownKind(kind))?;
+    let params = d.u8()?;
+    let params = ParamSet::from_id(params).ok_or(FormatError::UnknownParams(params))?;
+    let key_id = d.u64()?;
+    let payload_len = d.u64()?;
+    Ok(Header {
+        version,
+        kind,
+        params,
+        key_id,
+        payload_len,
+    })
+}
+
+// ヘッダと checksum を確かめて payload を返す
+pub fn decode_object(bytes: &[u8]) -> Result<(Header, &[u8]), FormatError> {
+    let header = read_header(bytes)?;
+    if header.kind == ObjectKind::CiphertextStream {
+        return Err(FormatError::Invalid(
+            "ciphertext streams must be read chunk by chunk".to_string(),
+        ));
+    }
+    let mut d = Decoder(&bytes[HEADER_LEN..]);
+    let len = usize::try_from(header.payload_len).map_err(|_| FormatError::Truncated)?;
+    let payload = d.bytes(len)?;
+    let checksum = d.u64()?;
+    d.finish()?;
+    if fnv1a(payload) != checksum {
+        return Err(FormatError::ChecksumMismatch);
+    }
+    Ok((header, payload))
+}
+
+fn expect_kind(header: &Header, expected: ObjectKind) -> Result<(), FormatError> {
+    if header.kind != expected {
+        return Err(FormatError::UnexpectedKind {
+            expected,
+            found: header.kind,
+        });
+    }
+    Ok(())
+}
+
+fn distribution_id(dist: KeyDistribution) -> u8 {
+    match dist {
+        KeyDistribution::Binary => 0,
+        KeyDistribution::Ternary => 1,
+        KeyDistribution::Gaussian => 2,
+    }
+}
+
+fn layout_id(layout: BootstrappingKeyLayout) -> u8 {
+    match layout {
+        BootstrappingKeyLayout::Binary => 0,
+        BootstrappingKeyLayout::Ternary => 1,
+        BootstrappingKeyLayout::Bundled => 2,
+    }
+}
+
+pub fn encode_secret_key(sk: &SecretKey, params: ParamSet, key_id: u64) -> Vec<u8> {
+    let mut e = Encoder::default();
+    e.u8(distribution_id(sk.lv0_dist));
+    e.u8(distribution_id(sk.lv1_dist));
+    e.ring(&sk.lv0);
+    e.ring(&sk.lv1);
+    encode_object(ObjectKind::SecretKey, params, key_id, &e.0)
+}
+
+pub fn decode_secret_key(bytes: &[u8]) -> Result<(Header, SecretKey), FormatError> {
+    let (header, payload) = decode_object(bytes)?;
+    expect_kind(&header, ObjectKind::SecretKey)?;
+
+    let mut d = Decoder(payload);
+    let dists = (d.u8()?, d.u8()?);
+    let (lv0_dist, lv1_dist) = header.params.key_distribution();
+    if dists != (distribution_id(lv0_dist), distribution_id(lv1_dist)) {
+        return Err(FormatError::Invalid(format!(
+            "key distribution does not match parameter set {}",
+            header.params.name()
+        )));
+    }
+    let sk = SecretKey {
+        lv0: d.ring()?,
+        lv1: d.ring()?,
+        lv0_dist,
+        lv1_dist,
+    };
+    d.finish()?;
+    Ok((header, sk))
+}
+
+const TRGSW_LEN: usize = 4 * 2 * trgsw::L * 2 * trgsw::N;
+pub(crate) const TLWE_LV0_LEN: usize = 4 * (super::params::tlwe::N + 1);
+const TLWE_LV1_LEN: usize = 4 * (super::params::trlwe::N + 1);
+const TRLWE_LEN: usize = 4 * 2 * super::params::trlwe::N;
+
+// bootstrapping key の部分: layout u8 | u32 count | TRGSW...
+fn encode_bk_part(e: &mut Encoder, bk: &BootstrappingKey) {
+    e.u8(layout_id(bk.layout()));
+    e.u32(bk.0.len() as u32);
+    for matrix in bk.0.iter() {
+        e.trgsw(matrix);
+    }
+}
+
+fn decode_bk_part(d: &mut Decoder, params: ParamSet) -> Result<BootstrappingKey, FormatError> {
+    let layout = params.bootstrapping_layout();
+    if d.u8()? != layout_id(layout) {
+        return Err(FormatError::Invalid(format!(
+            "bootstrapping key layout does not match parameter set {}",
+            params.name()
+        )));
+    }
+
+    let n = d.count(TRGSW_LEN)?;
+    if n != layout.size() {
+        return Err(FormatError::Invalid(format!(
+            "expected {} TRGSW in bootstrapping key, found {}",
+            layout.size(),
+            n
+        )));
+    }
+    let mut bk: Vec<TRGSWMatrix> = Vec::with_capacity(n);
+    for _ in 0..n {
+        bk.push(d.trgsw()?);
+    }
+    Ok(BootstrappingKey(bk, layout))
+}
+
+// key-switching key の部分: u32 count | TLWE lv0...
+fn encode_ks_part(e: &mut Encoder, ks: &KeySwitchingKey) {
+    e.u32(ks.0.len() as u32);
+    for c in ks.0.iter() {
+        e.tlwe_lv0(c);
+    }
+}
+
+fn decode_ks_part(d: &mut Decoder) -> Result<KeySwitchingKey, FormatError> {
+    let n = d.count(TLWE_LV0_LEN)?;
+    if n != KeySwitchingKey::SIZE {
+        return Err(FormatError::Invalid(format!(
+            "expected {} ciphertexts in key-switching key, found {}",
+            KeySwitchingKey::SIZE,
+            n
+        )));
+    }
+    let mut ks = Vec::with_capacity(n);
+    for _ in 0..n {
+        ks.push(d.tlwe_lv0()?);
+    }
+    Ok(KeySwitchingKey(ks))
+}
+
+pub fn encode_cloud_key(ck: &CloudKey, params: ParamSet, key_id: u64) -> Vec<u8> {
+    let mut e = Encoder(Vec::with_capacity(
+        10 + ck.bk.0.len() * TRGSW_LEN + ck.ks.0.len() * TLWE_LV0_LEN,
+    ));
+    encode_bk_part(&mut e, &ck.bk);
+    encode_ks_part(&mut e, &ck.ks);
+    encode_object(ObjectKind::CloudKey, params, key_id, &e.0)
+}
+
+pub fn decode_cloud_key(bytes: &[u8]) -> Result<(Header, CloudKey), FormatError> {
+    let (header, payload) = decode_object(bytes)?;
+    expect_kind(&header, ObjectKind::CloudKey)?;
+
+    let mut d = Decoder(payload);
+    let bk = decode_bk_part(&mut d, header.params)?;
+    let ks = decode_ks_part(&mut d)?;
+    d.finish()?;
+    Ok((header, CloudKey { bk, ks }))
+}
+
+pub fn encode_bootstrapping_key(bk: &BootstrappingKey, params: ParamSet, key_id: u64) -> Vec<u8> {
+    let mut e = Encoder(Vec::with_capacity(5 + bk.0.len() * TRGSW_LEN));
+    encode_bk_part(&mut e, bk);
+    encode_object(ObjectKind::BootstrappingKey, params, key_id, &e.0)
+}
+
+pub fn decode_bootstrapping_key(bytes: &[u8]) -> Result<(Header, BootstrappingKey), FormatError> {
+    let (header, payload) = decode_object(bytes)?;
+    expect_kind(&header, ObjectKind::BootstrappingKey)?;
+
+    let mut d = Decoder(payload);
+    let bk = decode_bk_part(&mut d, header.params)?;
+    d.finish()?;
+    Ok((header, bk))
+}
+
+pub fn encode_key_switching_key(ks: &KeySwitchingKey, params: ParamSet, key_id: u64) -> Vec<u8> {
+    let mut e = Encoder(Vec::with_capacity(4 + ks.0.len() * TLWE_LV0_LEN));
+    encode_ks_part(&mut e, ks);
+    encode_object(ObjectKind::KeySwitchingKey, params, key_id, &e.0)
+}
+
+pub fn decode_key_switching_key(bytes: &[u8]) -> Result<(Header, KeySwitchingKey), FormatError> {
+    let (header, payload) = decode_object(bytes)?;
+    expect_kind(&header, ObjectKind::KeySwitchingKey)?;
+
+    let mut d = Decoder(payload);
+    let ks = decode_ks_part(&mut d)?;
+    d.finish()?;
+    Ok((header, ks))
+}
+
+// 同じ種類の暗号文の列: u32 count | 暗号文...
+fn encode_list<T>(
+    kind: ObjectKind,
+    xs: &[T],
+    elem_len: usize,
+    encode: impl Fn(&mut Encoder, &T),
+    params: ParamSet,
+    key_id: u64,
+) -> Vec<u8> {
+    let mut e = Encoder(Vec::with_capacity(4 + xs.len() * elem_len));
+    e.u32(xs.len() as u32);
+    for x in xs {
+        encode(&mut e, x);
+    }
+    encode_object(kind, params, key_id, &e.0)
+}
+
+fn decode_list<T>(
+    bytes: &[u8],
+    kind: ObjectKind,
+    elem_len: usize,
+    decode: impl Fn(&mut Decoder) -> Result<T, FormatError>,
+) -> Result<(Header, Vec<T>), FormatError> {
+    let (header, payload) = decode_object(bytes)?;
+    expect_kind(&header, kind)?;
+
+    let mut d = Decoder(payload);
+    let n = d.count(elem_len)?;
+    let mut xs = Vec::with_capacity(n);
+    for _ in 0..n {
+        xs.push(decode(&mut d)?);
+    }
+    d.finish()?;
+    Ok((header, xs))
+}
+
+pub fn encode_tlwe_lv1(cs: &[CipherTLWELv1], params: ParamSet, key_id: u64) -> Vec<u8> {
+    let kind = ObjectKind::TLWELv1;
+    encode_list(kind, cs, TLWE_LV1_LEN, Encoder::tlwe_lv1, params, key_id)
+}
+
+pub fn decode_tlwe_lv1(bytes: &[u8]) -> Result<(Header, Vec<CipherTLWELv1>), FormatError> {
+    decode_list(bytes, ObjectKind::TLWELv1, TLWE_LV1_LEN, |d| d.tlwe_lv1())
+}
+
+pub fn encode_trlwe(cs: &[CipherTRLWE], params: ParamSet, key_id: u64) -> Vec<u8> {
+    let kind = ObjectKind::TRLWE;
+    encode_list(kind, cs, TRLWE_LEN, Encoder::trlwe, params, key_id)
+}
+
+pub fn decode_trlwe(bytes: &[u8]) -> Result<(Header, Vec<CipherTRLWE>), FormatError> {
+    decode_list(bytes, ObjectKind::TRLWE, TRLWE_LEN, |d| d.trlwe())
+}
+
+pub fn encode_trgsw(cs: &[TRGSWMatrix], params: ParamSet, key_id: u64) -> Vec<u8> {
+    let kind = ObjectKind::TRGSW;
+    encode_list(kind, cs, TRGSW_LEN, Encoder::trgsw, params, key_id)
+}
+
+pub fn decode_trgsw(bytes: &[u8]) -> Result<(Header, Vec<TRGSWMatrix>), FormatError> {
+    decode_list(bytes, ObjectKind::TRGSW, TRGSW_LEN, |d| d.trgsw())
+}
+
+pub fn encode_bundle(bundle: &CiphertextBundle, params: ParamSet, key_id: u64) -> Vec<u8> {
+    let mut e = Encoder(Vec::with_capacity(6 + bundle.len() * TLWE_LV0_LEN));
+    e.encoding(bundle.encoding);
+    e.u32(bundle.len() as u32);
+    for c in bundle.ciphertexts.iter() {
+        e.tlwe_lv0(c);
+    }
+    encode_object(ObjectKind::CiphertextBundle, params, key_id, &e.0)
+}
+
+pub fn decode_bundle(bytes: &[u8]) -> Result<(Header, CiphertextBundle), FormatError> {
+    let (header, payload) = decode_object(bytes)?;
+    expect_kind(&header, ObjectKind::CiphertextBundle)?;
+
+    let mut d = Decoder(payload);
+    let encoding = d.encoding()?;
+    let n = d.count(TLWE_LV0_LEN)?;
+    if n % encoding.unit() != 0 {
+        return Err(FormatError::Invalid(format!(
+            "{} ciphertexts do not fill {}-bit values",
+            n,
+            encoding.unit()
+        )));
+    }
+    let mut ciphertexts = Vec::with_capacity(n);
+    for _ in 0..n {
+        ciphertexts.push(d.tlwe_lv0()?);
+    }
+    d.finish()?;
+
+    Ok((
+        header,
+        CiphertextBundle {
+            encoding,
+            ciphertexts,
+        },
+    ))
+}
+
+#[derive(Clone, Debug)]
+pub struct Inspection {
+    pub header: Header,
+    // decode_object と同じ確認 (長さと checksum)
+    pub checksum: Result<(), FormatError>,
+    // 鍵がなくてもわかる中身の要約 (項目名と値)
+    pub contents: Result<Vec<(String, String)>, FormatError>,
+}
+
+fn lowercase_name(x: &dyn fmt::Debug) -> String {
+    format!("{:?}", x).to_lowercase()
+}
+
+fn distribution_name(id: u8) -> Result<String, FormatError> {
+    let dists = [
+        KeyDistribution::Binary,
+        KeyDistribution::Ternary,
+        KeyDistribution::Gaussian,
+    ];
+    match dists.iter().find(|&&dist| distribution_id(dist) == id) {
+        Some(dist) => Ok(lowercase_name(dist)),
+        None => Err(FormatError::Invalid(format!(
+            "unknown key distribution {}",
+            id
+        ))),
+    }
+}
+
+pub(crate) fn encoding_name(encoding: Encoding) -> String {
+    match encoding {
+        Encoding::Bits => "bits".to_string(),
+        Encoding::Integers { width } => format!("{}-bit integers", width),
+        Encoding::Bytes => "bytes".to_string(),
+    }
+}
+
+fn layout_name(id: u8) -> Result<String, FormatError> {
+    let layouts = [
+        BootstrappingKeyLayout::Binary,
+        BootstrappingKeyLayout::Ternary,
+        BootstrappingKeyLayout::Bundled,
+    ];
+    match layouts.iter().find(|&&layout| layout_id(layout) == id) {
+        Some(layout) => Ok(lowercase_name(layout)),
+        None => Err(FormatError::Invalid(format!(
+            "unknown bootstrapping key layout {}",
+            id
+        ))),
+    }
+}
+
+// 要素数を読んで中身を読み飛ばす
+fn skip_list(
+    d: &mut Decoder,
+    name: &str,
+    elem_len: usize,
+    contents: &mut Vec<(String, String)>,
+) -> Result<usize, FormatError> {
+    let n = d.count(elem_len)?;
+    d.bytes(n * elem_len)?;
+    contents.push((name.to_string(), n.to_string()));
+    Ok(n)
+}
+
+fn summarize(header: &Header, payload: &[u8]) -> Result<Vec<(String, String)>, FormatError> {
+    let mut contents = Vec::new();
+    let mut d = Decoder(payload);
+    match header.kind {
+        ObjectKind::SecretKey => {
+            let lv0 = distribution_name(d.u8()?)?;
+            let lv1 = distribution_name(d.u8()?)?;
+            d.bytes(4 * super::params::tlwe::N)?;
+            d.bytes(4 * super::params::trlwe::N)?;
+            let n0 = super::params::tlwe::N;
+            let n1 = super::params::trlwe::N;
+            contents.push((
+                "lv0 key".to_string(),
+                format!("{} ({} coefficients)", lv0, n0),
+            ));
+            contents.push((
+                "lv1 key".to_string(),
+                format!("{} ({} coefficients)", lv1, n1),
+            ));
+        }
+        ObjectKind::CloudKey | ObjectKind::BootstrappingKey | ObjectKind::KeySwitchingKey => {
+            if header.kind != ObjectKind::KeySwitchingKey {
+                contents.push(("layout".to_string(), layout_name(d.u8()?)?));
+                skip_list(&mut d, "TRGSW", TRGSW_LEN, &mut contents)?;
+            }
+            if header.kind != ObjectKind::BootstrappingKey {
+                skip_list(&mut d, "TLWE lv0", TLWE_LV0_LEN, &mut contents)?;
+            }
+        }
+        ObjectKind::CiphertextBundle => {
+            let encoding = encoding_name(d.encoding()?);
+            contents.push(("encoding".to_string(), encoding));
+            skip_list(&mut d, "TLWE lv0", TLWE_LV0_LEN, &mut contents)?;
+        }
+        ObjectKind::TLWELv1 => {
+            skip_list(&mut d, "TLWE lv1", TLWE_LV1_LEN, &mut contents)?;
+        }
+        ObjectKind::TRLWE => {
+            skip_list(&mut d, "TRLWE", TRLWE_LEN, &mut contents)?;
+        }
+        ObjectKind::TRGSW => {
+            skip_list(&mut d, "TRGSW", TRGSW_LEN, &mut contents)?;
+        }
+        // inspect で stream::inspect_stream に回している
+        ObjectKind::CiphertextStream => unreachable!(),
+    }
+    d.finish()?;
+    Ok(contents)
+}
+
+// 鍵を使わずにファイルの中身を調べる. ヘッダが読めなければエラー
+pub fn inspect(bytes: &[u8]) -> Result<Inspection, FormatError> {
+    let header = read_header(bytes)?;
+    if header.kind == ObjectKind::CiphertextStream {
+        return Ok(super::stream::inspect_stream(header, bytes));
+    }
+    let body = &bytes[HEADER_LEN..];
+    let len = usize::try_from(header.payload_len).map_or(body.len(), |len| len.min(body.len()));
+    Ok(Inspection {
+        header,
+        checksum: decode_object(bytes).map(|_| ()),
+        contents: summarize(&header, &body[..len]),
+    })
+}
+
+#[test]
+fn test_secret_key_roundtrip() {
+    let params = ParamSet::Gaussian;
+    let sk = params.secret_key();
+    let key_id = new_key_id();
+    let bytes = encode_secret_key(&sk, params, key_id);
+
+    let (header, sk2) = decode_secret_key(&bytes).unwrap();
+    assert_eq!(header.kind, ObjectKind::SecretKey);
+    assert_eq!(header.params, params);
+    assert_eq!(header.key_id, key_id);
+    assert_eq!((sk2.lv0, sk2.lv1), (sk.lv0, sk.lv1));
+    assert_eq!((sk2.lv0_dist, sk2.lv1_dist), (sk.lv0_dist, sk.lv1_dist));
+
+    // 壊れたファイル
+    let mut broken = bytes.clone();
+    broken[HEADER_LEN + 10] ^= 1;
+    assert_eq!(
+        decode_secret_key(&broken).err(),
+        Some(FormatError::ChecksumMismatch)
+    );
+    assert_eq!(
+        decode_secret_key(&bytes[..bytes.len() - 1]).err(),
+        Some(FormatError::Truncated)
+    );
+    assert_eq!(
+        decode_secret_key(b"not a key file at all....").err(),
+        Some(FormatError::BadMagic)
+    );
+    assert_eq!(
+        decode_cloud_key(&bytes).err(),
+        Some(FormatError::UnexpectedKind {
+            expected: ObjectKind::CloudKey,
+            found: ObjectKind::SecretKey
+        })
+    );
+}
+
+#[test]
+fn test_cloud_key_roundtrip() {
+    let params = ParamSet::Binary;
+    let sk = params.secret_key();
+    let ck = params.cloud_key(sk);
+    let key_id = new_key_id();
+    let bytes = encode_cloud_key(&ck, params, key_id);
+
+    let (header, ck2) = decode_cloud_key(&bytes).unwrap();
+    assert_eq!(header.kind, ObjectKind::CloudKey);
+    assert_eq!(header.key_id, key_id);
+    assert_eq!(ck2.bk.layout(), ck.bk.layout());
+    assert!(ck2.bk.0 == ck.bk.0);
+    for (c1, c2) in ck.ks.0.iter().zip(ck2.ks.0.iter()) {
+        assert_eq!(c1.describe(), c2.describe());
+    }
+}
+
+#[test]
+fn test_bundle_roundtrip() {
+    use super::bundle::Plaintext;
+    use super::tlwe::TLWE;
+
+    let params = ParamSet::Binary;
+    let sk = params.secret_key();
+    let tlwe = TLWE::new(sk);
+    let p = Plaintext::Integers {
+        width: 5,
+        values: vec![3, 31, 0],
+    };
+    let bundle = CiphertextBundle::encrypt(&p, &tlwe);
+    let bytes = encode_bundle(&bundle, params, 7);
+
+    let (header, bundle2) = decode_bundle(&bytes).unwrap();
+    assert_eq!(header.kind, ObjectKind::CiphertextBundle);
+    assert_eq!(header.key_id, 7);
+    assert_eq!(bundle2.encoding, bundle.encoding);
+    assert_eq!(bundle2.decrypt(&tlwe), p);
+}
+
+#[test]
+fn test_inspect() {
+    use super::trlwe::TRLWE;
+
+    let params = ParamSet::Ternary;
+    let sk = params.secret_key();
+    let c = TRLWE::new(sk).encrypt_torus([0; super::params::trlwe::N]);
+    let bytes = encode_trlwe(&[c, c], params, 7);
+    assert_eq!(decode_trlwe(&bytes).unwrap().1.len(), 2);
+
+    let info = inspect(&bytes).unwrap();
+    assert_eq!(info.header.kind, ObjectKind::TRLWE);
+    assert_eq!(info.header.params, params);
+    assert!(info.checksum.is_ok());
+    assert_eq!(
+        info.contents.unwrap(),
+        vec![("TRLWE".to_string(), "2".to_string())]
+    );
+
+    let info = inspect(&encode_secret_key(&sk, params, 7)).unwrap();
+    assert_eq!(info.contents.unwrap()[0].1, "ternary (635 coefficients)");
+
+    // 壊れていてもヘッダは読める
+    let mut broken = bytes.clone();
+    broken[HEADER_LEN + 100] ^= 1;
+    let info = inspect(&broken).unwrap();
+    assert_eq!(info.checksum, Err(FormatError::ChecksumMismatch));
+    assert!(info.contents.is_ok());
+    let info = inspect(&bytes[..bytes.len() - 100]).unwrap();
+    assert_eq!(info.checksum, Err(FormatError::Truncated));
+    assert_eq!(info.contents, Err(FormatError::Truncated));
+    assert!(inspect(b"KFHE").is_err());
+}
@@ -0,0 +1,614 @@
+// 暗号文の評価を別のプロセスに任せるための簡単なプロトコル
+//
+// フレームはすべてリトルエンディアン
+//   len u32 | tag u8 | body (len - 1 バイト)
+// クライアントは最初に cloud key を送り (UploadKey)、そのあと回路の id と入力の束を送る (Evaluate)
+// サーバは 1 つの要求に KeyAccepted, Outputs, Error のどれか 1 つで答える
+// 鍵と束は serialize の形式のまま送る. cloud key は接続ごとに持つ
+// 相手は認証しないので、フレームの長さは種類ごとに上限を決め、読んだ分だけバッファを伸ばす
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use super::bundle::{CiphertextBundle, Encoding};
+use super::circuit::Netlist;
+use super::estimate::sizes;
+use super::key::CloudKey;
+use super::optimizer::optimize;
+use super::params::ParamSet;
+use super::scheduler::Scheduler;
+use super::serialize::{
+    decode_bundle, decode_cloud_key, encode_bundle, read_header, Decoder, Encoder, FormatError,
+    Header, HEADER_LEN,
+};
+
+// Evaluate と Outputs の束の合計. TLWE lv0 で 6000 ビットほど
+pub const MAX_BUNDLES_LEN: usize = 16 << 20;
+// Error のメッセージ
+const MAX_ERROR_LEN: usize = 1 << 16;
+// 同時に処理する接続の数
+pub const MAX_CONNECTIONS: usize = 64;
+
+// どのパラメータの cloud key も収まる大きさ. ヘッダと個数、checksum の分を足す
+fn max_cloud_key_len() -> usize {
+    let key_len = |params| {
+        let s = sizes(params);
+        s.bootstrapping_key + s.key_switching_key
+    };
+    HEADER_LEN + 64 + ParamSet::ALL.iter().map(|&p| key_len(p)).max().unwrap()
+}
+
+// tag を除いた本体の長さの上限. 知らない tag なら None
+fn max_body_len(tag: u8) -> Option<usize> {
+    match tag {
+        1 => Some(max_cloud_key_len()),
+        2 | 4 => Some(MAX_BUNDLES_LEN),
+        3 => Some(8),
+        5 => Some(MAX_ERROR_LEN),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Message {
+    UploadKey(Vec<u8>),
+    Evaluate {
+        circuit: String,
+        inputs: Vec<Vec<u8>>,
+    },
+    KeyAccepted {
+        key_id: u64,
+    },
+    // 出力ポートごとの束
+    Outputs(Vec<Vec<u8>>),
+    Error(String),
+}
+
+impl Message {
+    fn tag(&self) -> u8 {
+        match self {
+            Message::UploadKey(_) => 1,
+            Message::Evaluate { .. } => 2,
+            Message::KeyAccepted { .. } => 3,
+            Message::Outputs(_) => 4,
+            Message::Error(_) => 5,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Message::UploadKey(_) => "UploadKey",
+            Message::Evaluate { .. } => "Evaluate",
+            Message::KeyAccepted { .. } => "KeyAccepted",
+            Message::Outputs(_) => "Outputs",
+            Message::Error(_) => "Error",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ProtocolError {
+    Io(io::Error),
+    Format(FormatError),
+    // 相手が Error を返した
+    Remote(String),
+    Unexpected(String),
+}
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProtocolError::Io(e) => write!(f, "{}", e),
+            ProtocolError::Format(e) => write!(f, "{}", e),
+            ProtocolError::Remote(msg) => write!(f, "server: {}", msg),
+            ProtocolError::Unexpected(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for ProtocolError {}
+
+impl From<io::Error> for ProtocolError {
+    fn from(e: io::Error) -> Self {
+        ProtocolError::Io(e)
+    }
+}
+
+impl From<FormatError> for ProtocolError {
+    fn from(e: FormatError) -> Self {
+        ProtocolError::Format(e)
+    }
+}
+
+fn unexpected(msg: &Message) -> ProtocolError {
+    ProtocolError::Unexpected(format!("unexpected message: {}", msg.name()))
+}
+
+fn encode_blobs(e: &mut Encoder, blobs: &[Vec<u8>]) {
+    e.u32(blobs.len() as u32);
+    for b in blobs {
+        e.u32(b.len() as u32);
+        e.0.extend_from_slice(b);
+    }
+}
+
+fn decode_blobs(d: &mut Decoder) -> Result<Vec<Vec<u8>>, FormatError> {
+    let n = d.count(4)?;
+    (0..n)
+        .map(|_| {
+            let len = d.u32()? as usize;
+            Ok(d.bytes(len)?.to_vec())
+        })
+        .collect()
+}
+
+pub fn write_message<W: Write>(w: &mut W, msg: &Message) -> Result<(), ProtocolError> {
+    let mut e = Encoder::default();
+    // 長さはあとで埋める
+    e.u32(0);
+    e.u8(msg.tag());
+    match msg {
+        Message::UploadKey(key) => e.0.extend_from_slice(key),
+        Message::Evaluate { circuit, inputs } => {
+            e.u32(circuit.len() as u32);
+            e.0.extend_from_slice(circuit.as_bytes());
+            encode_blobs(&mut e, inputs);
+        }
+        Message::KeyAccepted { key_id } => e.u64(*key_id),
+        Message::Outputs(outputs) => encode_blobs(&mut e, outputs),
+        Message::Error(msg) => e.0.extend_from_slice(msg.as_bytes()),
+    }
+    let len = e.0.len() - 4;
+    if Some(len - 1) > max_body_len(msg.tag()) {
+        return Err(ProtocolError::Unexpected(format!(
+            "{} message of {} bytes is too large",
+            msg.name(),
+            len
+        )));
+    }
+    e.0[..4].copy_from_slice(&(len as u32).to_le_bytes());
+    w.write_all(&e.0)?;
+    w.flush()?;
+    Ok(())
+}
+
+// 相手が接続を閉じていたら None
+pub fn read_message<R: Read>(r: &mut R) -> Result<Option<Message>, ProtocolError> {
+    let mut len = [0; 4];
+    match r.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+    let len = u32::from_le_bytes(len) as usize;
+    if len == 0 {
+        return Err(ProtocolError::Unexpected("bad frame length: 0".to_string()));
+    }
+    let mut tag = [0; 1];
+    r.read_exact(&mut tag)?;
+    let tag = tag[0];
+    match max_body_len(tag) {
+        Some(max) if len - 1 > max => {
+            return Err(ProtocolError::Unexpected(format!(
+                "frame of {} bytes is too large for message tag {}",
+                len, tag
+            )))
+        }
+        Some(_) => {}
+        None => {
+            return Err(ProtocolError::Unexpected(format!(
+                "unknown message tag: {}",
+                tag
+            )))
+        }
+    }
+
+    // 長さを信用して先に確保せず、届いた分だけ読む
+    let mut body = Vec::new();
+    r.by_ref().take((len - 1) as u64).read_to_end(&mut body)?;
+    if body.len() != len - 1 {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+    }
+
+    let mut d = Decoder(&body);
+    let msg = match tag {
+        1 => Message::UploadKey(d.bytes(body.len())?.to_vec()),
+        2 => {
+            let n = d.u32()? as usize;
+            let circuit = String::from_utf8(d.bytes(n)?.to_vec())
+                .map_err(|_| FormatError::Invalid("circuit id is not UTF-8".to_string()))?;
+            let inputs = decode_blobs(&mut d)?;
+            Message::Evaluate { circuit, inputs }
+        }
+        3 => Message::KeyAccepted { key_id: d.u64()? },
+        4 => Message::Outputs(decode_blobs(&mut d)?),
+        5 => Message::Error(String::from_utf8_lossy(d.bytes(body.len())?).into_owned()),
+        _ => unreachable!(),
+    };
+    d.finish()?;
+    Ok(Some(msg))
+}
+
+pub struct Server {
+    circuits: HashMap<String, Netlist>,
+    threads: usize,
+}
+
+impl Server {
+    pub fn new(threads: usize) -> Self {
+        assert!(threads > 0);
+        Self {
+            circuits: HashMap::new(),
+            threads,
+        }
+    }
+
+    // 登録するときに最適化しておく
+    pub fn add_circuit(&mut self, id: &str, netlist: Netlist) {
+        let (circuit, _) = optimize(&netlist.circuit);
+        self.circuits
+            .insert(id.to_string(), Netlist { circuit, ..netlist });
+    }
+
+    pub fn circuits(&self) -> Vec<&str> {
+        let mut ids: Vec<&str> = self.circuits.keys().map(|id| id.as_str()).collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    // 1 つの接続を相手が閉じるまで処理する
+    pub fn handle<S: Read + Write>(&self, mut stream: S) -> Result<(), ProtocolError> {
+        let mut key: Option<(Header, Arc<CloudKey>)> = None;
+        while let Some(msg) = read_message(&mut stream)? {
+            let reply = match msg {
+                Message::UploadKey(bytes) => match decode_cloud_key(&bytes) {
+                    Ok((header, ck)) => {
+                        key = Some((header, Arc::new(ck)));
+                        Message::KeyAccepted {
+                            key_id: header.key_id,
+                        }
+                    }
+                    Err(e) => Message::Error(format!("cloud key: {}", e)),
+                },
+                Message::Evaluate { circuit, inputs } => match &key {
+                    Some((header, ck)) => match self.evaluate(header, ck, &circuit, &inputs) {
+                        Ok(outputs) => Message::Outputs(outputs),
+                        Err(e) => Message::Error(e),
+                    },
+                    None => Message::Error("no cloud key has been uploaded".to_string()),
+                },
+                msg => Message::Error(format!("unexpected message: {}", msg.name())),
+            };
+            write_message(&mut stream, &reply)?;
+        }
+        Ok(())
+    }
+
+    fn evaluate(
+        &self,
+        header: &Header,
+        ck: &Arc<CloudKey>,
+        id: &str,
+        inputs: &[Vec<u8>],
+    ) -> Result<Vec<Vec<u8>>, String> {
+        let netlist = match self.circuits.get(id) {
+            Some(n) => n,
+            None => return Err(format!("unknown circuit: {}", id)),
+        };
+        if inputs.len() != netlist.inputs.len() {
+            return Err(format!(
+                "circuit {} has {} input ports, but {} bundles are given",
+                id,
+                netlist.inputs.len(),
+                inputs.len()
+            ));
+        }
+
+        let mut bits = Vec::new();
+        for (port, bytes) in netlist.inputs.iter().zip(inputs) {
+            let (h, bundle) = decode_bundle(bytes).map_err(|e| format!("{}: {}", port.name, e))?;
+            if h.key_id != header.key_id {
+                return Err(format!("{} was encrypted under a different key", port.name));
+            }
+            if bundle.len() != port.width {
+                return Err(format!(
+                    "input port {} is {} bits wide, but {} ciphertexts are given",
+                    port.name,
+                    port.width,
+                    bundle.len()
+                ));
+            }
+            bits.extend(bundle.ciphertexts);
+        }
+
+        let outputs =
+            Scheduler::new(Arc::clone(ck), self.threads).evaluate(&netlist.circuit, &bits);
+        let mut rest = &outputs[..];
+        Ok(netlist
+            .outputs
+            .iter()
+            .map(|port| {
+                let (bits, tail) = rest.split_at(port.width);
+                rest = tail;
+                let bundle = CiphertextBundle {
+                    encoding: Encoding::for_width(port.width),
+                    ciphertexts: bits.to_vec(),
+                };
+                encode_bundle(&bundle, header.params, header.key_id)
+            })
+            .collect())
+    }
+}
+
+// 処理中の接続を数える. スレッドが panic しても drop で戻す
+struct Active(Arc<AtomicUsize>);
+
+impl Drop for Active {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+// 接続ごとにスレッドを立てる. TcpListener::incoming() と UnixListener::incoming() のどちらも渡せる
+// 接続が MAX_CONNECTIONS を超えたら Error を返して閉じる
+// accept の失敗 (ファイル記述子が足りないなど) は一時的なものとして、少し待って続ける
+pub fn serve<S, I>(server: Arc<Server>, incoming: I) -> io::Result<()>
+where
+    S: Read + Write + Send + 'static,
+    I: Iterator<Item = io::Result<S>>,
+{
+    let active = Arc::new(AtomicUsize::new(0));
+    for stream in incoming {
+        let mut stream = match stream {
+            Ok(s) => s,
+            Err(e) => {
+                eprintln!("accept failed: {}", e);
+                thread::sleep(Duration::from_millis(100));
+                continue;
+            }
+        };
+        if active.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+            active.fetch_sub(1, Ordering::SeqCst);
+            let busy = Message::Error("too many connections".to_string());
+            let _ = write_message(&mut stream, &busy);
+            continue;
+        }
+        let guard = Active(Arc::clone(&active));
+        let server = Arc::clone(&server);
+        thread::spawn(move || {
+            let _guard = guard;
+            if let Err(e) = server.handle(stream) {
+                eprintln!("connection closed: {}", e);
+            }
+        });
+    }
+    Ok(())
+}
+
+pub struct Client<S> {
+    stream: S,
+    key: Option<Header>,
+}
+
+impl<S: Read + Write> Client<S> {
+    pub fn new(stream: S) -> Self {
+        Self { stream, key: None }
+    }
+
+    fn call(&mut self, msg: &Message) -> Result<Message, ProtocolError> {
+        write_message(&mut self.stream, msg)?;
+        match read_message(&mut self.stream)? {
+            Some(Message::Error(e)) => Err(ProtocolError::Remote(e)),
+            Some(reply) => Ok(reply),
+            None => Err(ProtocolError::Unexpected(
+                "connection closed by server".to_string(),
+            )),
+        }
+    }
+
+    // serialize の形式の cloud key を送り、サーバが受け取った key_id を返す
+    pub fn upload_key(&mut self, cloud_key: Vec<u8>) -> Result<u64, ProtocolError> {
+        let header = read_header(&cloud_key)?;
+        match self.call(&Message::UploadKey(cloud_key))? {
+            Message::KeyAccepted { key_id } if key_id == header.key_id => {
+                self.key = Some(header);
+                Ok(key_id)
+            }
+            reply => Err(unexpected(&reply)),
+        }
+    }
+
+    // 入力ポートごとの束を送り、出力ポートごとの束を受け取る
+    pub fn evaluate(
+        &mut self,
+        circuit: &str,
+        inputs: &[CiphertextBundle],
+    ) -> Result<Vec<CiphertextBundle>, ProtocolError> {
+        let header = match self.key {
+            Some(h) => h,
+            None => {
+                return Err(ProtocolError::Unexpected(
+                    "upload a cloud key first".to_string(),
+                ))
+            }
+        };
+        let inputs = inputs
+            .iter()
+            .map(|b| encode_bundle(b, header.params, header.key_id))
+            .collect();
+        let msg = Message::Evaluate {
+            circuit: circuit.to_string(),
+            inputs,
+        };
+        match self.call(&msg)? {
+            Message::Outputs(outputs) => outputs.iter().map(|o| Ok(decode_bundle(o)?.1)).collect(),
+            reply => Err(unexpected(&reply)),
+        }
+    }
+}
+
+#[test]
+fn test_message_roundtrip() {
+    let messages = [
+        Message::UploadKey(vec![1, 2, 3]),
+        Message::Evaluate {
+            circuit: "adder".to_string(),
+            inputs: vec![vec![4, 5], vec![]],
+        },
+        Message::KeyAccepted { key_id: 42 },
+        Message::Outputs(vec![vec![6]]),
+        Message::Error("oops".to_string()),
+    ];
+    let mut buf = Vec::new();
+    for msg in messages.iter() {
+        write_message(&mut buf, msg).unwrap();
+    }
+    let mut r = &buf[..];
+    for msg in messages.iter() {
+        assert_eq!(read_message(&mut r).unwrap().as_ref(), Some(msg));
+    }
+    assert!(read_message(&mut r).unwrap().is_none());
+
+    // 途中で切れたフレーム
+    let mut r = &buf[..buf.len() - 1];
+    for _ in 0..messages.len() - 1 {
+        read_message(&mut r).unwrap();
+    }
+    assert!(read_message(&mut r).is_err());
+
+    // 種類ごとの上限を超える長さは本体を読む前に断る
+    let frame = |len: u32, tag: u8| {
+        let mut f = len.to_le_bytes().to_vec();
+        f.push(tag);
+        f
+    };
+    for tag in [2, 3, 4, 5] {
+        let len = max_body_len(tag).unwrap() as u32 + 2;
+        assert!(read_message(&mut &frame(len, tag)[..]).is_err());
+    }
+    assert!(read_message(&mut &frame(u32::MAX, 1)[..]).is_err());
+    assert!(read_message(&mut &frame(2, 9)[..]).is_err());
+    // 上限以下でも本体が届かなければエラー
+    assert!(read_message(&mut &frame(1 << 20, 1)[..]).is_err());
+    let big = Message::Evaluate {
+        circuit: "adder".to_string(),
+        inputs: vec![vec![0; MAX_BUNDLES_LEN]],
+    };
+    assert!(write_message(&mut Vec::new(), &big).is_err());
+
+    // cloud key はどのパラメータでも送れる
+    for params in ParamSet::ALL {
+        let s = sizes(params);
+        assert!(HEADER_LEN + s.bootstrapping_key + s.key_switching_key < max_cloud_key_len());
+    }
+}
+
+#[test]
+fn test_client_server() {
+    use super::bundle::Plaintext;
+    use super::circuit::{Circuit, Port};
+    use super::params::ParamSet;
+    use super::serialize::{encode_cloud_key, new_key_id};
+    use super::tlwe::TLWE;
+    use std::net::{TcpListener, TcpStream};
+
+    // 1 ビットの AND と XOR
+    let mut circuit = Circuit::new();
+    let (a, b) = (circuit.input(), circuit.input());
+    let x = circuit.and(a, b);
+    circuit.output(x);
+    let x = circuit.xor(a, b);
+    circuit.output(x);
+    let port = |name: &str, width: usize| Port {
+        name: name.to_string(),
+        width,
+    };
+    let netlist = Netlist {
+        circuit,
+        inputs: vec![port("a", 1), port("b", 1)],
+        outputs: vec![port("and", 1), port("xor", 1)],
+    };
+    let mut server = Server::new(2);
+    server.add_circuit("and_xor", netlist.clone());
+    assert_eq!(server.circuits(), vec!["and_xor"]);
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = Arc::new(server);
+    thread::spawn(move || serve(server, listener.incoming()));
+
+    let params = ParamSet::Binary;
+    let sk = params.secret_key();
+    let tlwe = TLWE::new(sk);
+    let key_id = new_key_id();
+    let ck = encode_cloud_key(&params.cloud_key(sk), params, key_id);
+    let encrypt = |b: bool| CiphertextBundle::encrypt(&Plaintext::Bits(vec![b]), &tlwe);
+
+    let mut client = Client::new(TcpStream::connect(addr).unwrap());
+    // 鍵を送る前は評価できない
+    assert!(client.evaluate("and_xor", &[]).is_err());
+    assert_eq!(client.upload_key(ck.clone()).unwrap(), key_id);
+
+    let outputs = client
+        .evaluate("and_xor", &[encrypt(true), encrypt(true)])
+        .unwrap();
+    let outputs: Vec<Plaintext> = outputs.iter().map(|o| o.decrypt(&tlwe)).collect();
+    assert_eq!(
+        outputs,
+        vec![Plaintext::Bits(vec![true]), Plaintext::Bits(vec![false])]
+    );
+
+    // エラーを返しても接続は続く
+    match client.evaluate("unknown", &[]) {
+        Err(ProtocolError::Remote(msg)) => assert_eq!(msg, "unknown circuit: unknown"),
+        r => panic!("{:?}", r.map(|o| o.len())),
+    }
+    assert!(client.evaluate("and_xor", &[encrypt(true)]).is_err());
+    let outputs = client
+        .evaluate("and_xor", &[encrypt(false), encrypt(true)])
+        .unwrap();
+    assert_eq!(outputs[1].decrypt(&tlwe), Plaintext::Bits(vec![true]));
+
+    // Unix ソケットでも同じ
+    #[cfg(unix)]
+    {
+        use std::os::unix::net::{UnixListener, UnixStream};
+
+        let path = std::env::temp_dir().join(format!("kfhe-test-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        let mut server = Server::new(1);
+        server.add_circuit("and_xor", netlist);
+        thread::spawn(move || serve(Arc::new(server), listener.incoming()));
+
+        let mut client = Client::new(UnixStream::connect(&path).unwrap());
+        client.upload_key(ck).unwrap();
+        let outputs = client
+            .evaluate("and_xor", &[encrypt(true), encrypt(false)])
+            .unwrap();
+        assert_eq!(outputs[0].decrypt(&tlwe), Plaintext::Bits(vec![false]));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
+
+#[test]
+fn test_serve_accept_error() {
+    use std::net::{TcpListener, TcpStream};
+
+    // accept に失敗しても次の接続を受ける
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut client = Client::new(TcpStream::connect(listener.local_addr().unwrap()).unwrap());
+    let (stream, _) = listener.accept().unwrap();
+    let incoming = vec![Err(io::Error::from(io::ErrorKind::Other)), Ok(stream)];
+    thread::spawn(move || serve(Arc::new(Server::new(1)), incoming.into_iter()));
+
+    match client.call(&Message::UploadKey(vec![0; 8])) {
+        Err(ProtocolError::Remote(msg)) => assert!(msg.starts_with("cloud key:"), "{}", msg),
+        r => panic!("{:?}", r),
+    }
+}
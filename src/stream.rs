@@ -0,0 +1,489 @@
+// 大きなデータを一定のメモリで暗号化、評価、復号するための形式
+//
+// 数値はすべてリトルエンディアン
+//   header (serialize と同じ 24 バイト, kind = ciphertext stream, payload_len = 0)
+//   encoding tag u8 | width u8 | chunk_len u32
+//   チャンク: count u32 | TLWE lv0 × count | checksum u64
+//   終端:     0 u32 | total u64 | checksum u64
+// checksum はそのチャンクの count から checksum の直前までの FNV-1a
+// count は chunk_len 以下で encoding の 1 値のビット数の倍数 (値がチャンクをまたがない)
+
+use std::io::{self, Read, Write};
+
+use super::bundle::Encoding;
+use super::circuit::Circuit;
+use super::key::CloudKey;
+use super::params::ParamSet;
+use super::scheduler::Scheduler;
+use super::serialize::{
+    encode_header, encoding_name, read_header, Decoder, Encoder, Fnv1a, FormatError, Header,
+    Inspection, ObjectKind, HEADER_LEN, TLWE_LV0_LEN,
+};
+use super::tlwe::{CipherTLWELv0, TLWE};
+
+pub const DEFAULT_CHUNK_LEN: usize = 4096;
+pub const MAX_CHUNK_LEN: usize = 1 << 16;
+const STREAM_HEADER_LEN: usize = HEADER_LEN + 6;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StreamHeader {
+    pub params: ParamSet,
+    pub key_id: u64,
+    pub encoding: Encoding,
+    pub chunk_len: usize,
+}
+
+impl StreamHeader {
+    // chunk_len は encoding の倍数に切り下げ、範囲に収める
+    pub fn new(params: ParamSet, key_id: u64, encoding: Encoding, chunk_len: usize) -> Self {
+        let unit = encoding.unit();
+        let chunk_len = chunk_len.clamp(unit, MAX_CHUNK_LEN);
+        Self {
+            params,
+            key_id,
+            encoding,
+            chunk_len: chunk_len - chunk_len % unit,
+        }
+    }
+
+    fn validate(&self) -> Result<(), FormatError> {
+        let unit = self.encoding.unit();
+        if self.chunk_len == 0
+            || self.chunk_len > MAX_CHUNK_LEN
+            || !self.chunk_len.is_multiple_of(unit)
+        {
+            return Err(FormatError::Invalid(format!(
+                "chunk length {} for {}",
+                self.chunk_len,
+                encoding_name(self.encoding)
+            )));
+        }
+        Ok(())
+    }
+}
+
+fn invalid(e: FormatError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+// Reader や Writer の返すエラーを FormatError に戻す
+pub fn format_error(e: io::Error) -> FormatError {
+    if e.kind() == io::ErrorKind::UnexpectedEof {
+        return FormatError::Truncated;
+    }
+    let msg = e.to_string();
+    match e.into_inner().map(|inner| inner.downcast::<FormatError>()) {
+        Some(Ok(e)) => *e,
+        _ => FormatError::Invalid(msg),
+    }
+}
+
+// chunk_len 個たまるごとにチャンクを書き出す
+pub struct StreamWriter<W: Write> {
+    w: W,
+    header: StreamHeader,
+    chunk: Vec<CipherTLWELv0>,
+    total: u64,
+}
+
+impl<W: Write> StreamWriter<W> {
+    pub fn new(mut w: W, header: StreamHeader) -> io::Result<Self> {
+        header.validate().map_err(invalid)?;
+        let mut e = Encoder(Vec::with_capacity(STREAM_HEADER_LEN));
+        encode_header(
+            &mut e,
+            ObjectKind::CiphertextStream,
+            header.params,
+            header.key_id,
+            0,
+        );
+        e.encoding(header.encoding);
+        e.u32(header.chunk_len as u32);
+        w.write_all(&e.0)?;
+        Ok(Self {
+            w,
+            header,
+            chunk: Vec::with_capacity(header.chunk_len),
+            total: 0,
+        })
+    }
+
+    pub fn header(&self) -> &StreamHeader {
+        &self.header
+    }
+
+    // これまでに書いた (バッファ中のものを含む) 暗号文の数
+    pub fn len(&self) -> u64 {
+        self.total + self.chunk.len() as u64
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn push(&mut self, c: CipherTLWELv0) -> io::Result<()> {
+        self.chunk.push(c);
+        if self.chunk.len() == self.header.chunk_len {
+            self.write_chunk()?;
+        }
+        Ok(())
+    }
+
+    pub fn extend<I: IntoIterator<Item = CipherTLWELv0>>(&mut self, cs: I) -> io::Result<()> {
+        for c in cs {
+            self.push(c)?;
+        }
+        Ok(())
+    }
+
+    fn write_chunk(&mut self) -> io::Result<()> {
+        let mut e = Encoder(Vec::with_capacity(4 + self.chunk.len() * TLWE_LV0_LEN + 8));
+        e.u32(self.chunk.len() as u32);
+        for c in self.chunk.iter() {
+            e.tlwe_lv0(c);
+        }
+        let mut h = Fnv1a::default();
+        h.update(&e.0);
+        e.u64(h.0);
+        self.w.write_all(&e.0)?;
+        self.total += self.chunk.len() as u64;
+        self.chunk.clear();
+        Ok(())
+    }
+
+    // 残りのチャンクと終端を書いて、中の Writer を返す
+    pub fn finish(mut self) -> io::Result<W> {
+        let unit = self.header.encoding.unit() as u64;
+        if !self.len().is_multiple_of(unit) {
+            return Err(invalid(FormatError::Invalid(format!(
+                "{} ciphertexts do not fill {}",
+                self.len(),
+                encoding_name(self.header.encoding)
+            ))));
+        }
+        if !self.chunk.is_empty() {
+            self.write_chunk()?;
+        }
+        let mut e = Encoder::default();
+        e.u32(0);
+        e.u64(self.total);
+        let mut h = Fnv1a::default();
+        h.update(&e.0);
+        e.u64(h.0);
+        self.w.write_all(&e.0)?;
+        self.w.flush()?;
+        Ok(self.w)
+    }
+}
+
+// チャンクごとに checksum を確かめながら読む. Iterator としては暗号文を 1 つずつ返す
+pub struct StreamReader<R: Read> {
+    r: R,
+    header: StreamHeader,
+    chunk: std::vec::IntoIter<CipherTLWELv0>,
+    chunks: usize,
+    total: u64,
+    done: bool,
+}
+
+impl<R: Read> StreamReader<R> {
+    pub fn new(mut r: R) -> io::Result<Self> {
+        let mut buf = [0; STREAM_HEADER_LEN];
+        r.read_exact(&mut buf)?;
+        let header = read_header(&buf).map_err(invalid)?;
+        if header.kind != ObjectKind::CiphertextStream {
+            return Err(invalid(FormatError::UnexpectedKind {
+                expected: ObjectKind::CiphertextStream,
+                found: header.kind,
+            }));
+        }
+        let mut d = Decoder(&buf[HEADER_LEN..]);
+        let encoding = d.encoding().map_err(invalid)?;
+        let chunk_len = d.u32().map_err(invalid)? as usize;
+        let header = StreamHeader {
+            params: header.params,
+            key_id: header.key_id,
+            encoding,
+            chunk_len,
+        };
+        header.validate().map_err(invalid)?;
+        Ok(Self {
+            r,
+            header,
+            chunk: Vec::new().into_iter(),
+            chunks: 0,
+            total: 0,
+            done: false,
+        })
+    }
+
+    pub fn header(&self) -> &StreamHeader {
+        &self.header
+    }
+
+    // 次のチャンクを返す. 終端まで読んだら None
+    // Iterator として途中まで読んだチャンクがあれば、その残りを返す
+    pub fn next_chunk(&mut self) -> io::Result<Option<Vec<CipherTLWELv0>>> {
+        if self.chunk.len() > 0 {
+            return Ok(Some(std::mem::take(&mut self.chunk).collect()));
+        }
+        if self.done {
+            return Ok(None);
+        }
+        let result = self.read_chunk();
+        if !matches!(result, Ok(Some(_))) {
+            self.done = true;
+        }
+        result
+    }
+
+    fn read_chunk(&mut self) -> io::Result<Option<Vec<CipherTLWELv0>>> {
+        let mut h = Fnv1a::default();
+        let mut count = [0; 4];
+        self.r.read_exact(&mut count)?;
+        h.update(&count);
+        let count = u32::from_le_bytes(count) as usize;
+
+        if count == 0 {
+            let mut rest = [0; 16];
+            self.r.read_exact(&mut rest)?;
+            h.update(&rest[..8]);
+            let mut d = Decoder(&rest);
+            let total = d.u64().map_err(invalid)?;
+            if d.u64().map_err(invalid)? != h.0 {
+                return Err(invalid(FormatError::ChecksumMismatch));
+            }
+            if total != self.total {
+                return Err(invalid(FormatError::Invalid(format!(
+                    "stream should have {} ciphertexts, but has {}",
+                    total, self.total
+                ))));
+            }
+            return Ok(None);
+        }
+        if count > self.header.chunk_len || !count.is_multiple_of(self.header.encoding.unit()) {
+            return Err(invalid(FormatError::Invalid(format!(
+                "chunk of {} ciphertexts",
+                count
+            ))));
+        }
+
+        let len = count * TLWE_LV0_LEN;
+        let mut buf = vec![0; len + 8];
+        self.r.read_exact(&mut buf)?;
+        h.update(&buf[..len]);
+        let mut d = Decoder(&buf);
+        let cs = (0..count)
+            .map(|_| d.tlwe_lv0())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(invalid)?;
+        if d.u64().map_err(invalid)? != h.0 {
+            return Err(invalid(FormatError::ChecksumMismatch));
+        }
+        self.chunks += 1;
+        self.total += count as u64;
+        Ok(Some(cs))
+    }
+}
+
+impl<R: Read> Iterator for StreamReader<R> {
+    type Item = io::Result<CipherTLWELv0>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(c) = self.chunk.next() {
+                return Some(Ok(c));
+            }
+            match self.next_chunk() {
+                Ok(Some(cs)) => self.chunk = cs.into_iter(),
+                Ok(None) => return None,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+// バイト列を読みながら暗号化して書き出す. 書いたバイト数を返す
+pub fn encrypt_bytes<R: Read, W: Write>(
+    mut input: R,
+    tlwe: &TLWE,
+    w: &mut StreamWriter<W>,
+) -> io::Result<u64> {
+    assert_eq!(w.header().encoding, Encoding::Bytes);
+    let mut buf = vec![0; w.header().chunk_len / 8];
+    let mut n = 0;
+    loop {
+        let len = match input.read(&mut buf) {
+            Ok(0) => return Ok(n),
+            Ok(len) => len,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        for &byte in &buf[..len] {
+            w.extend((0..8).map(|i| tlwe.encrypt((byte >> i) & 1 == 1)))?;
+        }
+        n += len as u64;
+    }
+}
+
+// 入力のストリームを回路の入力の幅ごとに区切って評価し、出力を順に書く
+// 出力の Writer の encoding は回路の出力の幅の約数にしておく. 評価した回数を返す
+pub fn evaluate<R: Read, W: Write>(
+    scheduler: &Scheduler<CloudKey>,
+    circuit: &Circuit,
+    input: &mut StreamReader<R>,
+    output: &mut StreamWriter<W>,
+) -> io::Result<u64> {
+    let width = circuit.inputs().len();
+    assert!(width > 0);
+    let mut block = Vec::with_capacity(width);
+    let mut n = 0;
+    while let Some(cs) = input.next_chunk()? {
+        for c in cs {
+            block.push(c);
+            if block.len() == width {
+                output.extend(scheduler.evaluate(circuit, &block))?;
+                block.clear();
+                n += 1;
+            }
+        }
+    }
+    if !block.is_empty() {
+        return Err(invalid(FormatError::Invalid(format!(
+            "stream ends in the middle of a {}-bit input",
+            width
+        ))));
+    }
+    Ok(n)
+}
+
+// チャンクをたどって中身を数える
+pub(crate) fn inspect_stream(header: Header, bytes: &[u8]) -> Inspection {
+    let mut reader = match StreamReader::new(bytes) {
+        Ok(reader) => reader,
+        Err(e) => {
+            let e = format_error(e);
+            return Inspection {
+                header,
+                checksum: Err(e.clone()),
+                contents: Err(e),
+            };
+        }
+    };
+    let mut checksum = Ok(());
+    loop {
+        match reader.next_chunk() {
+            Ok(Some(_)) => {}
+            Ok(None) => break,
+            Err(e) => {
+                checksum = Err(format_error(e));
+                break;
+            }
+        }
+    }
+    if checksum.is_ok() && !reader.r.is_empty() {
+        checksum = Err(FormatError::Invalid(format!(
+            "{} trailing bytes",
+            reader.r.len()
+        )));
+    }
+    let h = reader.header;
+    let mut contents = vec![
+        ("encoding".to_string(), encoding_name(h.encoding)),
+        ("chunk length".to_string(), h.chunk_len.to_string()),
+        ("chunks".to_string(), reader.chunks.to_string()),
+        ("TLWE lv0".to_string(), reader.total.to_string()),
+    ];
+    if checksum.is_err() {
+        contents[3].1 += " (read so far)";
+    }
+    Inspection {
+        header,
+        checksum,
+        contents: Ok(contents),
+    }
+}
+
+#[test]
+fn test_stream_roundtrip() {
+    use super::bundle::Plaintext;
+
+    let params = ParamSet::Binary;
+    let sk = params.secret_key();
+    let tlwe = TLWE::new(sk);
+    let key_id = super::serialize::new_key_id();
+
+    // 3 バイトごとのチャンクと、最後の 1 バイトだけのチャンク
+    let data: Vec<u8> = (0..10u8).map(|i| i.wrapping_mul(37)).collect();
+    let header = StreamHeader::new(params, key_id, Encoding::Bytes, 27);
+    assert_eq!(header.chunk_len, 24);
+    let mut w = StreamWriter::new(Vec::new(), header).unwrap();
+    assert_eq!(encrypt_bytes(&data[..], &tlwe, &mut w).unwrap(), 10);
+    let bytes = w.finish().unwrap();
+
+    let reader = StreamReader::new(&bytes[..]).unwrap();
+    assert_eq!(*reader.header(), header);
+    let bits: Vec<bool> = reader.map(|c| tlwe.decrypt(c.unwrap())).collect();
+    assert_eq!(
+        Plaintext::from_bits(Encoding::Bytes, &bits),
+        Plaintext::Bytes(data.clone())
+    );
+
+    let info = super::serialize::inspect(&bytes).unwrap();
+    assert_eq!(info.header.kind, ObjectKind::CiphertextStream);
+    assert_eq!(info.checksum, Ok(()));
+    assert!(info
+        .contents
+        .unwrap()
+        .contains(&("chunks".to_string(), "4".to_string())));
+    assert!(super::serialize::decode_object(&bytes).is_err());
+
+    // 壊れたチャンクは checksum で、途中で切れたものは Truncated になる
+    let read_all = |bytes: &[u8]| -> Result<usize, FormatError> {
+        let reader = StreamReader::new(bytes).map_err(format_error)?;
+        let mut n = 0;
+        for c in reader {
+            c.map_err(format_error)?;
+            n += 1;
+        }
+        Ok(n)
+    };
+    assert_eq!(read_all(&bytes), Ok(80));
+    let mut broken = bytes.clone();
+    broken[STREAM_HEADER_LEN + 4 + 2 * TLWE_LV0_LEN] ^= 1;
+    assert_eq!(read_all(&broken), Err(FormatError::ChecksumMismatch));
+    assert_eq!(
+        read_all(&bytes[..bytes.len() - 20]),
+        Err(FormatError::Truncated)
+    );
+    assert!(super::serialize::inspect(&broken)
+        .unwrap()
+        .checksum
+        .is_err());
+
+    // 2 ビットずつ回路に通す. NOT とそのままの出力なので bootstrapping はしない
+    let mut circuit = Circuit::new();
+    let (x, y) = (circuit.input(), circuit.input());
+    let z = circuit.not(x);
+    circuit.output(z);
+    circuit.output(y);
+    let scheduler = Scheduler::new(std::sync::Arc::new(params.cloud_key(sk)), 2);
+    let header = StreamHeader::new(params, key_id, Encoding::Integers { width: 2 }, 4);
+    let mut output = StreamWriter::new(Vec::new(), header).unwrap();
+    let mut input = StreamReader::new(&bytes[..]).unwrap();
+    assert_eq!(
+        evaluate(&scheduler, &circuit, &mut input, &mut output).unwrap(),
+        40
+    );
+    let out = output.finish().unwrap();
+    let reader = StreamReader::new(&out[..]).unwrap();
+    let out_bits: Vec<bool> = reader.map(|c| tlwe.decrypt(c.unwrap())).collect();
+    let expected: Vec<bool> = bits.chunks(2).flat_map(|b| vec![!b[0], b[1]]).collect();
+    assert_eq!(out_bits, expected);
+
+    // 値の途中で終わるストリームは書けない
+    let header = StreamHeader::new(params, key_id, Encoding::Integers { width: 4 }, 8);
+    let mut w = StreamWriter::new(Vec::new(), header).unwrap();
+    w.extend((0..6).map(|_| tlwe.encrypt(true))).unwrap();
+    assert!(w.finish().is_err());
+}
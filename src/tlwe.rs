@@ -25,6 +25,11 @@ impl CipherTLWELv0 {
         let b = float_to_torus(0.125);
         Self(a, b)
     }
+
+    pub fn trivial(b: Torus) -> Self {
+        let a = [0; tlwe::N];
+        Self(a, b)
+    }
 }
 
 impl std::ops::Add for CipherTLWELv0 {
@@ -68,6 +73,28 @@ impl CipherTLWELv1 {
     }
 }
 
+impl std::ops::Add for CipherTLWELv1 {
+    type Output = Self;
+    fn add(self, rhs: Self) -> Self {
+        let (a0, b0) = self.describe();
+        let (a1, b1) = rhs.describe();
+        let a = vadd(a0, a1);
+        let b = b0.wrapping_add(b1);
+        CipherTLWELv1(a, b)
+    }
+}
+
+impl std::ops::Sub for CipherTLWELv1 {
+    type Output = Self;
+    fn sub(self, rhs: Self) -> Self {
+        let (a0, b0) = self.describe();
+        let (a1, b1) = rhs.describe();
+        let a = vsub(a0, a1);
+        let b = b0.wrapping_sub(b1);
+        CipherTLWELv1(a, b)
+    }
+}
+
 impl std::ops::Neg for CipherTLWELv1 {
     type Output = Self;
     fn neg(self) -> Self {
@@ -0,0 +1,202 @@
+// Bit の上に書いた関数を一度だけ実行して、ゲートの DAG (Circuit) として記録する
+// 記録した回路は平文でも暗号文でも評価できるし、optimizer や scheduler にも渡せる
+
+use std::cell::RefCell;
+use std::ops::{BitAnd, BitOr, BitXor, Not};
+use std::rc::Rc;
+
+use super::bit::Bit;
+use super::circuit::{Circuit, NodeId};
+
+// 記録中の回路のノード
+#[derive(Clone, Debug)]
+pub struct Traced {
+    id: NodeId,
+    circuit: Rc<RefCell<Circuit>>,
+}
+
+impl Traced {
+    pub fn id(&self) -> NodeId {
+        self.id
+    }
+
+    fn push(&self, f: impl FnOnce(&mut Circuit) -> NodeId) -> Traced {
+        let id = f(&mut self.circuit.borrow_mut());
+        Traced {
+            id,
+            circuit: self.circuit.clone(),
+        }
+    }
+
+    fn same_circuit(&self, rhs: &Traced) {
+        assert!(
+            Rc::ptr_eq(&self.circuit, &rhs.circuit),
+            "bits from different tracers"
+        );
+    }
+}
+
+impl BitAnd for Traced {
+    type Output = Traced;
+    fn bitand(self, rhs: Traced) -> Traced {
+        self.same_circuit(&rhs);
+        self.push(|c| c.and(self.id, rhs.id))
+    }
+}
+
+impl BitOr for Traced {
+    type Output = Traced;
+    fn bitor(self, rhs: Traced) -> Traced {
+        self.same_circuit(&rhs);
+        self.push(|c| c.or(self.id, rhs.id))
+    }
+}
+
+impl BitXor for Traced {
+    type Output = Traced;
+    fn bitxor(self, rhs: Traced) -> Traced {
+        self.same_circuit(&rhs);
+        self.push(|c| c.xor(self.id, rhs.id))
+    }
+}
+
+impl Not for Traced {
+    type Output = Traced;
+    fn not(self) -> Traced {
+        self.push(|c| c.not(self.id))
+    }
+}
+
+impl Bit for Traced {
+    fn constant(&self, b: bool) -> Traced {
+        self.push(|c| c.constant(b))
+    }
+
+    fn mux(&self, x: &Traced, y: &Traced) -> Traced {
+        self.same_circuit(x);
+        self.same_circuit(y);
+        self.push(|c| c.mux(self.id, x.id, y.id))
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Tracer {
+    circuit: Rc<RefCell<Circuit>>,
+}
+
+impl Tracer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn input(&self) -> Traced {
+        let id = self.circuit.borrow_mut().input();
+        Traced {
+            id,
+            circuit: self.circuit.clone(),
+        }
+    }
+
+    pub fn output(&self, b: &Traced) {
+        assert!(
+            Rc::ptr_eq(&self.circuit, &b.circuit),
+            "bit from another tracer"
+        );
+        self.circuit.borrow_mut().output(b.id);
+    }
+
+    // 記録した回路を取り出す. 記録中の Traced が残っていても構わない
+    pub fn finish(self) -> Circuit {
+        self.circuit.borrow().clone()
+    }
+}
+
+// inputs 個の入力を f に渡し、返り値を出力とする回路を作る
+pub fn trace<F>(inputs: usize, f: F) -> Circuit
+where
+    F: FnOnce(Vec<Traced>) -> Vec<Traced>,
+{
+    let tracer = Tracer::new();
+    let xs = (0..inputs).map(|_| tracer.input()).collect();
+    for y in f(xs).iter() {
+        tracer.output(y);
+    }
+    tracer.finish()
+}
+
+#[cfg(test)]
+fn full_adder<B: Bit>(x: B, y: B, c: B) -> (B, B) {
+    let t = x.clone() ^ y;
+    (t.clone() ^ c.clone(), t.mux(&c, &x))
+}
+
+#[cfg(test)]
+fn ripple_carry_add<B: Bit>(xs: &[B], ys: &[B]) -> Vec<B> {
+    let mut carry = xs[0].constant(false);
+    let mut zs = Vec::new();
+    for (x, y) in xs.iter().zip(ys.iter()) {
+        let (s, c) = full_adder(x.clone(), y.clone(), carry);
+        zs.push(s);
+        carry = c;
+    }
+    zs.push(carry);
+    zs
+}
+
+#[test]
+fn test_trace_cleartext() {
+    const W: usize = 3;
+    let circuit = trace(2 * W, |xs| ripple_carry_add(&xs[..W], &xs[W..]));
+    assert_eq!(circuit.inputs().len(), 2 * W);
+    assert_eq!(circuit.outputs().len(), W + 1);
+
+    // 同じ関数を bool で実行したものと一致する
+    for i in 0..(1 << (2 * W)) {
+        let bs: Vec<bool> = (0..2 * W).map(|k| (i >> k) & 1 == 1).collect();
+        let expected = ripple_carry_add(&bs[..W], &bs[W..]);
+        assert_eq!(circuit.evaluate_cleartext(&bs), expected);
+
+        let z = expected
+            .iter()
+            .enumerate()
+            .fold(0, |z, (k, &b)| z | ((b as usize) << k));
+        assert_eq!(z, (i & ((1 << W) - 1)) + (i >> W));
+    }
+}
+
+#[test]
+#[should_panic]
+fn test_trace_mixed_tracers() {
+    let (t0, t1) = (Tracer::new(), Tracer::new());
+    let _ = t0.input() & t1.input();
+}
+
+#[test]
+fn test_trace_encrypted() {
+    use super::boolean::FheBool;
+    use super::key::{CloudKey, SecretKey};
+    use super::sampling::random_bool_initialization;
+    use super::tlwe::TLWE;
+    use std::sync::Arc;
+
+    let sk = SecretKey::new();
+    let tlwe = TLWE::new(sk);
+    let key = Arc::new(CloudKey::new(sk));
+
+    let nand = |xs: &[_]| -> bool { !(xs[0] && xs[1]) };
+    let bs: [bool; 2] = random_bool_initialization();
+
+    // 記録した回路を暗号文で評価する
+    let circuit = trace(2, |xs| vec![!(xs[0].clone() & xs[1].clone())]);
+    let cs: Vec<_> = bs.iter().map(|&b| tlwe.encrypt(b)).collect();
+    let out = circuit.evaluate_encrypted(&key, &cs);
+    assert_eq!(tlwe.decrypt(out[0]), nand(&bs));
+
+    // FheBool で直接実行する
+    let xs: Vec<FheBool> = bs
+        .iter()
+        .map(|&b| FheBool::encrypt(b, &tlwe, key.clone()))
+        .collect();
+    let y = !(xs[0].clone() & xs[1].clone());
+    assert_eq!(y.decrypt(&tlwe), nand(&bs));
+}
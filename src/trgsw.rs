@@ -2,7 +2,7 @@ use super::key::SecretKey;
 use super::ops::{pmul, rmadd, vadd};
 use super::params::trgsw;
 use super::trlwe::{CipherTRLWE, TRLWE};
-use super::util::{float_to_torus, zpoly_to_ring, RingLv1, Torus};
+use super::util::{float_to_torus, rotate_ring, zpoly_to_ring, RingLv1, Torus};
 
 const N: usize = trgsw::N;
 const L: usize = trgsw::L;
@@ -24,7 +24,7 @@ fn intpoly_mul_as_torus<const N: usize>(zs: [i8; N], t: Torus) -> [Torus; N] {
     let mut ring = [0; N];
     for i in 0..N {
         let sc = zs[i];
-        let base = t.wrapping_mul(sc.abs() as Torus);
+        let base = t.wrapping_mul(sc.unsigned_abs() as Torus);
         ring[i] = if sc < 0 {
             0u32.wrapping_sub(base)
         } else {
@@ -66,7 +66,7 @@ impl TRGSW {
         let mut matrix: TRGSWMatrix = [[[0; N]; 2]; 2 * L];
         let zero_ring = [0; N];
         for i in 0..L {
-            let w = 2u32.pow(32 - (i as u32 + 1) * (BGBIT as u32));
+            let w = 2u32.pow(32 - (i as u32 + 1) * BGBIT);
             matrix[i][0] = intpoly_mul_as_torus(mu, w);
             matrix[i][1] = zero_ring;
             matrix[i + L][0] = zero_ring;
@@ -140,6 +140,16 @@ pub fn external_product(matrix: TRGSWMatrix, c: CipherTRLWE) -> CipherTRLWE {
     CipherTRLWE(a_, b_)
 }
 
+// X^k * matrix
+pub fn rotate_trgsw_matrix(matrix: TRGSWMatrix, k: usize) -> TRGSWMatrix {
+    let mut rot = uninitialized_trgsw_matrix();
+    for i in 0..(2 * L) {
+        rot[i][0] = rotate_ring(matrix[i][0], k);
+        rot[i][1] = rotate_ring(matrix[i][1], k);
+    }
+    rot
+}
+
 pub fn cmux(matrix: TRGSWMatrix, c0: CipherTRLWE, c1: CipherTRLWE) -> CipherTRLWE {
     external_product(matrix, c0 - c1) + c1
 }
@@ -166,7 +176,7 @@ fn test_decomposition() {
         let mut sa: u32 = 0;
         let mut sb: u32 = 0;
         for i in 0..L {
-            let w = 2u32.pow(32 - (i as u32 + 1) * (BGBIT as u32));
+            let w = 2u32.pow(32 - (i as u32 + 1) * BGBIT);
             sa = sa.wrapping_add((a_bar[i][j] as u32).wrapping_mul(w));
             sb = sb.wrapping_add((b_bar[i][j] as u32).wrapping_mul(w));
         }
@@ -1,3 +1,4 @@
+use super::error::KfheError;
 use super::key::SecretKey;
 use super::ops::{pmul, vadd, vsub};
 use super::params::trlwe;
@@ -86,8 +87,16 @@ impl TRLWE {
 }
 
 pub fn sample_extract_index(c: CipherTRLWE, k: usize) -> CipherTLWELv1 {
-    if k > N - 1 {
-        panic!("ArrayIndexOutOfBoundsException")
+    try_sample_extract_index(c, k).unwrap_or_else(|e| panic!("{}", e))
+}
+
+pub fn try_sample_extract_index(c: CipherTRLWE, k: usize) -> Result<CipherTLWELv1, KfheError> {
+    if k >= N {
+        return Err(KfheError::IndexOutOfRange {
+            what: "TRLWE coefficient",
+            index: k,
+            len: N,
+        });
     }
 
     let (a, b) = c.describe();
@@ -99,7 +108,7 @@ pub fn sample_extract_index(c: CipherTRLWE, k: usize) -> CipherTLWELv1 {
             ext_a[i] = 0u32.wrapping_sub(a[N + k - i]);
         }
     }
-    CipherTLWELv1(ext_a, b[k])
+    Ok(CipherTLWELv1(ext_a, b[k]))
 }
 
 #[test]
@@ -118,6 +127,7 @@ fn test_trlwe_enc_and_dec() {
 }
 
 #[test]
+#[allow(clippy::needless_range_loop)]
 fn test_sample_extract_index() {
     use super::ops::dot;
     use super::sampling::random_bool_initialization;
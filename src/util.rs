@@ -1,3 +1,4 @@
+use super::error::KfheError;
 use super::params;
 
 pub type Torus = u32;
@@ -17,20 +18,36 @@ pub fn boolpoly_normalization<const N: usize>(bs: [bool; N]) -> [f64; N] {
 }
 
 pub fn float_to_torus(x: f64) -> Torus {
+    try_float_to_torus(x).unwrap_or_else(|e| panic!("{}", e))
+}
+
+pub fn try_float_to_torus(x: f64) -> Result<Torus, KfheError> {
+    if !x.is_finite() {
+        return Err(KfheError::NotFinite(x));
+    }
     let y = x - (x + 0.5).floor();
+    // 2^52 を超える奇数などでは x + 0.5 が切り上げられて y = -1 になる
+    let y = if y < -0.5 {
+        y + 1.
+    } else if y >= 0.5 {
+        y - 1.
+    } else {
+        y
+    };
     _float_to_torus(y)
 }
 
-fn _float_to_torus(x: f64) -> Torus {
+fn _float_to_torus(x: f64) -> Result<Torus, KfheError> {
     // [-0.5, 0.5) to Torus(u32)
     //      [-0.5, 0) to [2^31, 2^32)
     //      [0, 0.5)  to [0, 2^31)
 
-    assert!(x >= -0.5, "input: {}", x);
-    assert!(x < 0.5, "input: {}", x);
+    if !(-0.5..0.5).contains(&x) {
+        return Err(KfheError::OutOfTorusRange(x));
+    }
 
     let length_ring = 2f64.powi(32);
-    ((if x < 0. { x + 1. } else { x }) * length_ring) as u32
+    Ok(((if x < 0. { x + 1. } else { x }) * length_ring) as u32)
 }
 
 pub fn torus_to_float(t: Torus) -> f64 {
@@ -70,7 +87,22 @@ pub fn zpoly_to_ring<const N: usize>(zp: [i8; N]) -> [Torus; N] {
 }
 
 pub fn rotate_ring<const N: usize>(ring: [Torus; N], k: usize) -> [Torus; N] {
-    assert!(k < 2 * N);
+    try_rotate_ring(ring, k).unwrap_or_else(|e| panic!("{}", e))
+}
+
+// X^k を掛ける. X^N = -1 なので k は 2N 未満
+#[allow(clippy::needless_range_loop, clippy::manual_is_multiple_of)]
+pub fn try_rotate_ring<const N: usize>(
+    ring: [Torus; N],
+    k: usize,
+) -> Result<[Torus; N], KfheError> {
+    if k >= 2 * N {
+        return Err(KfheError::IndexOutOfRange {
+            what: "rotation",
+            index: k,
+            len: 2 * N,
+        });
+    }
     let mut ret = [0; N];
     for i in 0..N {
         let q = (2 * N - k + i) / N;
@@ -81,7 +113,7 @@ pub fn rotate_ring<const N: usize>(ring: [Torus; N], k: usize) -> [Torus; N] {
             0u32.wrapping_sub(ring[r])
         }
     }
-    ret
+    Ok(ret)
 }
 
 #[test]
@@ -91,6 +123,23 @@ fn test_float_to_torus() {
     assert_eq!(float_to_torus(-0.5), 2u32.pow(31));
 }
 
+#[test]
+fn test_try_float_to_torus() {
+    assert_eq!(try_float_to_torus(1.25), Ok(2u32.pow(30)));
+    // 以前は assert で止まっていた値
+    assert_eq!(try_float_to_torus(4503599627370497.), Ok(0));
+    assert!(matches!(
+        try_float_to_torus(f64::NAN),
+        Err(KfheError::NotFinite(_))
+    ));
+    assert_eq!(
+        try_float_to_torus(f64::INFINITY),
+        Err(KfheError::NotFinite(f64::INFINITY))
+    );
+    assert_eq!(_float_to_torus(0.5), Err(KfheError::OutOfTorusRange(0.5)));
+    assert!(try_rotate_ring([1, 2, 3], 6).is_err());
+}
+
 #[test]
 fn test_torus_to_float() {
     assert_eq!(torus_to_float(float_to_torus(0.)), 0.);
@@ -98,6 +147,7 @@ fn test_torus_to_float() {
 }
 
 #[test]
+#[allow(clippy::needless_range_loop)]
 fn test_rotate_ring() {
     const N: usize = 1000;
     let mut arr = [0; N];
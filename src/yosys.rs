@@ -0,0 +1,491 @@
+// Yosys の write_json 出力 (単純なゲートセルのみ) を読み込む
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+
+use serde_json::Value;
+
+use super::circuit::{Circuit, Netlist, NodeId, Port};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum YosysError {
+    Json(String),
+    // JSON としては正しいが、期待した構造になっていない
+    Format(String),
+    ModuleNotFound(String),
+    UnsupportedCell { name: String, cell_type: String },
+    UndrivenNet(String),
+    MultipleDrivers(u64),
+    CombinationalLoop,
+}
+
+impl fmt::Display for YosysError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            YosysError::Json(e) => write!(f, "invalid json: {}", e),
+            YosysError::Format(e) => write!(f, "invalid yosys netlist: {}", e),
+            YosysError::ModuleNotFound(m) => write!(f, "module not found: {}", m),
+            YosysError::UnsupportedCell { name, cell_type } => {
+                write!(f, "unsupported cell type {} (cell {})", cell_type, name)
+            }
+            YosysError::UndrivenNet(n) => write!(f, "undriven net: {}", n),
+            YosysError::MultipleDrivers(n) => write!(f, "net {} has multiple drivers", n),
+            YosysError::CombinationalLoop => write!(f, "combinational loop"),
+        }
+    }
+}
+
+impl std::error::Error for YosysError {}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Signal {
+    Net(u64),
+    Const(bool),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum CellKind {
+    Buf,
+    Not,
+    And,
+    Nand,
+    Or,
+    Nor,
+    Xor,
+    Xnor,
+    // A & !B
+    AndNot,
+    // A | !B
+    OrNot,
+    // S ? B : A
+    Mux,
+    // !(S ? B : A)
+    Nmux,
+}
+
+impl CellKind {
+    fn from_type(t: &str) -> Option<Self> {
+        let kind = match t {
+            "$_BUF_" => CellKind::Buf,
+            "$_NOT_" => CellKind::Not,
+            "$_AND_" => CellKind::And,
+            "$_NAND_" => CellKind::Nand,
+            "$_OR_" => CellKind::Or,
+            "$_NOR_" => CellKind::Nor,
+            "$_XOR_" => CellKind::Xor,
+            "$_XNOR_" => CellKind::Xnor,
+            "$_ANDNOT_" => CellKind::AndNot,
+            "$_ORNOT_" => CellKind::OrNot,
+            "$_MUX_" => CellKind::Mux,
+            "$_NMUX_" => CellKind::Nmux,
+            _ => return None,
+        };
+        Some(kind)
+    }
+
+    fn input_ports(&self) -> &'static [&'static str] {
+        match self {
+            CellKind::Buf | CellKind::Not => &["A"],
+            CellKind::Mux | CellKind::Nmux => &["A", "B", "S"],
+            _ => &["A", "B"],
+        }
+    }
+}
+
+struct Cell {
+    kind: CellKind,
+    inputs: Vec<Signal>,
+    output: u64,
+}
+
+fn format_error<T>(msg: &str) -> Result<T, YosysError> {
+    Err(YosysError::Format(msg.to_string()))
+}
+
+fn parse_signal(v: &Value) -> Result<Signal, YosysError> {
+    match v {
+        Value::Number(n) => match n.as_u64() {
+            Some(n) => Ok(Signal::Net(n)),
+            None => format_error("net id must be a non-negative integer"),
+        },
+        Value::String(s) if s == "0" => Ok(Signal::Const(false)),
+        Value::String(s) if s == "1" => Ok(Signal::Const(true)),
+        Value::String(s) => Err(YosysError::UndrivenNet(s.clone())),
+        _ => format_error("bit must be a net id or a constant"),
+    }
+}
+
+fn parse_bits(v: &Value) -> Result<Vec<Signal>, YosysError> {
+    match v.as_array() {
+        Some(bits) => bits.iter().map(parse_signal).collect(),
+        None => format_error("bits must be an array"),
+    }
+}
+
+fn select_module<'a>(
+    modules: &'a serde_json::Map<String, Value>,
+    name: Option<&str>,
+) -> Result<&'a Value, YosysError> {
+    if let Some(name) = name {
+        return modules
+            .get(name)
+            .ok_or_else(|| YosysError::ModuleNotFound(name.to_string()));
+    }
+
+    if modules.len() == 1 {
+        return Ok(modules.values().next().unwrap());
+    }
+
+    // 複数ある場合は top 属性が付いているもの
+    let is_top = |m: &Value| match m.pointer("/attributes/top") {
+        Some(Value::String(s)) => s.trim_start_matches('0') == "1",
+        Some(Value::Number(n)) => n.as_u64() == Some(1),
+        _ => false,
+    };
+    modules
+        .values()
+        .find(|m| is_top(m))
+        .ok_or_else(|| YosysError::ModuleNotFound("top".to_string()))
+}
+
+// module が None の場合、モジュールが 1 つしかなければそれを、複数あれば top 属性の付いたものを読む
+pub fn parse_yosys_json(json: &str, module: Option<&str>) -> Result<Netlist, YosysError> {
+    let root: Value = serde_json::from_str(json).map_err(|e| YosysError::Json(e.to_string()))?;
+    let modules = match root.get("modules").and_then(Value::as_object) {
+        Some(m) => m,
+        None => return format_error("missing modules"),
+    };
+    let module = select_module(modules, module)?;
+
+    let mut circuit = Circuit::new();
+    let mut nets: HashMap<u64, NodeId> = HashMap::new();
+    let mut inputs = Vec::new();
+    let mut outputs = Vec::new();
+    let mut output_bits = Vec::new();
+
+    let empty = serde_json::Map::new();
+    let ports = module
+        .get("ports")
+        .and_then(Value::as_object)
+        .unwrap_or(&empty);
+    for (name, port) in ports {
+        let bits = parse_bits(port.get("bits").unwrap_or(&Value::Null))?;
+        let port_ = Port {
+            name: name.clone(),
+            width: bits.len(),
+        };
+        match port.get("direction").and_then(Value::as_str) {
+            Some("input") => {
+                for b in bits {
+                    let id = circuit.input();
+                    match b {
+                        Signal::Net(n) => {
+                            if nets.insert(n, id).is_some() {
+                                return Err(YosysError::MultipleDrivers(n));
+                            }
+                        }
+                        Signal::Const(_) => return format_error("constant bit in input port"),
+                    }
+                }
+                inputs.push(port_);
+            }
+            Some("output") => {
+                output_bits.extend(bits);
+                outputs.push(port_);
+            }
+            Some(d) => return format_error(&format!("unsupported port direction: {}", d)),
+            None => return format_error("missing port direction"),
+        }
+    }
+
+    let mut cells = Vec::new();
+    let cell_map = module
+        .get("cells")
+        .and_then(Value::as_object)
+        .unwrap_or(&empty);
+    for (name, cell) in cell_map {
+        let cell_type = cell.get("type").and_then(Value::as_str).unwrap_or("");
+        let kind = match CellKind::from_type(cell_type) {
+            Some(kind) => kind,
+            None => {
+                return Err(YosysError::UnsupportedCell {
+                    name: name.clone(),
+                    cell_type: cell_type.to_string(),
+                })
+            }
+        };
+
+        let connection = |port: &str| -> Result<Signal, YosysError> {
+            let bits = parse_bits(
+                cell.pointer(&format!("/connections/{}", port))
+                    .unwrap_or(&Value::Null),
+            )?;
+            match bits[..] {
+                [b] => Ok(b),
+                _ => format_error(&format!("port {} of cell {} must be 1 bit", port, name)),
+            }
+        };
+
+        let inputs = kind
+            .input_ports()
+            .iter()
+            .map(|p| connection(p))
+            .collect::<Result<Vec<_>, _>>()?;
+        let output = match connection("Y")? {
+            Signal::Net(n) => n,
+            Signal::Const(_) => return format_error("cell output connected to a constant"),
+        };
+        cells.push(Cell {
+            kind,
+            inputs,
+            output,
+        });
+    }
+
+    // セルの出力ネット -> セル
+    let mut drivers: HashMap<u64, usize> = HashMap::new();
+    for (i, cell) in cells.iter().enumerate() {
+        if nets.contains_key(&cell.output) || drivers.insert(cell.output, i).is_some() {
+            return Err(YosysError::MultipleDrivers(cell.output));
+        }
+    }
+
+    // トポロジカルソート
+    let mut indegree = vec![0; cells.len()];
+    let mut users: Vec<Vec<usize>> = vec![Vec::new(); cells.len()];
+    for (i, cell) in cells.iter().enumerate() {
+        for s in cell.inputs.iter() {
+            if let Signal::Net(n) = s {
+                if let Some(&d) = drivers.get(n) {
+                    indegree[i] += 1;
+                    users[d].push(i);
+                } else if !nets.contains_key(n) {
+                    return Err(YosysError::UndrivenNet(n.to_string()));
+                }
+            }
+        }
+    }
+    let mut queue: VecDeque<usize> = (0..cells.len()).filter(|&i| indegree[i] == 0).collect();
+    let mut order = Vec::with_capacity(cells.len());
+    while let Some(i) = queue.pop_front() {
+        order.push(i);
+        for &u in users[i].iter() {
+            indegree[u] -= 1;
+            if indegree[u] == 0 {
+                queue.push_back(u);
+            }
+        }
+    }
+    if order.len() < cells.len() {
+        return Err(YosysError::CombinationalLoop);
+    }
+
+    let mut consts: [Option<NodeId>; 2] = [None, None];
+    let mut resolve = |circuit: &mut Circuit, nets: &HashMap<u64, NodeId>, s: Signal| match s {
+        Signal::Net(n) => nets
+            .get(&n)
+            .copied()
+            .ok_or_else(|| YosysError::UndrivenNet(n.to_string())),
+        Signal::Const(b) => Ok(*consts[b as usize].get_or_insert_with(|| circuit.constant(b))),
+    };
+
+    for i in order {
+        let cell = &cells[i];
+        let ins = cell
+            .inputs
+            .iter()
+            .map(|&s| resolve(&mut circuit, &nets, s))
+            .collect::<Result<Vec<_>, _>>()?;
+        let c = &mut circuit;
+        let id = match cell.kind {
+            CellKind::Buf => ins[0],
+            CellKind::Not => c.not(ins[0]),
+            CellKind::And => c.and(ins[0], ins[1]),
+            CellKind::Nand => c.nand(ins[0], ins[1]),
+            CellKind::Or => c.or(ins[0], ins[1]),
+            CellKind::Nor => {
+                let t = c.or(ins[0], ins[1]);
+                c.not(t)
+            }
+            CellKind::Xor => c.xor(ins[0], ins[1]),
+            CellKind::Xnor => {
+                let t = c.xor(ins[0], ins[1]);
+                c.not(t)
+            }
+            CellKind::AndNot => {
+                let nb = c.not(ins[1]);
+                c.and(ins[0], nb)
+            }
+            CellKind::OrNot => {
+                let nb = c.not(ins[1]);
+                c.or(ins[0], nb)
+            }
+            CellKind::Mux => c.mux(ins[2], ins[1], ins[0]),
+            CellKind::Nmux => {
+                let t = c.mux(ins[2], ins[1], ins[0]);
+                c.not(t)
+            }
+        };
+        nets.insert(cell.output, id);
+    }
+
+    for s in output_bits {
+        let id = resolve(&mut circuit, &nets, s)?;
+        circuit.output(id);
+    }
+
+    Ok(Netlist {
+        circuit,
+        inputs,
+        outputs,
+    })
+}
+
+#[cfg(test)]
+const HALF_ADDER: &str = r#"{
+  "creator": "Yosys 0.9",
+  "modules": {
+    "half_adder": {
+      "attributes": { "top": "00000000000000000000000000000001" },
+      "ports": {
+        "a": { "direction": "input", "bits": [ 2 ] },
+        "b": { "direction": "input", "bits": [ 3 ] },
+        "c": { "direction": "output", "bits": [ 5 ] },
+        "s": { "direction": "output", "bits": [ 4 ] }
+      },
+      "cells": {
+        "$abc$1": {
+          "type": "$_XOR_",
+          "port_directions": { "A": "input", "B": "input", "Y": "output" },
+          "connections": { "A": [ 2 ], "B": [ 3 ], "Y": [ 4 ] }
+        },
+        "$abc$2": {
+          "type": "$_AND_",
+          "port_directions": { "A": "input", "B": "input", "Y": "output" },
+          "connections": { "A": [ 2 ], "B": [ 3 ], "Y": [ 5 ] }
+        }
+      }
+    }
+  }
+}"#;
+
+#[test]
+fn test_parse_half_adder() {
+    let netlist = parse_yosys_json(HALF_ADDER, None).unwrap();
+    let names: Vec<&str> = netlist.inputs.iter().map(|p| p.name.as_str()).collect();
+    assert_eq!(names, ["a", "b"]);
+    let names: Vec<&str> = netlist.outputs.iter().map(|p| p.name.as_str()).collect();
+    assert_eq!(names, ["c", "s"]);
+
+    for (a, b) in [(false, false), (false, true), (true, false), (true, true)] {
+        let out = netlist.circuit.evaluate_cleartext(&[a, b]);
+        assert_eq!(out, vec![a && b, a ^ b]);
+    }
+}
+
+#[test]
+fn test_parse_mux_and_constants() {
+    // y[0] = s ? b : a, y[1] = !(a | b), y[2] = a & !b, y[3] = 1 (cells are out of order)
+    let json = r#"{
+      "modules": {
+        "m": {
+          "ports": {
+            "a": { "direction": "input", "bits": [ 2 ] },
+            "b": { "direction": "input", "bits": [ 3 ] },
+            "s": { "direction": "input", "bits": [ 4 ] },
+            "y": { "direction": "output", "bits": [ 5, 7, 8, "1" ] }
+          },
+          "cells": {
+            "nor_not": { "type": "$_NOT_", "connections": { "A": [ 6 ], "Y": [ 7 ] } },
+            "mux": { "type": "$_MUX_", "connections": { "A": [ 2 ], "B": [ 3 ], "S": [ 4 ], "Y": [ 5 ] } },
+            "nor_or": { "type": "$_OR_", "connections": { "A": [ 2 ], "B": [ 3 ], "Y": [ 6 ] } },
+            "andnot": { "type": "$_ANDNOT_", "connections": { "A": [ 2 ], "B": [ 3 ], "Y": [ 8 ] } }
+          }
+        }
+      }
+    }"#;
+    let netlist = parse_yosys_json(json, None).unwrap();
+    assert_eq!(netlist.outputs[0].width, 4);
+
+    for i in 0..8 {
+        let (a, b, s) = (i & 1 == 1, i & 2 == 2, i & 4 == 4);
+        let out = netlist.circuit.evaluate_cleartext(&[a, b, s]);
+        assert_eq!(out, vec![if s { b } else { a }, !(a || b), a && !b, true]);
+    }
+}
+
+#[test]
+fn test_parse_port_order() {
+    // ポートは名前順ではなく宣言された順に並ぶ. y = b & !a, x = !b
+    let json = r#"{
+      "modules": {
+        "m": {
+          "ports": {
+            "b": { "direction": "input", "bits": [ 2 ] },
+            "a": { "direction": "input", "bits": [ 3 ] },
+            "y": { "direction": "output", "bits": [ 4 ] },
+            "x": { "direction": "output", "bits": [ 5 ] }
+          },
+          "cells": {
+            "andnot": { "type": "$_ANDNOT_", "connections": { "A": [ 2 ], "B": [ 3 ], "Y": [ 4 ] } },
+            "not": { "type": "$_NOT_", "connections": { "A": [ 2 ], "Y": [ 5 ] } }
+          }
+        }
+      }
+    }"#;
+    let netlist = parse_yosys_json(json, None).unwrap();
+    let names: Vec<&str> = netlist.inputs.iter().map(|p| p.name.as_str()).collect();
+    assert_eq!(names, ["b", "a"]);
+    let names: Vec<&str> = netlist.outputs.iter().map(|p| p.name.as_str()).collect();
+    assert_eq!(names, ["y", "x"]);
+
+    for (b, a) in [(false, false), (false, true), (true, false), (true, true)] {
+        let out = netlist.circuit.evaluate_cleartext(&[b, a]);
+        assert_eq!(out, vec![b && !a, !b]);
+    }
+}
+
+#[test]
+fn test_parse_errors() {
+    let dff = r#"{ "modules": { "m": {
+      "ports": { "d": { "direction": "input", "bits": [ 2 ] }, "q": { "direction": "output", "bits": [ 3 ] } },
+      "cells": { "ff": { "type": "$_DFF_P_", "connections": { "C": [ 2 ], "D": [ 2 ], "Q": [ 3 ] } } }
+    } } }"#;
+    assert_eq!(
+        parse_yosys_json(dff, None),
+        Err(YosysError::UnsupportedCell {
+            name: "ff".to_string(),
+            cell_type: "$_DFF_P_".to_string()
+        })
+    );
+
+    let undriven = r#"{ "modules": { "m": {
+      "ports": { "q": { "direction": "output", "bits": [ 3 ] } },
+      "cells": { "n": { "type": "$_NOT_", "connections": { "A": [ 2 ], "Y": [ 3 ] } } }
+    } } }"#;
+    assert_eq!(
+        parse_yosys_json(undriven, None),
+        Err(YosysError::UndrivenNet("2".to_string()))
+    );
+
+    let looped = r#"{ "modules": { "m": {
+      "ports": { "q": { "direction": "output", "bits": [ 3 ] } },
+      "cells": {
+        "n1": { "type": "$_NOT_", "connections": { "A": [ 2 ], "Y": [ 3 ] } },
+        "n2": { "type": "$_NOT_", "connections": { "A": [ 3 ], "Y": [ 2 ] } }
+      }
+    } } }"#;
+    assert_eq!(
+        parse_yosys_json(looped, None),
+        Err(YosysError::CombinationalLoop)
+    );
+
+    assert!(matches!(
+        parse_yosys_json(HALF_ADDER, Some("adder")),
+        Err(YosysError::ModuleNotFound(_))
+    ));
+    assert!(matches!(
+        parse_yosys_json("{", None),
+        Err(YosysError::Json(_))
+    ));
+}